@@ -12,13 +12,95 @@
   +----------------------------------------------+
 ")]
 use std::{
-    io::{Read, Write, stdin, stdout},
-    path::PathBuf,
+    io::{IsTerminal, Read, Write, stderr, stdin, stdout},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, SystemTime},
 };
 
 use badascii_doc::badascii;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Svg,
+    Png,
+    Pdf,
+    /// The diagram as plain text.
+    Ascii,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum ArrowStyle {
+    Closed,
+    Open,
+    None,
+}
+
+impl From<ArrowStyle> for badascii::ArrowStyle {
+    fn from(style: ArrowStyle) -> Self {
+        match style {
+            ArrowStyle::Closed => badascii::ArrowStyle::Closed,
+            ArrowStyle::Open => badascii::ArrowStyle::Open,
+            ArrowStyle::None => badascii::ArrowStyle::None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum WireStyle {
+    Straight,
+    Rounded,
+}
+
+impl From<WireStyle> for badascii::WireStyle {
+    fn from(style: WireStyle) -> Self {
+        match style {
+            WireStyle::Straight => badascii::WireStyle::Straight,
+            WireStyle::Rounded => badascii::WireStyle::Rounded,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum LineCap {
+    Round,
+    Butt,
+    Square,
+}
+
+impl From<LineCap> for badascii::LineCap {
+    fn from(cap: LineCap) -> Self {
+        match cap {
+            LineCap::Round => badascii::LineCap::Round,
+            LineCap::Butt => badascii::LineCap::Butt,
+            LineCap::Square => badascii::LineCap::Square,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum LineJoin {
+    Round,
+    Miter,
+    Bevel,
+}
+
+impl From<LineJoin> for badascii::LineJoin {
+    fn from(join: LineJoin) -> Self {
+        match join {
+            LineJoin::Round => badascii::LineJoin::Round,
+            LineJoin::Miter => badascii::LineJoin::Miter {
+                limit: badascii::LineJoin::DEFAULT_MITER_LIMIT,
+            },
+            LineJoin::Bevel => badascii::LineJoin::Bevel,
+        }
+    }
+}
 
 #[derive(Debug, Parser)]
 /// BADASCII CLI
@@ -26,16 +108,43 @@ use clap::Parser;
 /// Convert BadAscii diagrams to SVGs at the command line.
 ///
 struct Args {
-    /// The file containing the badascii diagram
+    /// The file(s) containing the badascii diagram(s).
     /// If unspecified, then `badascii-cli` will
     /// presume that the input comes via `stdin`.
-    #[arg(short, long)]
-    input: Option<PathBuf>,
+    #[arg(short, long, num_args = 1..)]
+    input: Vec<PathBuf>,
     /// The output file to write the SVG to.  If
     /// unspecified, then the output is written to
-    /// `stdout`.
+    /// `stdout`.  Cannot be used with multiple inputs,
+    /// unless they are stacked with `--concat`.
     #[arg(short, long)]
     output: Option<PathBuf>,
+    /// The directory to write the outputs to.  Each
+    /// input is written to a file named by `--name-template`.
+    #[arg(long, conflicts_with = "output")]
+    output_dir: Option<PathBuf>,
+    /// The file name for each output in `--output-dir`.
+    /// `{stem}` is the input file name without its extension,
+    /// `{index}` is the position of the input (starting at 1)
+    /// and `{ext}` is the extension of the output format.
+    /// Must contain `{stem}` or `{index}`.
+    #[arg(
+        long,
+        default_value = "{stem}.{ext}",
+        requires = "output_dir",
+        value_parser = parse_name_template
+    )]
+    name_template: String,
+    /// Stack the inputs top to bottom, in the order given,
+    /// into a single diagram, rather than rendering each of
+    /// them on its own.
+    #[arg(long, conflicts_with_all = ["output_dir", "watch"])]
+    concat: bool,
+    /// The number of blank rows between inputs stacked by
+    /// `--concat`, not counting any blank lines at the end
+    /// of each input, which are dropped.
+    #[arg(long, default_value_t = 1, requires = "concat")]
+    concat_gap: u32,
     /// Use the more formal mode, suitable for
     /// gatherings with canapes.
     #[arg(short, long)]
@@ -57,25 +166,248 @@ struct Args {
     /// it here.
     #[arg(short, long)]
     color: Option<String>,
+    /// Override the color used for the text labels in
+    /// the SVG.  If unspecified, the labels use the same
+    /// color as the strokes.
+    #[arg(long)]
+    text_color: Option<String>,
+    /// Embed the diagram source in the SVG, so that
+    /// it can be recovered from the SVG later for editing.
+    #[arg(long)]
+    embed_source: bool,
+    /// Override the font family used for the text labels
+    /// in the SVG.  Defaults to `monospace`.
+    #[arg(long)]
+    font_family: Option<String>,
+    /// A title for the SVG, read out by screen readers.
+    #[arg(long)]
+    title: Option<String>,
+    /// A longer description of the diagram for the SVG.
+    #[arg(long)]
+    description: Option<String>,
+    /// Fill the boxes whose interior is entirely made of `░`
+    /// with this color, instead of drawing the `░` as text.
+    #[arg(long)]
+    fill_color: Option<String>,
     /// Override the color used for the background of the
     /// SVG.  By default, the SVGs render in dark mode.
     #[arg(short, long)]
     background: Option<String>,
+    /// The output format.  If unspecified, the format is
+    /// inferred from the extension of the output file, and
+    /// otherwise defaults to SVG.
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+    /// How to draw the arrow heads at the ends of wires.
+    #[arg(long, value_enum, default_value_t = ArrowStyle::Closed)]
+    arrow_style: ArrowStyle,
+    /// How to draw the corners where a horizontal and a
+    /// vertical wire meet.
+    #[arg(long, value_enum, default_value_t = WireStyle::Straight)]
+    wire_style: WireStyle,
+    /// How the ends of the strokes are drawn.
+    #[arg(long, value_enum, default_value_t = LineCap::Round)]
+    line_cap: LineCap,
+    /// How the strokes are joined where they turn a corner.
+    #[arg(long, value_enum, default_value_t = LineJoin::Round)]
+    line_join: LineJoin,
+    /// Multiply the size of the PNG output by this factor,
+    /// for a sharp image on a high density display (PNG only).
+    #[arg(long, default_value_t = 1.0)]
+    scale: f32,
+    /// Blank space (in pixels) to add around all sides of
+    /// the diagram.
+    #[arg(long, default_value_t = 0.0)]
+    padding: f32,
+    /// Draw a border around the whole diagram (including
+    /// the padding).
+    #[arg(long)]
+    frame: bool,
+    /// Draw each phrase of a label as a run of text with natural
+    /// spacing, rather than one glyph per cell.  The font family
+    /// defaults to `sans-serif` in this mode.
+    #[arg(long)]
+    proportional_labels: bool,
+    /// Emit each word of the labels as one text element (SVG only),
+    /// rather than one per character, for smaller files.
+    #[arg(long)]
+    merged_labels: bool,
+    /// Fade the shapes of the SVG in one at a time, followed by
+    /// the labels (SVG only), for presentations.
+    #[arg(long)]
+    animate: bool,
+    /// Give each wire and box of the SVG an `id` derived from
+    /// where it is in the diagram, for scripts and stylesheets
+    /// (SVG only).
+    #[arg(long, conflicts_with = "animate")]
+    element_ids: bool,
+    /// Space (in cells) to keep between the labels in a box and
+    /// its border.
+    #[arg(long, default_value_t = 0.0)]
+    label_padding: f32,
+    /// How much the rough strokes bow away from a straight
+    /// line.  Defaults to 2.
+    #[arg(long)]
+    bowing: Option<f32>,
+    /// Strip the insignificant whitespace out of the SVG output.
+    #[arg(long)]
+    minify: bool,
+    /// Keep the blank lines and whitespace around the diagram in
+    /// the ASCII output (with every line padded to the same width),
+    /// rather than trimming them.
+    #[arg(long)]
+    keep_blank_lines: bool,
+    /// Report likely mistakes in the diagram on `stderr`, like
+    /// boxes that do not quite close.  The output is still
+    /// rendered as usual.  On a terminal, each report shows the
+    /// rows around the mistake with a caret under it, in color
+    /// unless `NO_COLOR` is set.
+    #[arg(long)]
+    lint: bool,
+    /// Stay resident and re-render the output whenever the
+    /// input file changes.  Requires a single `--input` and
+    /// an `--output`.  Stop with Ctrl-C.
+    #[arg(short, long, requires_all = ["input", "output"])]
+    watch: bool,
+    /// The largest output (in pixels) that will be rendered
+    /// without `--allow-large`.  PNG outputs above this size
+    /// are refused, and SVG or PDF outputs generate a warning.
+    #[arg(long, default_value_t = DEFAULT_MAX_PIXELS)]
+    max_pixels: f64,
+    /// Render the output even if it exceeds `--max-pixels`.
+    #[arg(long)]
+    allow_large: bool,
 }
 
-fn main() {
-    let args = Args::parse();
-    let input = if let Some(input) = args.input.as_ref() {
-        std::fs::read_to_string(input)
-            .unwrap_or_else(|_| panic!("Unable to open input {:?} for reading", input))
+/// Roughly an 8K x 8K image, which is far larger than any
+/// sensible diagram.
+const DEFAULT_MAX_PIXELS: f64 = 64_000_000.0;
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Svg => "svg",
+            Format::Png => "png",
+            Format::Pdf => "pdf",
+            Format::Ascii => "txt",
+        }
+    }
+}
+
+const NAME_PLACEHOLDERS: [&str; 3] = ["{stem}", "{index}", "{ext}"];
+
+// Only the known placeholders are allowed, and every output needs
+// a distinct name, so the template must vary with the input.
+fn parse_name_template(template: &str) -> Result<String, String> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let tail = &rest[start..];
+        let Some(placeholder) = NAME_PLACEHOLDERS.iter().find(|p| tail.starts_with(*p)) else {
+            return Err(format!(
+                "unknown placeholder in {template:?} (expected one of {})",
+                NAME_PLACEHOLDERS.join(", ")
+            ));
+        };
+        rest = &tail[placeholder.len()..];
+    }
+    if !template.contains("{stem}") && !template.contains("{index}") {
+        return Err(format!(
+            "{template:?} must contain {{stem}} or {{index}} so each output gets its own name"
+        ));
+    }
+    if template.contains(['/', '\\']) {
+        return Err(format!("{template:?} must be a file name, not a path"));
+    }
+    Ok(template.to_string())
+}
+
+// The stem goes in last, so braces in an input's file name are
+// left alone.
+fn expand_name_template(template: &str, stem: &str, index: usize, ext: &str) -> String {
+    template
+        .replace("{index}", &index.to_string())
+        .replace("{ext}", ext)
+        .replace("{stem}", stem)
+}
+
+// The job trims the text down to the diagram, so the ASCII output
+// starts over from the input.
+fn ascii(args: &Args, input: &str) -> Vec<u8> {
+    let buffer = badascii::TextBuffer::with_text(input);
+    if args.keep_blank_lines {
+        buffer.render_exact().into_bytes()
     } else {
-        let mut ret = String::new();
-        stdin()
-            .read_to_string(&mut ret)
-            .expect("Reading from stdin failed");
-        ret
+        buffer
+            .to_lines()
+            .into_iter()
+            .map(|line| line + "\n")
+            .collect::<String>()
+            .into_bytes()
+    }
+}
+
+// A diagnostic as rustc would show it: the message, then the rows
+// around the cell it points at, with a caret under the cell.
+//
+//   line 3, column 6: box is missing a `+` corner
+//     |
+//   2 | | hi |
+//   3 | +----
+//     |      ^
+fn snippet(lines: &[String], diagnostic: &badascii::analyze::Diagnostic, color: bool) -> String {
+    let paint = |code: &str, text: &str| {
+        if color {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
     };
-    let buffer = badascii::TextBuffer::with_text(&input);
+    let row = diagnostic.pos.y as usize;
+    let col = diagnostic.pos.x as usize;
+    let first = row.saturating_sub(1);
+    let last = (row + 1).min(lines.len().saturating_sub(1)).max(row);
+    let width = (last + 1).to_string().len();
+    let gutter = |label: &str| paint("1;34", &format!("{label:>width$} |"));
+    let mut out = format!(
+        "{}: {}\n{}\n",
+        paint("1", &format!("line {}, column {}", row + 1, col + 1)),
+        paint("1;33", &diagnostic.message),
+        gutter("")
+    );
+    for (ndx, line) in lines.iter().enumerate().take(last + 1).skip(first) {
+        if ndx != row && line.is_empty() {
+            continue;
+        }
+        out += &format!("{} {line}\n", gutter(&(ndx + 1).to_string()));
+        if ndx == row {
+            out += &format!("{} {}{}\n", gutter(""), " ".repeat(col), paint("1;33", "^"));
+        }
+    }
+    out
+}
+
+// Render the input in the given format.  Errors are returned rather
+// than reported, so that `watch` can carry on after a bad edit.
+fn render(args: &Args, input: &str, format: Format) -> Result<Vec<u8>, String> {
+    let buffer = badascii::TextBuffer::with_text(input);
+    if args.lint {
+        // Snippets are for people, so a pipe gets the plain form
+        let snippets = stderr().is_terminal();
+        let color = snippets && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty());
+        let lines = buffer.lines_iter().collect::<Vec<_>>();
+        for diagnostic in badascii::analyze::diagnose(&buffer) {
+            if snippets {
+                eprint!("{}", snippet(&lines, &diagnostic, color));
+            } else {
+                eprintln!(
+                    "line {}, column {}: {}",
+                    diagnostic.pos.y + 1,
+                    diagnostic.pos.x + 1,
+                    diagnostic.message
+                );
+            }
+        }
+    }
     let mut job = if args.formal_mode {
         badascii::RenderJob::formal(buffer)
     } else {
@@ -87,15 +419,222 @@ fn main() {
     if let Some(height) = args.height {
         job.height = height;
     }
-    let color = args.color.unwrap_or_else(|| "#808080".to_string());
-    let background = args.background.unwrap_or_else(|| "#0A0A0A".to_string());
-    let svg = badascii::svg::render(&job, &color, &background);
+    job.text_color = args.text_color.clone();
+    job.embed_source = args.embed_source;
+    job.arrow_style = args.arrow_style.into();
+    job.wire_style = args.wire_style.into();
+    job.options.line_cap = Some(args.line_cap.into());
+    job.options.line_join = Some(args.line_join.into());
+    job.fill_color = args.fill_color.clone();
+    job.title = args.title.clone();
+    job.description = args.description.clone();
+    job.padding = args.padding;
+    job.frame = args.frame;
+    job.proportional_labels = args.proportional_labels;
+    job.merged_labels = args.merged_labels;
+    job.animate = args.animate;
+    job.element_ids = args.element_ids;
+    job.label_padding = args.label_padding;
+    if let Some(bowing) = args.bowing {
+        job.options.bowing = Some(bowing);
+    }
+    if args.proportional_labels {
+        job.font_family = "sans-serif".into();
+    }
+    if let Some(font_family) = &args.font_family {
+        job.font_family = font_family.clone();
+    }
+    // The PNG has `scale` device pixels for each pixel of the job.
+    let (width, height) = match format {
+        Format::Png => (job.width * args.scale, job.height * args.scale),
+        _ => (job.width, job.height),
+    };
+    let pixels = width as f64 * height as f64;
+    if format != Format::Ascii && !args.allow_large && pixels > args.max_pixels {
+        if format == Format::Png {
+            return Err(format!(
+                "Refusing to render a {width}x{height} PNG ({pixels} pixels exceeds the limit of {}).  Use --allow-large to override.",
+                args.max_pixels
+            ));
+        }
+        eprintln!(
+            "Warning: rendering a very large {width}x{height} {} ({pixels} pixels exceeds the limit of {}).",
+            format.extension().to_uppercase(),
+            args.max_pixels
+        );
+    }
+    let color = args.color.as_deref().unwrap_or("#808080");
+    let background = args.background.as_deref().unwrap_or("#0A0A0A");
+    match format {
+        Format::Svg => {
+            let svg = badascii::svg::render(&job, color, background);
+            if args.minify {
+                Ok(badascii::svg::minify(&svg).into_bytes())
+            } else {
+                Ok(svg.into_bytes())
+            }
+        }
+        Format::Png => {
+            let mut png = vec![];
+            badascii::bitmap::render(&job, color, background, args.scale)
+                .and_then(|img| Ok(img.write_png(&mut png)?))
+                .map_err(|err| format!("Unable to render PNG: {err}"))?;
+            Ok(png)
+        }
+        Format::Pdf => badascii::pdf::render(&job, color, background)
+            .map_err(|err| format!("Unable to render PDF: {err}")),
+        Format::Ascii => Ok(ascii(args, input)),
+    }
+}
+
+// Render for a one-shot run, where there is nothing to do but give
+// up if the input cannot be rendered.
+fn render_or_exit(args: &Args, input: &str, format: Format) -> Vec<u8> {
+    render(args, input, format).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    })
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn watch(args: &Args, input: &Path, output: &Path, format: Format) {
+    let running = Arc::new(AtomicBool::new(true));
+    let flag = running.clone();
+    ctrlc::set_handler(move || flag.store(false, Ordering::SeqCst))
+        .expect("Unable to install Ctrl-C handler");
+    let mut last_modified = None;
+    while running.load(Ordering::SeqCst) {
+        let mtime = modified(input);
+        if mtime.is_some() && mtime != last_modified {
+            last_modified = mtime;
+            match std::fs::read_to_string(input).map(|text| render(args, &text, format)) {
+                Ok(Err(err)) => eprintln!("{err}"),
+                Ok(Ok(data)) => {
+                    std::fs::write(output, data).unwrap_or_else(|_| {
+                        panic!("Unable to write to output file {}", output.display())
+                    });
+                    eprintln!(
+                        "[{}] Rendered {} to {}",
+                        humantime::format_rfc3339_seconds(SystemTime::now()),
+                        input.display(),
+                        output.display()
+                    );
+                }
+                Err(err) => eprintln!("Unable to read input {}: {err}", input.display()),
+            }
+        }
+        std::thread::sleep(Duration::from_millis(250));
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    if args.input.len() > 1 && args.output.is_some() && !args.concat {
+        eprintln!("--output cannot be used with multiple inputs.  Use --output-dir instead.");
+        std::process::exit(1);
+    }
+    let format = args.format.unwrap_or_else(|| {
+        match args
+            .output
+            .as_ref()
+            .and_then(|output| output.extension())
+            .and_then(|ext| ext.to_str())
+        {
+            Some(ext) if ext.eq_ignore_ascii_case("png") => Format::Png,
+            Some(ext) if ext.eq_ignore_ascii_case("pdf") => Format::Pdf,
+            Some(ext) if ext.eq_ignore_ascii_case("txt") => Format::Ascii,
+            _ => Format::Svg,
+        }
+    });
+    if let Some(output_dir) = args.output_dir.as_ref() {
+        if args.input.is_empty() {
+            eprintln!("--output-dir needs at least one --input.");
+            std::process::exit(1);
+        }
+        let outputs = args
+            .input
+            .iter()
+            .enumerate()
+            .map(|(index, input)| {
+                let stem = input.file_stem().unwrap_or(input.as_os_str());
+                output_dir.join(expand_name_template(
+                    &args.name_template,
+                    &stem.to_string_lossy(),
+                    index + 1,
+                    format.extension(),
+                ))
+            })
+            .collect::<Vec<_>>();
+        // Check the names up front, so that one input does not
+        // silently overwrite the output of another.
+        for (index, output) in outputs.iter().enumerate() {
+            if let Some(other) = outputs[..index].iter().position(|o| o == output) {
+                eprintln!(
+                    "Inputs {} and {} would both be written to {}.  Use --name-template with {{index}} to tell them apart.",
+                    args.input[other].display(),
+                    args.input[index].display(),
+                    output.display()
+                );
+                std::process::exit(1);
+            }
+        }
+        std::fs::create_dir_all(output_dir).unwrap_or_else(|_| {
+            panic!("Unable to create output directory {}", output_dir.display())
+        });
+        for (input, output) in args.input.iter().zip(&outputs) {
+            let text = std::fs::read_to_string(input)
+                .unwrap_or_else(|_| panic!("Unable to open input {:?} for reading", input));
+            std::fs::write(output, render_or_exit(&args, &text, format))
+                .unwrap_or_else(|_| panic!("Unable to write to output file {}", output.display()));
+        }
+        return;
+    }
+    if args.input.len() > 1 && !args.concat {
+        eprintln!("Multiple inputs require --output-dir or --concat.");
+        std::process::exit(1);
+    }
+    if args.watch {
+        if let (Some(input), Some(output)) = (args.input.first(), args.output.as_ref()) {
+            watch(&args, input, output, format);
+        }
+        return;
+    }
+    if matches!(format, Format::Png | Format::Pdf)
+        && args.output.is_none()
+        && stdout().is_terminal()
+    {
+        eprintln!("Refusing to write binary data to a terminal.  Use --output or redirect stdout.");
+        std::process::exit(1);
+    }
+    let input = if args.concat && !args.input.is_empty() {
+        let mut stack = badascii::TextBuffer::new(0, 0);
+        for (index, input) in args.input.iter().enumerate() {
+            let text = std::fs::read_to_string(input)
+                .unwrap_or_else(|_| panic!("Unable to open input {:?} for reading", input));
+            let gap = if index == 0 { 0 } else { args.concat_gap };
+            stack.append_below(&badascii::TextBuffer::with_text(text.trim_end()), gap);
+        }
+        stack.render_exact()
+    } else if let Some(input) = args.input.first() {
+        std::fs::read_to_string(input)
+            .unwrap_or_else(|_| panic!("Unable to open input {:?} for reading", input))
+    } else {
+        let mut ret = String::new();
+        stdin()
+            .read_to_string(&mut ret)
+            .expect("Reading from stdin failed");
+        ret
+    };
+    let data = render_or_exit(&args, &input, format);
     if let Some(output) = args.output.as_ref() {
-        std::fs::write(output, svg)
+        std::fs::write(output, data)
             .unwrap_or_else(|_| panic!("Unable to write to output file {}", output.display()));
     } else {
         stdout()
-            .write_all(svg.as_bytes())
+            .write_all(&data)
             .unwrap_or_else(|_| panic!("Unable to write to stdout"))
     }
 }
@@ -0,0 +1,456 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    time::{Duration, Instant},
+};
+
+const TINY_DIAGRAM: &str = "
++----+
+| hi |--->
++----+
+";
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("badascii-cli-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Runs the tool with `args`, feeding it `input` on stdin, and
+// checks that it succeeds.
+fn render_stdin(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{args:?} failed");
+    output
+}
+
+fn wait_for(path: &Path, pred: impl Fn(&str) -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if std::fs::read_to_string(path).is_ok_and(|text| pred(&text)) {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+#[test]
+fn test_png_output_inferred_from_extension() {
+    let dir = scratch_dir("png");
+    let input = dir.join("tiny.txt");
+    let output = dir.join("tiny.png");
+    std::fs::write(&input, TINY_DIAGRAM).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .arg("--input")
+        .arg(&input)
+        .arg("--output")
+        .arg(&output)
+        .arg("--color")
+        .arg("#FFFFFF")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let png = std::fs::read(&output).unwrap();
+    assert!(png.starts_with(b"\x89PNG"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_pdf_output_inferred_from_extension() {
+    let dir = scratch_dir("pdf");
+    let input = dir.join("tiny.txt");
+    let output = dir.join("tiny.pdf");
+    std::fs::write(&input, TINY_DIAGRAM).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .arg("--input")
+        .arg(&input)
+        .arg("--output")
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let pdf = std::fs::read(&output).unwrap();
+    assert!(pdf.starts_with(b"%PDF"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_png_output_to_redirected_stdout() {
+    let dir = scratch_dir("png-stdout");
+    let input = dir.join("tiny.txt");
+    std::fs::write(&input, TINY_DIAGRAM).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .arg("--input")
+        .arg(&input)
+        .arg("--format")
+        .arg("png")
+        .stdout(Stdio::piped())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.starts_with(b"\x89PNG"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_batch_mode_writes_one_svg_per_input() {
+    let dir = scratch_dir("batch");
+    let first = dir.join("first.txt");
+    let second = dir.join("second.txt");
+    std::fs::write(&first, TINY_DIAGRAM).unwrap();
+    std::fs::write(&second, TINY_DIAGRAM).unwrap();
+    let output_dir = dir.join("out");
+    let status = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .arg("--input")
+        .arg(&first)
+        .arg(&second)
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    for name in ["first.svg", "second.svg"] {
+        let svg = std::fs::read_to_string(output_dir.join(name)).unwrap();
+        assert!(svg.starts_with("<svg"));
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_batch_mode_rejects_output() {
+    let dir = scratch_dir("batch-output");
+    let first = dir.join("first.txt");
+    let second = dir.join("second.txt");
+    std::fs::write(&first, TINY_DIAGRAM).unwrap();
+    std::fs::write(&second, TINY_DIAGRAM).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .arg("--input")
+        .arg(&first)
+        .arg(&second)
+        .arg("--output")
+        .arg(dir.join("out.svg"))
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_batch_mode_rejects_missing_and_clashing_outputs() {
+    let dir = scratch_dir("batch-clash");
+    let output_dir = dir.join("out");
+    // No inputs to write
+    let status = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+    // Two inputs with the same name in different directories
+    for sub in ["a", "b"] {
+        std::fs::create_dir_all(dir.join(sub)).unwrap();
+        std::fs::write(dir.join(sub).join("x.txt"), TINY_DIAGRAM).unwrap();
+    }
+    let status = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .arg("--input")
+        .arg(dir.join("a").join("x.txt"))
+        .arg(dir.join("b").join("x.txt"))
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+    assert!(!output_dir.exists());
+    // Numbering the outputs tells them apart
+    let status = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .arg("--input")
+        .arg(dir.join("a").join("x.txt"))
+        .arg(dir.join("b").join("x.txt"))
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .arg("--name-template")
+        .arg("{stem}-{index}.{ext}")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(output_dir.join("x-1.svg").exists());
+    assert!(output_dir.join("x-2.svg").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_name_template_names_batch_outputs() {
+    let dir = scratch_dir("batch-template");
+    let first = dir.join("first.txt");
+    let second = dir.join("second.txt");
+    std::fs::write(&first, TINY_DIAGRAM).unwrap();
+    std::fs::write(&second, TINY_DIAGRAM).unwrap();
+    let output_dir = dir.join("out");
+    let status = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .arg("--input")
+        .arg(&first)
+        .arg(&second)
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .arg("--name-template")
+        .arg("{stem}-{index}.{ext}")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    for name in ["first-1.svg", "second-2.svg"] {
+        let svg = std::fs::read_to_string(output_dir.join(name)).unwrap();
+        assert!(svg.starts_with("<svg"));
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_concat_stacks_inputs() {
+    let dir = scratch_dir("concat");
+    let top = dir.join("top.txt");
+    let bottom = dir.join("bottom.txt");
+    std::fs::write(&top, "+---+\n| a |\n+---+\n").unwrap();
+    std::fs::write(&bottom, "b-c\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .arg("--input")
+        .arg(&top)
+        .arg(&bottom)
+        .arg("--concat")
+        .arg("--concat-gap")
+        .arg("2")
+        .arg("--format")
+        .arg("ascii")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "+---+\n| a |\n+---+\n\n\nb-c\n"
+    );
+    // Without --concat, several inputs need somewhere to go
+    let status = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .arg("--input")
+        .arg(&top)
+        .arg(&bottom)
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_name_template_rejects_bad_templates() {
+    let dir = scratch_dir("batch-bad-template");
+    let input = dir.join("tiny.txt");
+    std::fs::write(&input, TINY_DIAGRAM).unwrap();
+    for template in ["diagram.{ext}", "{stem}.{format}", "sub/{stem}.{ext}"] {
+        let status = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+            .arg("--input")
+            .arg(&input)
+            .arg("--output-dir")
+            .arg(dir.join("out"))
+            .arg("--name-template")
+            .arg(template)
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!status.success(), "{template} was accepted");
+    }
+    assert!(!dir.join("out").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_watch_mode_rerenders_on_change() {
+    let dir = scratch_dir("watch");
+    let input = dir.join("tiny.txt");
+    let output = dir.join("tiny.svg");
+    std::fs::write(&input, TINY_DIAGRAM).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .arg("--watch")
+        .arg("--input")
+        .arg(&input)
+        .arg("--output")
+        .arg(&output)
+        .arg("--embed-source")
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    assert!(wait_for(&output, |svg| svg.contains("hi")));
+    // Make sure the modification time moves, even on coarse filesystems
+    std::thread::sleep(Duration::from_millis(1100));
+    std::fs::write(&input, TINY_DIAGRAM.replace("hi", "yo")).unwrap();
+    let changed = wait_for(&output, |svg| svg.contains("yo"));
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(changed);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_watch_mode_survives_render_errors() {
+    let dir = scratch_dir("watch-errors");
+    let input = dir.join("tiny.txt");
+    let output = dir.join("tiny.png");
+    std::fs::write(&input, TINY_DIAGRAM).unwrap();
+    // The PNG cannot be rendered in a color that does not parse
+    let mut child = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .arg("--watch")
+        .arg("--input")
+        .arg(&input)
+        .arg("--output")
+        .arg(&output)
+        .arg("--color")
+        .arg("not-a-color")
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(1100));
+    let alive = child.try_wait().unwrap().is_none();
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(alive);
+    assert!(!output.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_oversized_png_is_rejected() {
+    let dir = scratch_dir("oversized");
+    let input = dir.join("tiny.txt");
+    let output = dir.join("tiny.png");
+    std::fs::write(&input, TINY_DIAGRAM).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .arg("--input")
+        .arg(&input)
+        .arg("--output")
+        .arg(&output)
+        .arg("--max-pixels")
+        .arg("100")
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+    assert!(!output.exists());
+    let status = Command::new(env!("CARGO_BIN_EXE_badascii-cli"))
+        .arg("--input")
+        .arg(&input)
+        .arg("--output")
+        .arg(&output)
+        .arg("--max-pixels")
+        .arg("100")
+        .arg("--allow-large")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(output.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_arrow_style_changes_output() {
+    let render = |style: &str| {
+        let output = render_stdin(&["--formal-mode", "--arrow-style", style], "+--->");
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let closed = render("closed");
+    let open = render("open");
+    let none = render("none");
+    assert_ne!(closed, open);
+    assert_ne!(open, none);
+    assert_ne!(closed, none);
+}
+
+#[test]
+fn test_bowing_changes_output() {
+    let render =
+        |args: &[&str]| String::from_utf8(render_stdin(args, TINY_DIAGRAM).stdout).unwrap();
+    let plain = render(&[]);
+    assert_eq!(plain, render(&["--bowing", "2"]));
+    assert_ne!(plain, render(&["--bowing", "10"]));
+}
+
+#[test]
+fn test_line_cap_and_join() {
+    let output = render_stdin(
+        &["--line-cap", "butt", "--line-join", "bevel"],
+        TINY_DIAGRAM,
+    );
+    let svg = String::from_utf8(output.stdout).unwrap();
+    assert!(svg.contains(r#"stroke-linecap="butt""#));
+    assert!(svg.contains(r#"stroke-linejoin="bevel""#));
+}
+
+#[test]
+fn test_padding_expands_view_box() {
+    let output = render_stdin(&["--formal-mode", "--padding", "10"], "+--->");
+    let svg = String::from_utf8(output.stdout).unwrap();
+    assert!(svg.contains(r#"viewBox="-10 -10 70 35""#));
+}
+
+#[test]
+fn test_fill_color() {
+    let output = render_stdin(&["--fill-color", "#336699"], "+--+\n|░░|\n+--+");
+    let svg = String::from_utf8(output.stdout).unwrap();
+    assert!(svg.contains(r##"<rect class="badascii-fill" fill="#336699""##));
+    assert!(!svg.contains('░'));
+}
+
+#[test]
+fn test_minify_is_smaller() {
+    let normal = render_stdin(&[], TINY_DIAGRAM).stdout;
+    let minified = render_stdin(&["--minify"], TINY_DIAGRAM).stdout;
+    assert!(minified.len() < normal.len());
+    assert!(!minified.contains(&b'\n'));
+}
+
+#[test]
+fn test_ascii_output_trims_unless_asked() {
+    let render =
+        |args: &[&str]| String::from_utf8(render_stdin(args, TINY_DIAGRAM).stdout).unwrap();
+    assert_eq!(
+        render(&["--format", "ascii"]),
+        "+----+\n| hi |--->\n+----+\n"
+    );
+    let padded = TINY_DIAGRAM
+        .split('\n')
+        .map(|line| format!("{line:10}"))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        render(&["--format", "ascii", "--keep-blank-lines"]),
+        padded.join("\n")
+    );
+}
+
+#[test]
+fn test_lint_reports_unclosed_box() {
+    let lint = |diagram: &str| {
+        let output = render_stdin(&["--lint", "--format", "ascii"], diagram);
+        String::from_utf8(output.stderr).unwrap()
+    };
+    assert_eq!(
+        lint("+----+\n| hi |\n+---- \n"),
+        "line 3, column 6: box is missing a `+` corner\n"
+    );
+    assert_eq!(lint(TINY_DIAGRAM), "");
+}
@@ -1,7 +1,11 @@
 use badascii::{render::RenderJob, text_buffer::TextBuffer};
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{LitStr, parse_macro_input};
+use syn::{
+    Ident, Lit, LitStr, Token,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+};
 
 fn strip_outer(x: &str) -> String {
     let x = x
@@ -24,22 +28,99 @@ fn get_text_buffer(input: LitStr) -> TextBuffer {
     TextBuffer::with_text(&input)
 }
 
-#[proc_macro]
-pub fn badascii_formal(input: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(input as LitStr);
-    let text_buffer = get_text_buffer(input);
-    let job = RenderJob::formal(text_buffer);
-    let svg = badascii::svg::render(&job, "currentColor", "none");
+// The arguments to the macros: the diagram (or path), followed
+// by optional `key = value` pairs, e.g.
+// `badascii!("...", color = "#333", width = 400.0)`.
+struct MacroInput {
+    text: LitStr,
+    color: String,
+    background: String,
+    width: Option<f32>,
+}
+
+impl Parse for MacroInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = MacroInput {
+            text: input.parse()?,
+            color: "currentColor".into(),
+            background: "none".into(),
+            width: None,
+        };
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            match key.to_string().as_str() {
+                "color" => args.color = input.parse::<LitStr>()?.value(),
+                "background" => args.background = input.parse::<LitStr>()?.value(),
+                "width" => {
+                    args.width = Some(match input.parse::<Lit>()? {
+                        Lit::Float(width) => width.base10_parse()?,
+                        Lit::Int(width) => width.base10_parse()?,
+                        lit => return Err(syn::Error::new(lit.span(), "Expected a number")),
+                    })
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        format!("Unknown option `{key}`, expected color, background or width"),
+                    ));
+                }
+            }
+        }
+        Ok(args)
+    }
+}
+
+fn centered_svg(mut job: RenderJob, args: &MacroInput) -> TokenStream {
+    if let Some(width) = args.width {
+        job.scale_to_width(width);
+    }
+    let svg = badascii::svg::render(&job, &args.color, &args.background);
     let svg = format!("<p></p><div style=\"text-align:center;\">{svg}</div><p></p>");
     quote!(#svg).into()
 }
 
+#[proc_macro]
+pub fn badascii_formal(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input as MacroInput);
+    let text_buffer = get_text_buffer(args.text.clone());
+    centered_svg(RenderJob::formal(text_buffer), &args)
+}
+
 #[proc_macro]
 pub fn badascii(input: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(input as LitStr);
-    let text_buffer = get_text_buffer(input);
-    let job = RenderJob::rough(text_buffer);
-    let svg = badascii::svg::render(&job, "currentColor", "none");
-    let svg = format!("<p></p><div style=\"text-align:center;\">{svg}</div><p></p>");
-    quote!(#svg).into()
+    let args = parse_macro_input!(input as MacroInput);
+    let text_buffer = get_text_buffer(args.text.clone());
+    centered_svg(RenderJob::rough(text_buffer), &args)
+}
+
+/// Render the diagram in the given file, which is resolved
+/// relative to the `CARGO_MANIFEST_DIR` of the calling crate.
+///
+/// The file is read when the macro is expanded, and the compiler
+/// does not know to watch it.  After editing the diagram, touch the
+/// source file that uses it (or add a `cargo:rerun-if-changed` line
+/// for it to a build script) to pick up the change.
+///
+/// ```compile_fail
+/// let html = badascii_doc::badascii_file!("no/such/diagram.txt");
+/// ```
+#[proc_macro]
+pub fn badascii_file(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input as MacroInput);
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let path = std::path::Path::new(&manifest_dir).join(args.text.value());
+    match std::fs::read_to_string(&path) {
+        Ok(text) => centered_svg(RenderJob::rough(TextBuffer::with_text(&text)), &args),
+        Err(err) => syn::Error::new(
+            args.text.span(),
+            format!("Unable to read diagram {}: {err}", path.display()),
+        )
+        .to_compile_error()
+        .into(),
+    }
 }
@@ -0,0 +1,37 @@
+use badascii_doc::{badascii, badascii_file, badascii_formal};
+
+#[test]
+fn test_bare_literal() {
+    let html = badascii!("+--> ok");
+    assert!(html.contains(r#"stroke="currentColor""#));
+    assert!(html.contains(r#"width="70px""#));
+}
+
+#[test]
+fn test_color_and_width() {
+    let html = badascii_formal!("+--> ok", color = "#333", background = "#FFF", width = 90.0,);
+    assert!(html.contains(r##"stroke="#333""##));
+    assert!(html.contains(r##"fill="#FFF""##));
+    assert!(html.contains(r#"width="90px""#));
+    assert!(html.contains(r#"viewBox="0 0 90 "#));
+}
+
+#[test]
+fn test_integer_width() {
+    let html = badascii!("+--> ok", width = 90);
+    assert!(html.contains(r#"width="90px""#));
+}
+
+#[test]
+fn test_file_matches_literal() {
+    assert_eq!(badascii_file!("tests/diagram.txt"), badascii!("+--> ok"));
+    assert_eq!(
+        badascii_file!("tests/diagram.txt", width = 90),
+        badascii!("+--> ok", width = 90)
+    );
+}
+
+/// The diagram in a file can also be used as documentation.
+#[doc = badascii_file!("tests/diagram.txt", width = 90)]
+#[allow(dead_code)]
+struct Documented;
@@ -14,4 +14,8 @@ pub enum Action {
     Enter,
     Paste(String),
     Copy,
+    Undo,
+    Redo,
+    SelectAll,
+    Duplicate,
 }
@@ -1,64 +1,24 @@
-use std::collections::VecDeque;
-
-use badascii::{RenderJob, TextBuffer, rect::Rectangle, tc::TextCoordinate, text_buffer::Size};
+use badascii::{RenderJob, Size, TextBuffer, tc::TextCoordinate};
+#[cfg(target_arch = "wasm32")]
+use base64::engine::general_purpose::STANDARD;
 use base64::{Engine as _, engine::general_purpose::URL_SAFE};
 use eframe::CreationContext;
 use egui::{
-    Align2, Button, Checkbox, Color32, ColorImage, CursorIcon, DragValue, Event, FontId, Key,
-    Modifiers, OpenUrl, Painter, Pos2, Rect, Response, Scene, Sense, Ui, Vec2, epaint::PathStroke,
-    global_theme_preference_switch, util::hash, vec2,
+    Align2, Button, Checkbox, Color32, ColorImage, DragValue, FontId, OpenUrl, Painter, Rect,
+    Scene, Sense, Ui, Vec2, global_theme_preference_switch, vec2,
 };
 use egui_dock::{DockArea, DockState, NodeIndex, Style, TabViewer};
 use miniz_oxide::deflate::compress_to_vec;
-use rasterize::Image;
-
-use crate::{action::Action, roughr_egui::stroke_opset};
-
-const TEXT_SCALE_FACTOR: f32 = 1.5;
-
-#[derive(Copy, Clone, Debug)]
-struct TextState {
-    origin: TextCoordinate,
-    cursor: TextCoordinate,
-}
-
-#[derive(Copy, Clone, Debug)]
-struct MoveState {
-    selection: Rectangle,
-    origin: TextCoordinate,
-    move_pos: TextCoordinate,
-}
+use rasterize::{Image, Layer, LinColor};
 
-#[derive(Clone, Debug)]
-enum Tool {
-    Selection(Option<TextCoordinate>),
-    Text(Option<TextState>),
-    Selected(Rectangle),
-    MovingText(MoveState),
-}
-
-fn map_key(key: &Key, modifiers: &Modifiers) -> Option<Action> {
-    match key {
-        Key::Backspace => Some(Action::Backspace),
-        Key::ArrowUp if modifiers.shift_only() => Some(Action::UpControlArrow),
-        Key::ArrowDown if modifiers.shift_only() => Some(Action::DownControlArrow),
-        Key::ArrowLeft if modifiers.shift_only() => Some(Action::LeftControlArrow),
-        Key::ArrowRight if modifiers.shift_only() => Some(Action::RightControlArrow),
-        Key::ArrowUp if !modifiers.any() => Some(Action::UpArrow),
-        Key::ArrowDown if !modifiers.any() => Some(Action::DownArrow),
-        Key::ArrowLeft if !modifiers.any() => Some(Action::LeftArrow),
-        Key::ArrowRight if !modifiers.any() => Some(Action::RightArrow),
-        Key::Escape => Some(Action::Escape),
-        Key::Enter => Some(Action::Enter),
-        Key::Copy => Some(Action::Copy),
-        _ => None,
-    }
-}
-
-#[derive(Clone)]
-struct Snapshot {
-    text: TextBuffer,
-}
+pub use crate::canvas::DEFAULT_MAX_UNDO_DEPTH;
+use crate::{
+    canvas::{
+        AsciiCanvas, FindReplace, MAX_CANVAS_SIZE, TEXT_SCALE_FACTOR, canvas_job, roughr_options,
+    },
+    roughr_egui::stroke_opset,
+    templates::TEMPLATES,
+};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Tab {
@@ -67,56 +27,65 @@ pub enum Tab {
 }
 
 pub struct MyApp {
-    num_rows: u32,
-    num_cols: u32,
-    tool: Tool,
-    snapshots: VecDeque<Snapshot>,
-    futures: Vec<Snapshot>,
-    selected_text: TextBuffer,
-    text: TextBuffer,
-    copy_buffer: Option<String>,
-    hover_pos: Option<TextCoordinate>,
+    canvas: AsciiCanvas,
     resize: Option<Size>,
-    prev_action: Option<Action>,
     dock_state: DockState<Tab>,
     scene_rect: Rect,
     drag_delta: Option<Vec2>,
     canvas_size: Vec2,
-    rough_mode: bool,
     reset_zoom: bool,
     base_url: String,
+    file_error: Option<String>,
+    export_error: Option<String>,
+    cell_aspect: f32,
+    #[cfg(target_arch = "wasm32")]
+    pending_file: std::sync::Arc<std::sync::Mutex<Option<String>>>,
 }
 
 const INITIAL_TEXT: &str = include_str!("startup_screen.txt");
 
+/// The width of a cell as a fraction of its height, for the preview
+/// and the exported images.  The default suits most monospace fonts.
+const DEFAULT_CELL_ASPECT: f32 = 10.0 / 15.0;
+const CELL_ASPECT_RANGE: std::ops::RangeInclusive<f32> = 0.25..=2.0;
+
+/// The height of a cell in the exported images, in pixels.
+const EXPORT_CELL_HEIGHT: f32 = 15.0;
+
+// The largest area with room for `size` cells of the given aspect
+// that fits in `available`.
+fn fit_cells(available: Vec2, size: Size, cell_aspect: f32) -> Vec2 {
+    let cells = vec2(
+        size.num_cols.max(1) as f32 * cell_aspect,
+        size.num_rows.max(1) as f32,
+    );
+    let scale = (available.x / cells.x).min(available.y / cells.y);
+    cells * scale
+}
+
 impl Default for MyApp {
     fn default() -> Self {
-        let num_rows = 40;
-        let num_cols = 100;
-        let mut text = TextBuffer::new(num_rows, num_cols);
-        text.paste(INITIAL_TEXT, TextCoordinate { x: 0, y: 0 });
+        let mut canvas = AsciiCanvas::new(40, 100);
+        canvas
+            .text_mut()
+            .paste(INITIAL_TEXT, TextCoordinate { x: 0, y: 0 });
         let mut state = DockState::new(vec![Tab::Ascii]);
         let surface = state.main_surface_mut();
         surface.split_right(NodeIndex::root(), 0.7, vec![Tab::Preview]);
         Self {
-            snapshots: VecDeque::with_capacity(100),
-            futures: Vec::new(),
-            num_rows,
-            num_cols,
-            tool: Tool::Selection(None),
-            selected_text: TextBuffer::new(num_rows, num_cols),
-            text,
-            copy_buffer: None,
-            hover_pos: None,
+            canvas,
             resize: None,
-            prev_action: None,
             dock_state: state,
             scene_rect: Rect::NAN,
             drag_delta: None,
-            rough_mode: true,
             reset_zoom: false,
             base_url: Default::default(),
             canvas_size: vec2(1000.0, 600.0),
+            file_error: None,
+            export_error: None,
+            cell_aspect: DEFAULT_CELL_ASPECT,
+            #[cfg(target_arch = "wasm32")]
+            pending_file: Default::default(),
         }
     }
 }
@@ -128,20 +97,23 @@ impl MyApp {
         let vals = map.get("d")?.first()?;
         let rows = map.get("r")?.first()?;
         let cols = map.get("c")?.first()?;
-        let rows = rows.parse::<u32>().ok()?.min(1024);
-        let cols = cols.parse::<u32>().ok()?.min(1024);
+        let rows = rows.parse::<u32>().ok()?.min(MAX_CANVAS_SIZE);
+        let cols = cols.parse::<u32>().ok()?.min(MAX_CANVAS_SIZE);
         let decoded = URL_SAFE.decode(&vals).ok()?;
         let decompressed = miniz_oxide::inflate::decompress_to_vec(&decoded).ok()?;
         let ascii = String::from_utf8_lossy(&decompressed);
         let mut me = Self::default();
-        me.text.clear_all();
-        me.text = me.text.resize(Size {
-            num_cols: cols,
-            num_rows: rows,
-        });
-        me.text.paste(&ascii, TextCoordinate { x: 0, y: 0 });
-        me.num_rows = rows;
-        me.num_cols = cols;
+        if let Some(aspect) = map.get("a").and_then(|a| a.first()?.parse::<f32>().ok()) {
+            me.cell_aspect = aspect.clamp(*CELL_ASPECT_RANGE.start(), *CELL_ASPECT_RANGE.end());
+        }
+        me.canvas.set_text(TextBuffer::with_dimensions(
+            &ascii,
+            Size {
+                num_cols: cols,
+                num_rows: rows,
+            },
+            TextCoordinate { x: 0, y: 0 },
+        ));
         Some(me)
     }
 
@@ -151,386 +123,109 @@ impl MyApp {
         #[cfg(not(target_arch = "wasm32"))]
         Self::default()
     }
-    fn map_pos_to_coords(&self, canvas: &Rect, pos: Pos2) -> Option<TextCoordinate> {
-        let top_left = canvas.left_top();
-        let delta = pos - top_left;
-        let delta_x = canvas.width() / self.num_cols as f32;
-        let delta_y = canvas.height() / self.num_rows as f32;
-        let col = (delta.x / delta_x).floor() as i32;
-        let row = (delta.y / delta_y).floor() as i32;
-        if (0..(self.num_cols as i32)).contains(&col) && (0..(self.num_rows as i32)).contains(&row)
-        {
-            Some(TextCoordinate {
-                x: col as u32,
-                y: row as u32,
-            })
-        } else {
-            None
-        }
-    }
-    fn map_text_coordinate_to_cell_center(&self, canvas: &Rect, coord: &TextCoordinate) -> Pos2 {
-        let left_top = canvas.left_top();
-        let delta_x = canvas.width() / self.num_cols as f32;
-        let delta_y = canvas.height() / self.num_rows as f32;
-        let left_top_corner = left_top + vec2(delta_x * coord.x as f32, delta_y * coord.y as f32);
-        left_top_corner + vec2(delta_x / 2.0, delta_y / 2.0)
-    }
-    fn map_rectangle_to_rect(&self, canvas: &Rect, rect: &Rectangle) -> Rect {
-        let corner_1 = self.map_text_coordinate_to_cell_center(canvas, &rect.corner_1);
-        let corner_2 = self.map_text_coordinate_to_cell_center(canvas, &rect.corner_2);
-        Rect::from_two_pos(corner_1, corner_2)
-    }
-    fn snapshot(&mut self) {
-        while self.snapshots.len() >= 100 {
-            self.snapshots.pop_front();
-        }
-        let mut text = self.text.clone();
-        for (pos, c) in self.selected_text.iter() {
-            text.set_text(&pos, Some(c))
-        }
-        let text_hash = hash(&text);
-        let last_hash = self.snapshots.back().map(|t| hash(&t.text)).unwrap_or(!0);
-        if text_hash != last_hash {
-            self.snapshots.push_back(Snapshot { text });
-        }
-    }
-    fn set_text(&mut self, ch: char, position: &TextCoordinate) {
-        self.text.set_text(position, Some(ch));
-    }
-    fn clear_text(&mut self, position: &TextCoordinate) {
-        self.text.set_text(position, None);
-    }
-    fn on_drag_start(&mut self, tc: TextCoordinate, resp: &Response) {
-        match &self.tool {
-            Tool::Selection(None) => {
-                if !resp.dragged_by(egui::PointerButton::Secondary) {
-                    self.tool = Tool::Selection(Some(tc));
-                }
-            }
-            Tool::Selected(rect) => {
-                self.tool = Tool::MovingText(MoveState {
-                    selection: *rect,
-                    origin: tc,
-                    move_pos: tc,
-                })
-            }
-            Tool::Text(_) => self.tool = Tool::Selection(Some(tc)),
-            _ => (),
-        }
-    }
-    fn on_drag(&mut self, corner2: TextCoordinate, canvas: &Rect, painter: &Painter) {
-        let delta_x = canvas.width() / self.num_cols as f32;
-        let delta_y = canvas.height() / self.num_rows as f32;
-        match &self.tool {
-            Tool::Selection(Some(corner1)) => {
-                let selection_box = Rectangle::new(*corner1, corner2);
-                let rect = self.map_rectangle_to_rect(canvas, &selection_box);
-                let rect = rect.expand2(vec2(delta_x / 2.0, delta_y / 2.0));
-                painter.rect_stroke(
-                    rect,
-                    1.0,
-                    (1.0, Color32::LIGHT_GREEN),
-                    egui::StrokeKind::Middle,
-                );
-            }
-            Tool::MovingText(MoveState {
-                selection,
-                origin,
-                move_pos: _,
-            }) => {
-                self.tool = Tool::MovingText(MoveState {
-                    selection: *selection,
-                    origin: *origin,
-                    move_pos: corner2,
-                });
-            }
-            _ => {}
-        }
-    }
-    fn on_drag_stop(&mut self, corner2: TextCoordinate) {
-        match &self.tool {
-            Tool::Selection(Some(corner1)) => {
-                let selection = Rectangle::new(*corner1, corner2);
-                if selection
-                    .iter_interior()
-                    .any(|pos| self.text.get(pos).is_some())
-                {
-                    self.snapshot();
-                    self.selected_text = self.text.clone();
-                    self.text.clear_rectangle(selection);
-                    self.tool = Tool::Selected(selection);
-                } else {
-                    self.tool = Tool::Selection(None);
-                }
-            }
-            Tool::MovingText(MoveState {
-                selection,
-                origin,
-                move_pos,
-            }) => {
-                let mut swap_buf = TextBuffer::new(self.num_rows, self.num_cols);
-                for pos in selection.iter_interior() {
-                    let selection = self.selected_text.get(pos);
-                    let new_pos = pos.shifted(*origin, *move_pos);
-                    swap_buf.merge_text(&new_pos, selection);
-                }
-                let selection_shifted = selection.shifted(*origin, *move_pos);
-                self.snapshot();
-                self.selected_text = swap_buf;
-                self.tool = Tool::Selected(selection_shifted);
-            }
-            _ => {}
-        }
-    }
-    fn on_click(&mut self, pos: TextCoordinate) {
-        match &self.tool {
-            Tool::Text(_) => {
-                self.snapshot();
-                self.tool = Tool::Text(Some(TextState {
-                    origin: pos,
-                    cursor: pos,
-                }))
-            }
-            Tool::Selected(selection_box) => {
-                let selection_box = *selection_box;
-                self.snapshot();
-                for pos in selection_box.iter_interior() {
-                    let selection = self.selected_text.get(pos);
-                    self.text.merge_text(&pos, selection);
-                }
-                self.selected_text.clear_all();
-                self.tool = Tool::Selection(None);
-            }
-            Tool::Selection(None) => {
-                self.tool = Tool::Text(Some(TextState {
-                    origin: pos,
-                    cursor: pos,
-                }));
-            }
-            _ => {}
-        }
-    }
-    fn on_action_with_text(&mut self, text_state: TextState, action: Action) {
-        if self.resize.is_some() {
-            return;
-        }
-        let TextState { cursor, origin } = text_state;
-        match action.clone() {
-            Action::Paste(txt) => {
-                self.text.paste(&txt, cursor);
-            }
-            Action::Backspace => {
-                self.clear_text(&cursor);
-                self.tool = Tool::Text(Some(TextState {
-                    origin,
-                    cursor: cursor.left(),
-                }));
-            }
-            Action::Char(ch) => {
-                self.set_text(ch, &cursor);
-                self.tool = Tool::Text(Some(TextState {
-                    origin,
-                    cursor: cursor.right(),
-                }));
-            }
-            Action::RightControlArrow => {
-                let char = if (self.prev_action != Some(Action::RightControlArrow))
-                    && (self.prev_action != Some(Action::LeftControlArrow))
-                {
-                    '+'
-                } else {
-                    '-'
-                };
-                self.set_text(char, &cursor);
-                self.tool = Tool::Text(Some(TextState {
-                    origin,
-                    cursor: cursor.right(),
-                }));
-            }
-            Action::RightArrow => {
-                self.tool = Tool::Text(Some(TextState {
-                    origin,
-                    cursor: cursor.right(),
-                }));
-            }
-            Action::LeftControlArrow => {
-                let char = if (self.prev_action != Some(Action::RightControlArrow))
-                    && (self.prev_action != Some(Action::LeftControlArrow))
-                {
-                    '+'
-                } else {
-                    '-'
-                };
-                self.set_text(char, &cursor);
-                self.tool = Tool::Text(Some(TextState {
-                    origin,
-                    cursor: cursor.left(),
-                }));
-            }
-            Action::LeftArrow => {
-                self.tool = Tool::Text(Some(TextState {
-                    origin,
-                    cursor: cursor.left(),
-                }));
-            }
-            Action::UpControlArrow => {
-                let char = if (self.prev_action != Some(Action::UpControlArrow))
-                    && (self.prev_action != Some(Action::DownControlArrow))
-                {
-                    '+'
-                } else {
-                    '|'
-                };
-                self.set_text(char, &cursor);
-                self.tool = Tool::Text(Some(TextState {
-                    origin,
-                    cursor: cursor.up(),
-                }));
-            }
-            Action::UpArrow => {
-                self.tool = Tool::Text(Some(TextState {
-                    origin,
-                    cursor: cursor.up(),
-                }));
-            }
-            Action::DownControlArrow => {
-                let char = if (self.prev_action != Some(Action::DownControlArrow))
-                    && (self.prev_action != Some(Action::UpControlArrow))
-                {
-                    '+'
-                } else {
-                    '|'
-                };
-                self.set_text(char, &cursor);
-                self.tool = Tool::Text(Some(TextState {
-                    origin,
-                    cursor: cursor.down(),
-                }));
-            }
-            Action::DownArrow => {
-                self.tool = Tool::Text(Some(TextState {
-                    origin,
-                    cursor: cursor.down(),
-                }));
-            }
-            Action::Escape => {
-                self.tool = Tool::Selection(None);
-            }
-            Action::Enter => {
-                let origin = origin.down();
-                self.tool = Tool::Text(Some(TextState {
-                    origin,
-                    cursor: origin,
-                }));
-            }
-            Action::Copy => {
-                self.copy_buffer = Some(self.text.render());
-            }
-        }
-        self.prev_action = Some(action);
-    }
-    fn on_action(&mut self, action: Action) {
-        match &self.tool {
-            Tool::Text(Some(text_state)) => {
-                self.on_action_with_text(*text_state, action);
-            }
-            Tool::Selection(None) => match action {
-                Action::Char('t') => self.tool = Tool::Text(None),
-                Action::Copy => {
-                    self.copy_buffer = Some(self.text.render());
-                }
-                Action::Paste(txt) => {
-                    self.snapshot();
-                    let hover_pos = self.hover_pos.unwrap_or_default();
-                    let rect = self.selected_text.paste(&txt, hover_pos);
-                    self.tool = Tool::Selected(rect);
-                }
-                _ => {}
-            },
-            Tool::Selected(rect) if action == Action::Copy => {
-                let selection = self.selected_text.window(rect);
-                self.copy_buffer = Some(selection.render());
-            }
-            Tool::Selected(rect) if action == Action::Escape => {
-                for pos in rect.iter_interior() {
-                    let selection = self.selected_text.get(pos);
-                    self.text.merge_text(&pos, selection);
-                }
-                self.selected_text.clear_all();
-                self.tool = Tool::Selection(None);
-            }
-            Tool::Selected(_) if action == Action::Backspace => {
-                self.selected_text.clear_all();
-                self.tool = Tool::Selection(None);
-            }
-            _ if action == Action::Escape => self.tool = Tool::Selection(None),
-            _ => {}
-        }
-    }
-    fn on_hover(&mut self, tc: Option<TextCoordinate>) {
-        self.hover_pos = tc;
-    }
-    fn undo(&mut self) {
-        if let Some(buf) = self.snapshots.pop_back() {
-            self.futures.push(buf.clone());
-            self.text = buf.text;
-            self.selected_text.clear_all();
-            self.tool = Tool::Selection(None);
-        }
-    }
-    fn redo(&mut self) {
-        if let Some(buf) = self.futures.pop() {
-            self.text = buf.text;
-            self.selected_text.clear_all();
-            self.tool = Tool::Selection(None);
-            self.snapshot();
-        }
+    /// Set the number of undo steps to keep (at least one).  Each
+    /// step holds a copy of the canvas, so long editing sessions
+    /// can trade memory for a deeper history.
+    pub fn with_max_undo_depth(mut self, depth: usize) -> Self {
+        self.canvas = self.canvas.with_max_undo_depth(depth);
+        self
     }
     fn ascii_control_panel(&mut self, ui: &mut Ui) {
         ui.horizontal(|ui| {
             global_theme_preference_switch(ui);
+            self.file_menu(ui);
             if ui.button("⚙").on_hover_text("Canvas Size").clicked() {
-                self.resize = Some(Size {
-                    num_cols: self.num_cols,
-                    num_rows: self.num_rows,
-                });
+                self.resize = Some(self.canvas.text().size());
             }
             if ui
-                .add_enabled(!self.snapshots.is_empty(), Button::new("Undo"))
+                .add_enabled(self.canvas.can_undo(), Button::new("Undo"))
+                .on_hover_text("Ctrl+Z")
                 .clicked()
             {
-                self.undo();
+                self.canvas.undo();
             }
             if ui
-                .add_enabled(!self.futures.is_empty(), Button::new("Redo"))
+                .add_enabled(self.canvas.can_redo(), Button::new("Redo"))
+                .on_hover_text("Ctrl+Y")
                 .clicked()
             {
-                self.redo();
+                self.canvas.redo();
             }
             if ui
                 .button("📋")
                 .on_hover_text("Copy ASCII version to clipboard")
                 .clicked()
             {
-                let ascii = self.text.render();
+                let ascii = self.canvas.text_with_selection().render();
                 ui.output_mut(|o| o.commands.push(egui::OutputCommand::CopyText(ascii)))
             }
             if ui.button("Clear").clicked() {
-                self.text.clear_all();
+                self.canvas.text_mut().clear_all();
+            }
+            if ui
+                .selectable_label(self.canvas.line_tool(), "Line")
+                .on_hover_text("Drag to draw a wire (L)")
+                .clicked()
+            {
+                self.canvas.set_line_tool(!self.canvas.line_tool());
+            }
+            if ui
+                .selectable_label(self.canvas.rect_tool(), "Box")
+                .on_hover_text("Drag from corner to corner to draw a box (R)")
+                .clicked()
+            {
+                self.canvas.set_rect_tool(!self.canvas.rect_tool());
+            }
+            if ui
+                .selectable_label(self.canvas.pin_tool(), "Pin")
+                .on_hover_text(
+                    "Click the side of a box, type a name, and press Enter to add a pin.  \
+                     The arrow keys switch between an input and an output (P)",
+                )
+                .clicked()
+            {
+                self.canvas.set_pin_tool(!self.canvas.pin_tool());
+            }
+            if ui
+                .selectable_label(self.canvas.find.is_some(), "🔍")
+                .on_hover_text("Find and replace")
+                .clicked()
+            {
+                self.canvas.find = match self.canvas.find {
+                    Some(_) => None,
+                    None => Some(FindReplace::default()),
+                };
             }
+            ui.menu_button("Templates", |ui| {
+                for (name, template) in TEMPLATES {
+                    if ui.button(*name).clicked() {
+                        self.canvas.insert_template(template);
+                        ui.close_menu();
+                    }
+                }
+            });
+            ui.add(Checkbox::new(&mut self.canvas.show_grid, "Grid"));
+            ui.add(Checkbox::new(&mut self.canvas.show_wire_ends, "Arrows"))
+                .on_hover_text("Draw the arrow heads and junctions over the text");
+            ui.add_enabled(
+                self.canvas.show_grid,
+                DragValue::new(&mut self.canvas.grid_spacing)
+                    .range(1..=16)
+                    .prefix("every "),
+            )
+            .on_hover_text("Number of cells between grid lines");
             if ui
                 .button("🔗")
                 .on_hover_text("Copy URL for this diagram")
                 .clicked()
             {
-                let ascii = self.text.render();
-                let compressed = compress_to_vec(ascii.as_bytes(), 10);
+                let text = self.canvas.text_with_selection();
+                let compressed = compress_to_vec(text.render().as_bytes(), 10);
                 let encoded = URL_SAFE.encode(compressed);
-                let num_cols = self.text.size().num_cols;
-                let num_rows = self.text.size().num_rows;
+                let Size { num_cols, num_rows } = text.size();
                 let url = format!(
-                    "{}/?d={}&c={}&r={}",
-                    self.base_url, encoded, num_cols, num_rows
+                    "{}/?d={}&c={}&r={}&a={}",
+                    self.base_url, encoded, num_cols, num_rows, self.cell_aspect
                 );
                 ui.output_mut(|o| o.commands.push(egui::OutputCommand::CopyText(url)));
             }
@@ -548,27 +243,196 @@ impl MyApp {
             }
         });
     }
+    fn find_panel(&mut self, ui: &mut Ui) {
+        let num_matches = self
+            .canvas
+            .find
+            .as_ref()
+            .map(|find| self.canvas.text().find(&find.needle).len());
+        let (Some(find), Some(num_matches)) = (self.canvas.find.as_mut(), num_matches) else {
+            return;
+        };
+        let mut should_replace = false;
+        ui.horizontal(|ui| {
+            ui.label("Find");
+            ui.text_edit_singleline(&mut find.needle);
+            ui.label("Replace");
+            ui.text_edit_singleline(&mut find.replacement);
+            ui.label(format!("{num_matches} matches"));
+            should_replace = ui
+                .add_enabled(num_matches > 0, Button::new("Replace all"))
+                .clicked();
+        });
+        if should_replace {
+            self.canvas.replace_all();
+        }
+    }
+    // The job for exporting the diagram, trimmed to its contents.
+    fn export_job(&self) -> RenderJob {
+        let text = self.canvas.text_with_selection().shrink_to_fit();
+        RenderJob {
+            width: text.size().num_cols as f32 * EXPORT_CELL_HEIGHT * self.cell_aspect,
+            height: text.size().num_rows as f32 * EXPORT_CELL_HEIGHT,
+            options: roughr_options(self.canvas.rough_mode),
+            ..RenderJob::formal(text)
+        }
+    }
+    // The SVG of the diagram, in the colors of the current theme.
+    fn export_svg(&self, ui: &Ui) -> String {
+        let text_color = ui.visuals().strong_text_color().to_hex();
+        let background_color = ui.visuals().extreme_bg_color.to_hex();
+        badascii::svg::render(&self.export_job(), &text_color, &background_color)
+    }
+    // The bitmap of the diagram, in the colors of the current theme.
+    fn export_image(&self, ui: &Ui) -> Result<Layer<LinColor>, String> {
+        let text_color = ui.visuals().strong_text_color().to_hex();
+        let background_color = ui.visuals().extreme_bg_color.to_hex();
+        badascii::bitmap::render(&self.export_job(), &text_color, &background_color, 1.0)
+            .map_err(|err| format!("Unable to render the image: {err}"))
+    }
+    // The diagram as the bytes of a PNG file.
+    fn export_png(&self, ui: &Ui) -> Result<Vec<u8>, String> {
+        let mut png = vec![];
+        self.export_image(ui)?
+            .write_png(&mut png)
+            .map_err(|err| format!("Unable to encode the PNG: {err}"))?;
+        Ok(png)
+    }
+    // Replace the diagram with the contents of a file, which can be
+    // undone.  The canvas grows to fit the text, but no larger than
+    // `MAX_CANVAS_SIZE` in either direction.  The size is checked
+    // before anything is allocated for the text.
+    fn load_text(&mut self, text: &str) -> Result<(), String> {
+        let loaded = Size {
+            num_rows: text.lines().count() as u32,
+            num_cols: text
+                .lines()
+                .map(|line| line.chars().count())
+                .max()
+                .unwrap_or(0) as u32,
+        };
+        if loaded.num_rows > MAX_CANVAS_SIZE || loaded.num_cols > MAX_CANVAS_SIZE {
+            return Err(format!(
+                "The file is {}x{}, which is larger than the {MAX_CANVAS_SIZE}x{MAX_CANVAS_SIZE} limit",
+                loaded.num_cols, loaded.num_rows
+            ));
+        }
+        self.canvas.snapshot();
+        let current = self.canvas.text().size();
+        let size = Size {
+            num_cols: loaded.num_cols.max(current.num_cols),
+            num_rows: loaded.num_rows.max(current.num_rows),
+        };
+        self.canvas.set_text(TextBuffer::with_dimensions(
+            text,
+            size,
+            TextCoordinate { x: 0, y: 0 },
+        ));
+        Ok(())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn open_file(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Text", &["txt"])
+            .pick_file()
+        else {
+            return;
+        };
+        self.file_error = std::fs::read_to_string(&path)
+            .map_err(|err| format!("Unable to read {}: {err}", path.display()))
+            .and_then(|text| self.load_text(&text))
+            .err();
+    }
+    // The browser picks the file asynchronously, so the text is
+    // loaded on a later frame.
+    #[cfg(target_arch = "wasm32")]
+    fn open_file(&mut self) {
+        let pending = self.pending_file.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Some(file) = rfd::AsyncFileDialog::new()
+                .add_filter("Text", &["txt"])
+                .pick_file()
+                .await
+            {
+                let text = String::from_utf8_lossy(&file.read().await).into_owned();
+                *pending.lock().unwrap() = Some(text);
+            }
+        });
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_file(name: &str, contents: &[u8]) -> Result<(), String> {
+        let Some(path) = rfd::FileDialog::new().set_file_name(name).save_file() else {
+            return Ok(());
+        };
+        std::fs::write(&path, contents)
+            .map_err(|err| format!("Unable to write {}: {err}", path.display()))
+    }
+    // The browser saves the file as a download.
+    #[cfg(target_arch = "wasm32")]
+    fn save_file(name: &str, contents: &[u8]) -> Result<(), String> {
+        use eframe::wasm_bindgen::JsCast as _;
+        let href = format!(
+            "data:application/octet-stream;base64,{}",
+            STANDARD.encode(contents)
+        );
+        let anchor = web_sys::window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.create_element("a").ok())
+            .and_then(|element| element.dyn_into::<web_sys::HtmlElement>().ok());
+        let Some(anchor) = anchor else {
+            return Err("Unable to start the download".into());
+        };
+        let _ = anchor.set_attribute("href", &href);
+        let _ = anchor.set_attribute("download", name);
+        anchor.click();
+        Ok(())
+    }
+    fn file_menu(&mut self, ui: &mut Ui) {
+        if ui
+            .button("Open")
+            .on_hover_text("Open a diagram from a text file")
+            .clicked()
+        {
+            self.open_file();
+        }
+        ui.menu_button("Save", |ui| {
+            if ui.button("ASCII (.txt)").clicked() {
+                let ascii = self.canvas.text_with_selection().render();
+                self.file_error = Self::save_file("diagram.txt", ascii.as_bytes()).err();
+                ui.close_menu();
+            }
+            if ui.button("SVG (.svg)").clicked() {
+                let svg = self.export_svg(ui);
+                self.file_error = Self::save_file("diagram.svg", svg.as_bytes()).err();
+                ui.close_menu();
+            }
+        });
+        #[cfg(target_arch = "wasm32")]
+        if let Some(text) = self.pending_file.lock().unwrap().take() {
+            self.file_error = self.load_text(&text).err();
+        }
+        if let Some(err) = &self.file_error {
+            ui.colored_label(ui.visuals().error_fg_color, err);
+        }
+    }
     fn preview_control_panel(&mut self, ui: &mut Ui) {
         ui.horizontal(|ui| {
             global_theme_preference_switch(ui);
-            ui.add(Checkbox::new(&mut self.rough_mode, "Rough Sketch"));
+            ui.add(Checkbox::new(&mut self.canvas.rough_mode, "Rough Sketch"));
+            ui.add(
+                DragValue::new(&mut self.cell_aspect)
+                    .range(CELL_ASPECT_RANGE)
+                    .speed(0.01)
+                    .fixed_decimals(2)
+                    .prefix("cell w:h "),
+            )
+            .on_hover_text("Width of a cell relative to its height");
             if ui
                 .button("SVG 📋")
                 .on_hover_text("Copy raw SVG to clipboard")
                 .clicked()
             {
-                let text = self.text.shrink_to_fit();
-                let job = RenderJob {
-                    width: text.size().num_cols as f32 * 10.0,
-                    height: text.size().num_rows as f32 * 15.0,
-                    text,
-                    options: self.roughr_options(),
-                    x0: 0.0,
-                    y0: 0.0,
-                };
-                let text_color = ui.visuals().strong_text_color().to_hex();
-                let background_color = ui.visuals().extreme_bg_color.to_hex();
-                let svg = badascii::svg::render(&job, &text_color, &background_color);
+                let svg = self.export_svg(ui);
                 ui.output_mut(|o| o.commands.push(egui::OutputCommand::CopyText(svg)))
             }
             if ui
@@ -576,38 +440,45 @@ impl MyApp {
                 .on_hover_text("Copy image to clipboard")
                 .clicked()
             {
-                let text = self.text.shrink_to_fit();
-                let job = RenderJob {
-                    width: text.size().num_cols as f32 * 10.0,
-                    height: text.size().num_rows as f32 * 15.0,
-                    text,
-                    options: self.roughr_options(),
-                    x0: 0.0,
-                    y0: 0.0,
-                };
-                let text_color = ui.visuals().strong_text_color().to_hex();
-                let background_color = ui.visuals().extreme_bg_color.to_hex();
-                if let Ok(img) = badascii::bitmap::render(&job, &text_color, &background_color) {
-                    let shape = img.shape();
-                    let mut egui_image =
-                        ColorImage::new([shape.width, shape.height], Color32::TRANSPARENT);
-                    let data = img.data();
-                    let shape = img.shape();
-                    for row in 0..shape.height {
-                        for col in 0..shape.width {
-                            let color = &data[shape.offset(row, col)];
-                            egui_image.pixels[row * shape.width + col] =
-                                Color32::from_rgba_premultiplied(
-                                    (color.red() * 255.0) as u8,
-                                    (color.green() * 255.0) as u8,
-                                    (color.blue() * 255.0) as u8,
-                                    (color.alpha() * 255.0) as u8,
-                                );
+                match self.export_image(ui) {
+                    Ok(img) => {
+                        let shape = img.shape();
+                        let mut egui_image =
+                            ColorImage::new([shape.width, shape.height], Color32::TRANSPARENT);
+                        let data = img.data();
+                        for row in 0..shape.height {
+                            for col in 0..shape.width {
+                                let color = &data[shape.offset(row, col)];
+                                egui_image.pixels[row * shape.width + col] =
+                                    Color32::from_rgba_premultiplied(
+                                        (color.red() * 255.0) as u8,
+                                        (color.green() * 255.0) as u8,
+                                        (color.blue() * 255.0) as u8,
+                                        (color.alpha() * 255.0) as u8,
+                                    );
+                            }
                         }
+                        ui.output_mut(|o| {
+                            o.commands.push(egui::OutputCommand::CopyImage(egui_image))
+                        });
+                        self.export_error = None;
                     }
-                    ui.output_mut(|o| o.commands.push(egui::OutputCommand::CopyImage(egui_image)))
+                    Err(err) => self.export_error = Some(err),
                 }
             }
+            if ui
+                .button("Save PNG")
+                .on_hover_text("Save the image as a PNG file")
+                .clicked()
+            {
+                self.export_error = self
+                    .export_png(ui)
+                    .and_then(|png| Self::save_file("diagram.png", &png))
+                    .err();
+            }
+            if let Some(err) = &self.export_error {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+            }
         });
     }
     fn resize_panel(&mut self, ui: &mut Ui) {
@@ -646,9 +517,7 @@ impl MyApp {
             });
             if should_close {
                 if should_apply {
-                    self.num_cols = resize.num_cols;
-                    self.num_rows = resize.num_rows;
-                    self.text = self.text.resize(resize);
+                    self.canvas.set_text(self.canvas.text().resize(resize));
                 }
                 self.resize = None;
             } else {
@@ -656,240 +525,49 @@ impl MyApp {
             }
         };
     }
-    fn draw_grid(&mut self, canvas: &Rect, painter: &Painter, grid_color: Color32) {
-        let delta_x = canvas.width() / self.num_cols as f32;
-        let delta_y = canvas.height() / self.num_rows as f32;
-        let top_left = canvas.left_top();
-        for column in 0..=self.num_cols {
-            let col_x = column as f32 * delta_x;
-            let p0 = top_left + vec2(col_x, 0.0);
-            let p1 = top_left + vec2(col_x, canvas.height());
-            painter.line(vec![p0, p1], PathStroke::new(1.0, grid_color));
-        }
-        for row in 0..=self.num_rows {
-            let row_y = row as f32 * delta_y;
-            let p0 = top_left + vec2(0.0, row_y);
-            let p1 = top_left + vec2(canvas.width(), row_y);
-            painter.line(vec![p0, p1], PathStroke::new(1.0, grid_color));
-        }
-    }
-    fn draw_text_buffer(&mut self, canvas: &Rect, painter: &Painter, text_color: Color32) {
-        let delta_x = canvas.width() / self.num_cols as f32;
-        let delta_y = canvas.height() / self.num_rows as f32;
-        let text_size = delta_x.min(delta_y) * TEXT_SCALE_FACTOR;
-        let monospace = FontId::monospace(text_size);
-        for (coord, ch) in self.text.iter() {
-            let center = self.map_text_coordinate_to_cell_center(canvas, &coord);
-            painter.text(
-                center,
-                Align2::CENTER_CENTER,
-                ch,
-                monospace.clone(),
-                text_color,
-            );
-        }
-    }
-    fn roughr_options(&self) -> roughr::core::Options {
-        if self.rough_mode {
-            roughr::core::Options::default()
-        } else {
-            roughr::core::Options {
-                disable_multi_stroke: Some(true),
-                max_randomness_offset: Some(0.0),
-                roughness: Some(0.0),
-                ..Default::default()
-            }
-        }
-    }
     fn draw_rendered_schematic(&mut self, canvas: &Rect, painter: &Painter, color: Color32) {
         self.canvas_size = canvas.size();
-        let top_left = canvas.left_top();
-        let mut text = self.text.clone();
-        if let Tool::Selected(_rect) = &self.tool {
-            for (pos, c) in self.selected_text.iter() {
-                text.set_text(&pos, Some(c))
-            }
-        }
-        let job = RenderJob {
-            width: canvas.width(),
-            height: canvas.height(),
-            text,
-            options: self.roughr_options(),
-            x0: top_left.x,
-            y0: top_left.y,
-        };
+        let text = self.canvas.text_with_selection();
+        let Size { num_cols, num_rows } = text.size();
+        let job = canvas_job(canvas, text, self.canvas.rough_mode);
         let (tb, ops) = job.invoke();
         for op in ops {
             stroke_opset(op, painter, color);
         }
-        let delta_x = canvas.width() / self.num_cols as f32;
-        let delta_y = canvas.height() / self.num_rows as f32;
+        let delta_x = canvas.width() / num_cols as f32;
+        let delta_y = canvas.height() / num_rows as f32;
         let text_size = delta_x.min(delta_y) * TEXT_SCALE_FACTOR;
         let monospace = FontId::monospace(text_size);
         for (coord, ch) in tb.iter() {
-            let center = self.map_text_coordinate_to_cell_center(canvas, &coord);
+            let center = canvas.left_top()
+                + vec2(
+                    delta_x * (coord.x as f32 + 0.5),
+                    delta_y * (coord.y as f32 + 0.5),
+                );
             painter.text(center, Align2::CENTER_CENTER, ch, monospace.clone(), color);
         }
     }
-    fn show_hover(&mut self, canvas: &Rect, pos: Pos2, painter: &Painter) {
-        let top_left = canvas.left_top();
-        let delta_x = canvas.width() / self.num_cols as f32;
-        let delta_y = canvas.height() / self.num_rows as f32;
-        if let Some(text_coordinate) = self.map_pos_to_coords(canvas, pos) {
-            let col = text_coordinate.x as f32;
-            let row = text_coordinate.y as f32;
-            let top_left_corner = top_left + vec2(col * delta_x, row * delta_y);
-            let bottom_right_corner = top_left_corner + vec2(delta_x, delta_y);
-            painter.rect_filled(
-                Rect::from_two_pos(top_left_corner, bottom_right_corner),
-                1.0,
-                Color32::LIGHT_BLUE.linear_multiply(0.3),
-            );
-            self.on_hover(Some(text_coordinate));
+    fn draw_ascii_widget(&mut self, ui: &mut Ui) {
+        // The canvas ignores keys while the resize dialog is open.
+        let resp = ui
+            .add_enabled_ui(self.resize.is_none(), |ui| self.canvas.show(ui))
+            .inner;
+        if resp.dragged_by(egui::PointerButton::Secondary) {
+            self.drag_delta = Some(resp.drag_delta());
         } else {
-            self.on_hover(None);
+            self.drag_delta = None;
         }
-    }
-    fn on_handle_interaction(
-        &mut self,
-        resp: &Response,
-        canvas: &Rect,
-        pos: Pos2,
-        painter: &Painter,
-    ) {
-        if let Some(text_coordinate) = self.map_pos_to_coords(canvas, pos) {
-            if resp.drag_started() {
-                self.on_drag_start(text_coordinate, resp);
-            } else if resp.dragged() {
-                self.on_drag(text_coordinate, canvas, painter);
-            } else if resp.drag_stopped() {
-                self.on_drag_stop(text_coordinate);
-            } else if resp.clicked() {
-                self.on_click(text_coordinate);
-            }
-            if resp.dragged_by(egui::PointerButton::Secondary) {
-                self.drag_delta = Some(resp.drag_delta());
-            } else {
-                self.drag_delta = None;
-            }
+        if resp.double_clicked() {
+            self.reset_zoom = true;
         }
     }
-    fn tool_specific_drawing(&self, canvas: &Rect, painter: &Painter) {
-        let delta_x = canvas.width() / self.num_cols as f32;
-        let delta_y = canvas.height() / self.num_rows as f32;
-        let text_size = delta_x.min(delta_y) * TEXT_SCALE_FACTOR;
-        let monospace = FontId::monospace(text_size);
-        match self.tool {
-            Tool::Text(Some(TextState { origin: _, cursor })) => {
-                let center = self.map_text_coordinate_to_cell_center(canvas, &cursor);
-                let rect = Rect::from_center_size(center, vec2(delta_x, delta_y));
-                painter.rect_stroke(
-                    rect,
-                    0.5,
-                    (1.0, Color32::LIGHT_YELLOW),
-                    egui::StrokeKind::Middle,
-                );
-            }
-            Tool::Selected(selection_box) => {
-                for (coord, ch) in self.selected_text.iter() {
-                    if selection_box.contains(&coord) {
-                        let center = self.map_text_coordinate_to_cell_center(canvas, &coord);
-                        painter.text(
-                            center,
-                            Align2::CENTER_CENTER,
-                            ch,
-                            monospace.clone(),
-                            Color32::GREEN.linear_multiply(0.5),
-                        );
-                    }
-                }
-            }
-            Tool::MovingText(MoveState {
-                selection,
-                origin,
-                move_pos,
-            }) => {
-                let bbox_shifted = selection.shifted(origin, move_pos);
-                for (coord, ch) in self.selected_text.iter() {
-                    let coord = coord.shifted(origin, move_pos);
-                    if bbox_shifted.contains(&coord) {
-                        let center = self.map_text_coordinate_to_cell_center(canvas, &coord);
-                        painter.text(
-                            center,
-                            Align2::CENTER_CENTER,
-                            ch,
-                            monospace.clone(),
-                            Color32::GREEN,
-                        );
-                    }
-                }
-            }
-            _ => {}
-        }
-    }
-    fn process_actions(&mut self, ui: &mut Ui) {
-        if let Some(action) = ui.input(|i| {
-            i.events.iter().find_map(|x| match x {
-                Event::Text(string) => {
-                    if string.len() == 1 {
-                        Some(Action::Char(string.chars().nth(0).unwrap()))
-                    } else {
-                        None
-                    }
-                }
-                Event::Key {
-                    key,
-                    pressed: true,
-                    modifiers,
-                    ..
-                } => map_key(key, modifiers),
-                Event::Paste(string) => Some(Action::Paste(string.clone())),
-                Event::Copy => Some(Action::Copy),
-                _ => None,
-            })
-        }) {
-            self.on_action(action);
-        }
-    }
-    fn draw_ascii_widget(&mut self, ui: &mut Ui) {
-        egui::Frame::canvas(ui.style()).show(ui, |ui| {
-            let desired_size = ui.available_size();
-            let (resp, painter) = ui.allocate_painter(desired_size, Sense::click_and_drag());
-            let canvas = resp.rect;
-            let text_color = ui.style().visuals.strong_text_color();
-            let grid_color = ui.style().visuals.code_bg_color;
-            self.draw_grid(&canvas, &painter, grid_color);
-            self.draw_text_buffer(&canvas, &painter, text_color);
-            if let Some(pos) = resp.hover_pos() {
-                self.show_hover(&canvas, pos, &painter);
-                match &self.tool {
-                    Tool::Text(_) => {
-                        ui.ctx().set_cursor_icon(CursorIcon::Text);
-                    }
-                    Tool::Selected(..) => {
-                        ui.ctx().set_cursor_icon(CursorIcon::Grab);
-                    }
-                    Tool::MovingText(..) => {
-                        ui.ctx().set_cursor_icon(CursorIcon::Grabbing);
-                    }
-                    _ => {
-                        ui.ctx().set_cursor_icon(CursorIcon::Default);
-                    }
-                }
-            }
-            if let Some(pos) = resp.interact_pointer_pos() {
-                self.on_handle_interaction(&resp, &canvas, pos, &painter);
-            }
-            self.process_actions(ui);
-            self.tool_specific_drawing(&canvas, &painter);
-            if resp.double_clicked() {
-                self.reset_zoom = true;
-            }
-        });
-    }
     fn draw_preview_widget(&mut self, ui: &mut Ui) {
         egui::Frame::canvas(ui.style()).show(ui, |ui| {
-            let desired_size = ui.available_size();
+            let desired_size = fit_cells(
+                ui.available_size(),
+                self.canvas.text().size(),
+                self.cell_aspect,
+            );
             let (resp, painter) = ui.allocate_painter(desired_size, Sense::click_and_drag());
             let canvas = resp.rect;
             let text_color = ui.style().visuals.strong_text_color();
@@ -927,6 +605,7 @@ impl TabViewer for MyApp {
         match tab {
             Tab::Ascii => {
                 self.ascii_control_panel(ui);
+                self.find_panel(ui);
                 scene.show(ui, &mut scene_rect, |ui| {
                     self.draw_ascii_widget(ui);
                 });
@@ -964,10 +643,70 @@ impl eframe::App for MyApp {
                     .show_leaf_collapse_buttons(false)
                     .show_inside(ui, self);
                 self.dock_state = dockstate;
-                if let Some(txt) = std::mem::take(&mut self.copy_buffer) {
-                    ctx.copy_text(txt);
-                }
             })
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_text_grows_canvas() {
+        let mut app = MyApp::default();
+        let before = app.canvas.text().render();
+        let wide = format!("+{}+", "-".repeat(148));
+        app.load_text(&wide).unwrap();
+        assert_eq!(
+            app.canvas.text().size(),
+            Size {
+                num_cols: 150,
+                num_rows: 40
+            }
+        );
+        assert_eq!(app.canvas.text().render(), format!("{wide}\n"));
+        let huge = "x\n".repeat(MAX_CANVAS_SIZE as usize + 1);
+        assert!(app.load_text(&huge).is_err());
+        // One long line is refused too, without allocating rows for it
+        let long = format!("{}\n{}", "x".repeat(1 << 20), "\n".repeat(1 << 20));
+        assert!(app.load_text(&long).is_err());
+        assert_eq!(app.canvas.text().render(), format!("{wide}\n"));
+        // Opening a file can be undone
+        app.canvas.undo();
+        assert_eq!(app.canvas.text().render(), before);
+    }
+
+    #[test]
+    fn test_export_includes_selection() {
+        let mut app = MyApp::default();
+        app.canvas.set_text(TextBuffer::new(10, 20));
+        // A template floats as a selection until it is dropped
+        app.canvas.insert_template("+--+\n|  |\n+--+");
+        assert!(app.canvas.text().iter().next().is_none());
+        let job = app.export_job();
+        assert_eq!(job.text.render(), "+--+\n|  |\n+--+\n");
+        let svg = badascii::svg::render(&job, "black", "white");
+        assert!(svg.contains("<path"));
+    }
+
+    #[test]
+    fn test_cell_aspect() {
+        let size = Size::new(10, 20);
+        assert_eq!(fit_cells(vec2(400.0, 400.0), size, 0.5), vec2(400.0, 400.0));
+        assert_eq!(fit_cells(vec2(400.0, 400.0), size, 1.0), vec2(400.0, 200.0));
+        assert_eq!(
+            fit_cells(vec2(100.0, 400.0), size, 0.25),
+            vec2(100.0, 200.0)
+        );
+        // The export matches the preview
+        let mut app = MyApp::default();
+        app.canvas
+            .set_text(TextBuffer::with_text("+--+\n|  |\n+--+"));
+        let job = app.export_job();
+        assert_eq!((job.width, job.height), (40.0, 45.0));
+        app.cell_aspect = 1.0;
+        let job = app.export_job();
+        assert_eq!((job.width, job.height), (60.0, 45.0));
+    }
+}
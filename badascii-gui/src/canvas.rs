@@ -0,0 +1,1952 @@
+use std::collections::VecDeque;
+
+use badascii::{
+    RenderJob, Size, TextBuffer,
+    analyze::{Side, get_rectangles},
+    rect::Rectangle,
+    tc::TextCoordinate,
+};
+use egui::{
+    Align2, Color32, CursorIcon, Event, FontId, Key, Modifiers, Painter, Pos2, Rect, Response,
+    Sense, Ui, epaint::PathStroke, util::hash, vec2,
+};
+use roughr::core::Drawable;
+use unicode_width::UnicodeWidthChar;
+
+use crate::{action::Action, roughr_egui::stroke_opset};
+
+pub(crate) const TEXT_SCALE_FACTOR: f32 = 1.5;
+
+// How far (in columns and rows) each duplicate is placed from the
+// original.
+const DUPLICATE_STEP: (u32, u32) = (2, 1);
+
+/// The largest canvas (in either direction) that is loaded from a
+/// URL or a file, or that pasting and typing will grow to.
+pub(crate) const MAX_CANVAS_SIZE: u32 = 1024;
+
+/// The number of undo steps kept by default.
+pub const DEFAULT_MAX_UNDO_DEPTH: usize = 100;
+
+#[derive(Copy, Clone, Debug)]
+struct TextState {
+    origin: TextCoordinate,
+    cursor: TextCoordinate,
+}
+
+// The regions of text lifted off the canvas, which move together.
+// Holding shift while dragging out a region adds it to the rest.
+#[derive(Clone, Debug, PartialEq)]
+struct Selection(Vec<Rectangle>);
+
+impl From<Rectangle> for Selection {
+    fn from(rect: Rectangle) -> Self {
+        Selection(vec![rect])
+    }
+}
+
+impl Selection {
+    fn contains(&self, pos: &TextCoordinate) -> bool {
+        self.0.iter().any(|rect| rect.contains(pos))
+    }
+    fn shifted(&self, origin: TextCoordinate, move_pos: TextCoordinate) -> Self {
+        Selection(
+            self.0
+                .iter()
+                .map(|rect| rect.shifted(origin, move_pos))
+                .collect(),
+        )
+    }
+    fn with(&self, rect: Rectangle) -> Self {
+        let mut rects = self.0.clone();
+        rects.push(rect);
+        Selection(rects)
+    }
+    // The smallest rectangle around all of the regions.
+    fn bounding_box(&self) -> Rectangle {
+        let left_top = TextCoordinate {
+            x: self
+                .0
+                .iter()
+                .map(|rect| rect.left())
+                .min()
+                .unwrap_or_default(),
+            y: self
+                .0
+                .iter()
+                .map(|rect| rect.top())
+                .min()
+                .unwrap_or_default(),
+        };
+        let right_bottom = TextCoordinate {
+            x: self
+                .0
+                .iter()
+                .map(|rect| rect.right_bottom().x)
+                .max()
+                .unwrap_or_default(),
+            y: self
+                .0
+                .iter()
+                .map(|rect| rect.right_bottom().y)
+                .max()
+                .unwrap_or_default(),
+        };
+        Rectangle::new(left_top, right_bottom)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct MoveState {
+    selection: Selection,
+    origin: TextCoordinate,
+    move_pos: TextCoordinate,
+}
+
+#[derive(Clone, Debug)]
+struct AddState {
+    selection: Selection,
+    corner: TextCoordinate,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct LineState {
+    start: TextCoordinate,
+    end: TextCoordinate,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum PinDirection {
+    In,
+    Out,
+}
+
+// A port on the side of a box, `offset` cells along that side from
+// its top or left corner.
+#[derive(Clone, Debug, PartialEq)]
+struct Pin {
+    name: String,
+    side: Side,
+    direction: PinDirection,
+    offset: u32,
+}
+
+#[derive(Clone, Debug)]
+struct PinState {
+    rect: Rectangle,
+    pin: Pin,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FindReplace {
+    pub(crate) needle: String,
+    pub(crate) replacement: String,
+}
+
+#[derive(Clone, Debug)]
+enum Tool {
+    Selection(Option<TextCoordinate>),
+    Text(Option<TextState>),
+    Selected(Selection),
+    AddingSelection(AddState),
+    MovingText(MoveState),
+    Line(Option<LineState>),
+    Rect(Option<Rectangle>),
+    Pin(Option<PinState>),
+}
+
+// Route an orthogonal wire from `start` to `end`, going
+// horizontally first and then vertically.  Returns the
+// characters to write, with a `+` at each end and at the bend.
+fn connect(start: TextCoordinate, end: TextCoordinate) -> Vec<(TextCoordinate, char)> {
+    if start == end {
+        return vec![];
+    }
+    let corner = TextCoordinate {
+        x: end.x,
+        y: start.y,
+    };
+    let horiz = (start.x.min(end.x)..=start.x.max(end.x)).map(|x| {
+        let ch = if x == start.x || x == end.x { '+' } else { '-' };
+        (TextCoordinate { x, y: start.y }, ch)
+    });
+    let vert = (start.y.min(end.y)..=start.y.max(end.y)).map(|y| {
+        let ch = if y == start.y || y == end.y { '+' } else { '|' };
+        (TextCoordinate { x: end.x, y }, ch)
+    });
+    let mut route: Vec<_> = horiz.collect();
+    route.extend(vert.filter(|(pos, _)| *pos != corner));
+    route
+}
+
+// The border of a box filling `rect`, with a `+` at each corner.
+// Drags less than two cells across in either direction do not make
+// a box, and give no characters.
+fn outline(rect: Rectangle) -> Vec<(TextCoordinate, char)> {
+    if rect.width() < 2 || rect.height() < 2 {
+        return vec![];
+    }
+    let Rectangle {
+        corner_1: left_top,
+        corner_2: right_bottom,
+    } = rect.normalize();
+    rect.iter_interior()
+        .filter_map(|pos| {
+            let side = pos.x == left_top.x || pos.x == right_bottom.x;
+            let end = pos.y == left_top.y || pos.y == right_bottom.y;
+            match (side, end) {
+                (true, true) => Some((pos, '+')),
+                (true, false) => Some((pos, '|')),
+                (false, true) => Some((pos, '-')),
+                (false, false) => None,
+            }
+        })
+        .collect()
+}
+
+// The box in `text` with `pos` on one of its sides (but not at a
+// corner), along with that side and how far along it `pos` is.
+fn pin_site(text: &TextBuffer, pos: TextCoordinate) -> Option<(Rectangle, Side, u32)> {
+    get_rectangles(text).into_iter().find_map(|rect| {
+        let Rectangle {
+            corner_1: left_top,
+            corner_2: right_bottom,
+        } = rect.normalize();
+        let inside_x = pos.x > left_top.x && pos.x < right_bottom.x;
+        let inside_y = pos.y > left_top.y && pos.y < right_bottom.y;
+        let side = if inside_y && pos.x == left_top.x {
+            Side::Left
+        } else if inside_y && pos.x == right_bottom.x {
+            Side::Right
+        } else if inside_x && pos.y == left_top.y {
+            Side::Top
+        } else if inside_x && pos.y == right_bottom.y {
+            Side::Bottom
+        } else {
+            return None;
+        };
+        let offset = match side {
+            Side::Left | Side::Right => pos.y - left_top.y,
+            Side::Top | Side::Bottom => pos.x - left_top.x,
+        };
+        Some((rect, side, offset))
+    })
+}
+
+// The characters that draw `pin` on the side of `rect`: a short
+// wire outside the box with an arrow showing which way the signal
+// flows, and the name just inside the box.  An output starts its
+// wire with a `+` on the border, while an input starts from a `+`
+// out past the arrow, so both read as wires.  Cells that would fall
+// off the left or top of the canvas are left out.
+fn pin_cells(rect: Rectangle, pin: &Pin) -> Vec<(TextCoordinate, char)> {
+    let Rectangle {
+        corner_1: left_top,
+        corner_2: right_bottom,
+    } = rect.normalize();
+    let (x, y) = match pin.side {
+        Side::Left => (left_top.x, left_top.y + pin.offset),
+        Side::Right => (right_bottom.x, left_top.y + pin.offset),
+        Side::Top => (left_top.x + pin.offset, left_top.y),
+        Side::Bottom => (left_top.x + pin.offset, right_bottom.y),
+    };
+    let (dx, dy, run, line) = match pin.side {
+        Side::Left => (-1, 0, 3, '-'),
+        Side::Right => (1, 0, 3, '-'),
+        Side::Top => (0, -1, 2, '|'),
+        Side::Bottom => (0, 1, 2, '|'),
+    };
+    let arrow = |dx: i64, dy: i64| match (dx, dy) {
+        (1, _) => '>',
+        (-1, _) => '<',
+        (_, 1) => 'v',
+        _ => '^',
+    };
+    let at = |step: i64| {
+        let (x, y) = (x as i64 + dx * step, y as i64 + dy * step);
+        (x >= 0 && y >= 0).then_some(TextCoordinate {
+            x: x as u32,
+            y: y as u32,
+        })
+    };
+    let mut cells = vec![];
+    for step in 0..=run {
+        let ch = match (pin.direction, step) {
+            (PinDirection::In, 0) => continue,
+            (PinDirection::In, 1) => arrow(-dx, -dy),
+            (PinDirection::In, _) if step == run => '+',
+            (PinDirection::Out, 0) => '+',
+            (PinDirection::Out, _) if step == run => arrow(dx, dy),
+            _ => line,
+        };
+        cells.extend(at(step).map(|pos| (pos, ch)));
+    }
+    // The name is cut short rather than run over the far side
+    let (label_x, label_y, room) = match pin.side {
+        Side::Left => (x + 1, y, right_bottom.x.saturating_sub(x + 1)),
+        Side::Right => {
+            let len = (pin.name.chars().count() as u32).min(x.saturating_sub(left_top.x + 1));
+            (x - len, y, len)
+        }
+        Side::Top => (x, y + 1, right_bottom.x - x),
+        Side::Bottom => (x, y - 1, right_bottom.x - x),
+    };
+    cells.extend(
+        pin.name
+            .chars()
+            .take(room as usize)
+            .zip(label_x..)
+            .map(|(ch, x)| (TextCoordinate { x, y: label_y }, ch)),
+    );
+    cells
+}
+
+// True for glyphs that take up two cells when drawn, like CJK
+// ideographs.
+fn is_wide(ch: char) -> bool {
+    ch.width() == Some(2)
+}
+
+fn map_key(key: &Key, modifiers: &Modifiers) -> Option<Action> {
+    match key {
+        Key::Backspace => Some(Action::Backspace),
+        Key::ArrowUp if modifiers.shift_only() => Some(Action::UpControlArrow),
+        Key::ArrowDown if modifiers.shift_only() => Some(Action::DownControlArrow),
+        Key::ArrowLeft if modifiers.shift_only() => Some(Action::LeftControlArrow),
+        Key::ArrowRight if modifiers.shift_only() => Some(Action::RightControlArrow),
+        Key::ArrowUp if !modifiers.any() => Some(Action::UpArrow),
+        Key::ArrowDown if !modifiers.any() => Some(Action::DownArrow),
+        Key::ArrowLeft if !modifiers.any() => Some(Action::LeftArrow),
+        Key::ArrowRight if !modifiers.any() => Some(Action::RightArrow),
+        Key::Escape => Some(Action::Escape),
+        Key::Enter => Some(Action::Enter),
+        Key::Copy => Some(Action::Copy),
+        Key::Z if modifiers.command && modifiers.shift => Some(Action::Redo),
+        Key::Z if modifiers.command => Some(Action::Undo),
+        Key::Y if modifiers.command => Some(Action::Redo),
+        Key::A if modifiers.command => Some(Action::SelectAll),
+        Key::D if modifiers.command => Some(Action::Duplicate),
+        _ => None,
+    }
+}
+
+impl Tool {
+    // The tool as it was before any drag in progress began, since
+    // the drag is over by the time an edit is undone.
+    fn at_rest(&self) -> Tool {
+        match self {
+            Tool::Selection(_) => Tool::Selection(None),
+            Tool::MovingText(MoveState { selection, .. })
+            | Tool::AddingSelection(AddState { selection, .. }) => {
+                Tool::Selected(selection.clone())
+            }
+            Tool::Line(_) => Tool::Line(None),
+            Tool::Rect(_) => Tool::Rect(None),
+            Tool::Pin(_) => Tool::Pin(None),
+            tool => tool.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Snapshot {
+    text: TextBuffer,
+    selected_text: TextBuffer,
+    tool: Tool,
+}
+
+impl Snapshot {
+    // The text as it is shown, with the selection dropped in place.
+    fn combined(&self) -> TextBuffer {
+        let mut text = self.text.clone();
+        for (pos, c) in self.selected_text.iter() {
+            text.set_text(&pos, Some(c))
+        }
+        text
+    }
+}
+
+/// The ASCII editing canvas, with its tools and undo history, as a
+/// widget that can be embedded in any egui app.
+pub struct AsciiCanvas {
+    num_rows: u32,
+    num_cols: u32,
+    tool: Tool,
+    snapshots: VecDeque<Snapshot>,
+    futures: Vec<Snapshot>,
+    selected_text: TextBuffer,
+    text: TextBuffer,
+    copy_buffer: Option<String>,
+    hover_pos: Option<TextCoordinate>,
+    prev_action: Option<Action>,
+    max_undo_depth: usize,
+    pub(crate) show_grid: bool,
+    pub(crate) grid_spacing: u32,
+    pub(crate) show_wire_ends: bool,
+    pub(crate) rough_mode: bool,
+    pub(crate) find: Option<FindReplace>,
+}
+
+impl Default for AsciiCanvas {
+    fn default() -> Self {
+        Self::new(40, 100)
+    }
+}
+
+pub(crate) fn roughr_options(rough_mode: bool) -> roughr::core::Options {
+    if rough_mode {
+        roughr::core::Options::default()
+    } else {
+        roughr::core::Options {
+            disable_multi_stroke: Some(true),
+            max_randomness_offset: Some(0.0),
+            roughness: Some(0.0),
+            ..Default::default()
+        }
+    }
+}
+
+// A job that draws `text` cell for cell over the canvas.
+pub(crate) fn canvas_job(canvas: &Rect, text: TextBuffer, rough_mode: bool) -> RenderJob {
+    let top_left = canvas.left_top();
+    RenderJob {
+        options: roughr_options(rough_mode),
+        x0: top_left.x,
+        y0: top_left.y,
+        ..RenderJob::new(text, canvas.width(), canvas.height())
+    }
+}
+
+impl AsciiCanvas {
+    /// A blank canvas of the given size.
+    pub fn new(num_rows: u32, num_cols: u32) -> Self {
+        Self {
+            num_rows,
+            num_cols,
+            tool: Tool::Selection(None),
+            snapshots: VecDeque::with_capacity(DEFAULT_MAX_UNDO_DEPTH),
+            futures: Vec::new(),
+            selected_text: TextBuffer::new(num_rows, num_cols),
+            text: TextBuffer::new(num_rows, num_cols),
+            copy_buffer: None,
+            hover_pos: None,
+            prev_action: None,
+            max_undo_depth: DEFAULT_MAX_UNDO_DEPTH,
+            show_grid: true,
+            grid_spacing: 1,
+            show_wire_ends: false,
+            rough_mode: true,
+            find: None,
+        }
+    }
+    /// Set the number of undo steps to keep (at least one).  Each
+    /// step holds a copy of the canvas, so long editing sessions
+    /// can trade memory for a deeper history.
+    pub fn with_max_undo_depth(mut self, depth: usize) -> Self {
+        self.max_undo_depth = depth.max(1);
+        self
+    }
+    /// The text on the canvas, not including a selection that is
+    /// still being moved.
+    pub fn text(&self) -> &TextBuffer {
+        &self.text
+    }
+    /// Mutable access to the text on the canvas.  Changes made
+    /// through here are not recorded in the undo history.
+    pub fn text_mut(&mut self) -> &mut TextBuffer {
+        &mut self.text
+    }
+    /// Replace the text on the canvas, which takes on the size of
+    /// `text`.  Any selection is dropped, and the undo history is
+    /// left as is.
+    pub fn set_text(&mut self, text: TextBuffer) {
+        let Size { num_rows, num_cols } = text.size();
+        self.num_rows = num_rows;
+        self.num_cols = num_cols;
+        self.selected_text = TextBuffer::new(num_rows, num_cols);
+        self.text = text;
+        self.tool = Tool::Selection(None);
+    }
+    /// The text on the canvas, with any selection that is being
+    /// moved placed where it currently sits.
+    pub fn text_with_selection(&self) -> TextBuffer {
+        let mut text = self.text.clone();
+        if let Tool::Selected(_rect) = &self.tool {
+            for (pos, c) in self.selected_text.iter() {
+                text.set_text(&pos, Some(c))
+            }
+        }
+        text
+    }
+    /// True if there is an edit to undo.
+    pub fn can_undo(&self) -> bool {
+        !self.snapshots.is_empty()
+    }
+    /// True if there is an undone edit to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.futures.is_empty()
+    }
+    /// True if dragging on the canvas draws a wire.
+    pub fn line_tool(&self) -> bool {
+        matches!(self.tool, Tool::Line(_))
+    }
+    /// Switch between the line tool, where dragging draws a wire
+    /// with a bend, and the usual selection tool.
+    pub fn set_line_tool(&mut self, on: bool) {
+        self.tool = if on {
+            Tool::Line(None)
+        } else {
+            Tool::Selection(None)
+        };
+    }
+    /// True if dragging on the canvas draws a box.
+    pub fn rect_tool(&self) -> bool {
+        matches!(self.tool, Tool::Rect(_))
+    }
+    /// Switch between the box tool, where dragging from corner to
+    /// corner draws a box, and the usual selection tool.
+    pub fn set_rect_tool(&mut self, on: bool) {
+        self.tool = if on {
+            Tool::Rect(None)
+        } else {
+            Tool::Selection(None)
+        };
+    }
+    /// True if clicking on the side of a box adds a pin to it.
+    pub fn pin_tool(&self) -> bool {
+        matches!(self.tool, Tool::Pin(_))
+    }
+    /// Switch between the pin tool, where clicking on the side of a
+    /// box starts a named input or output there, and the usual
+    /// selection tool.
+    pub fn set_pin_tool(&mut self, on: bool) {
+        self.tool = if on {
+            Tool::Pin(None)
+        } else {
+            Tool::Selection(None)
+        };
+    }
+    fn map_pos_to_coords(&self, canvas: &Rect, pos: Pos2) -> Option<TextCoordinate> {
+        let top_left = canvas.left_top();
+        let delta = pos - top_left;
+        let delta_x = canvas.width() / self.num_cols as f32;
+        let delta_y = canvas.height() / self.num_rows as f32;
+        let col = (delta.x / delta_x).floor() as i32;
+        let row = (delta.y / delta_y).floor() as i32;
+        if (0..(self.num_cols as i32)).contains(&col) && (0..(self.num_rows as i32)).contains(&row)
+        {
+            let pos = TextCoordinate {
+                x: col as u32,
+                y: row as u32,
+            };
+            // A wide glyph spills over into the (empty) cell to its
+            // right, so a click on that half belongs to the glyph.
+            if pos.x > 0
+                && self.text.get(pos).is_none()
+                && self.text.get(pos.left()).is_some_and(is_wide)
+            {
+                Some(pos.left())
+            } else {
+                Some(pos)
+            }
+        } else {
+            None
+        }
+    }
+    fn map_text_coordinate_to_cell_center(&self, canvas: &Rect, coord: &TextCoordinate) -> Pos2 {
+        let left_top = canvas.left_top();
+        let delta_x = canvas.width() / self.num_cols as f32;
+        let delta_y = canvas.height() / self.num_rows as f32;
+        let left_top_corner = left_top + vec2(delta_x * coord.x as f32, delta_y * coord.y as f32);
+        left_top_corner + vec2(delta_x / 2.0, delta_y / 2.0)
+    }
+    fn map_rectangle_to_rect(&self, canvas: &Rect, rect: &Rectangle) -> Rect {
+        let corner_1 = self.map_text_coordinate_to_cell_center(canvas, &rect.corner_1);
+        let corner_2 = self.map_text_coordinate_to_cell_center(canvas, &rect.corner_2);
+        Rect::from_two_pos(corner_1, corner_2)
+    }
+    // Save the current state for undo.  Call this before an edit.
+    pub(crate) fn snapshot(&mut self) {
+        let snapshot = self.current();
+        let text_hash = hash(snapshot.combined());
+        let last_hash = self
+            .snapshots
+            .back()
+            .map(|t| hash(t.combined()))
+            .unwrap_or(!0);
+        if text_hash != last_hash {
+            while self.snapshots.len() >= self.max_undo_depth {
+                self.snapshots.pop_front();
+            }
+            self.snapshots.push_back(snapshot);
+            self.futures.clear();
+        }
+    }
+    fn current(&self) -> Snapshot {
+        Snapshot {
+            text: self.text.clone(),
+            selected_text: self.selected_text.clone(),
+            tool: self.tool.at_rest(),
+        }
+    }
+    fn restore(&mut self, snapshot: Snapshot) {
+        let Size { num_rows, num_cols } = snapshot.text.size();
+        self.num_rows = num_rows;
+        self.num_cols = num_cols;
+        self.text = snapshot.text;
+        self.selected_text = snapshot.selected_text;
+        self.tool = snapshot.tool;
+    }
+    // Enlarge the canvas to at least `rows` by `cols` (but no more
+    // than `MAX_CANVAS_SIZE`), so text typed or pasted past the
+    // edge is kept.
+    fn grow(&mut self, rows: u32, cols: u32) {
+        let (rows, cols) = (rows.min(MAX_CANVAS_SIZE), cols.min(MAX_CANVAS_SIZE));
+        self.text.ensure_capacity(rows, cols);
+        self.selected_text.ensure_capacity(rows, cols);
+        let Size { num_rows, num_cols } = self.text.size();
+        self.num_rows = num_rows;
+        self.num_cols = num_cols;
+    }
+    fn grow_to_paste(&mut self, text: &str, pos: TextCoordinate) {
+        let rows = text.lines().count() as u32;
+        let cols = text
+            .lines()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0) as u32;
+        self.grow(pos.y + rows, pos.x + cols);
+    }
+    fn set_char(&mut self, ch: char, position: &TextCoordinate) {
+        self.grow(position.y + 1, position.x + 1);
+        self.text.set_text(position, Some(ch));
+    }
+    fn clear_char(&mut self, position: &TextCoordinate) {
+        self.text.set_text(position, None);
+    }
+    fn on_drag_start(&mut self, tc: TextCoordinate, resp: &Response) {
+        let adding = resp.ctx.input(|i| i.modifiers.shift);
+        match &self.tool {
+            Tool::Selection(None) if !resp.dragged_by(egui::PointerButton::Secondary) => {
+                self.tool = Tool::Selection(Some(tc));
+            }
+            Tool::Selected(selection) if adding => {
+                self.tool = Tool::AddingSelection(AddState {
+                    selection: selection.clone(),
+                    corner: tc,
+                })
+            }
+            Tool::Selected(selection) => {
+                self.tool = Tool::MovingText(MoveState {
+                    selection: selection.clone(),
+                    origin: tc,
+                    move_pos: tc,
+                })
+            }
+            Tool::Text(_) => self.tool = Tool::Selection(Some(tc)),
+            Tool::Line(_) if !resp.dragged_by(egui::PointerButton::Secondary) => {
+                self.tool = Tool::Line(Some(LineState { start: tc, end: tc }));
+            }
+            Tool::Rect(_) if !resp.dragged_by(egui::PointerButton::Secondary) => {
+                self.tool = Tool::Rect(Some(Rectangle::new(tc, tc)));
+            }
+            _ => (),
+        }
+    }
+    fn on_drag(&mut self, corner2: TextCoordinate, canvas: &Rect, painter: &Painter) {
+        let delta_x = canvas.width() / self.num_cols as f32;
+        let delta_y = canvas.height() / self.num_rows as f32;
+        match &self.tool {
+            Tool::Selection(Some(corner1))
+            | Tool::AddingSelection(AddState {
+                corner: corner1, ..
+            }) => {
+                let selection_box = Rectangle::new(*corner1, corner2);
+                let rect = self.map_rectangle_to_rect(canvas, &selection_box);
+                let rect = rect.expand2(vec2(delta_x / 2.0, delta_y / 2.0));
+                painter.rect_stroke(
+                    rect,
+                    1.0,
+                    (1.0, Color32::LIGHT_GREEN),
+                    egui::StrokeKind::Middle,
+                );
+            }
+            Tool::MovingText(MoveState {
+                selection,
+                origin,
+                move_pos: _,
+            }) => {
+                self.tool = Tool::MovingText(MoveState {
+                    selection: selection.clone(),
+                    origin: *origin,
+                    move_pos: corner2,
+                });
+            }
+            Tool::Line(Some(LineState { start, end: _ })) => {
+                self.tool = Tool::Line(Some(LineState {
+                    start: *start,
+                    end: corner2,
+                }));
+            }
+            Tool::Rect(Some(rect)) => {
+                self.tool = Tool::Rect(Some(Rectangle::new(rect.corner_1, corner2)));
+            }
+            _ => {}
+        }
+    }
+    fn on_drag_stop(&mut self, corner2: TextCoordinate) {
+        match &self.tool {
+            Tool::Selection(Some(corner1)) => {
+                let rect = Rectangle::new(*corner1, corner2);
+                if self.has_text(rect) {
+                    self.snapshot();
+                    self.selected_text.clear_all();
+                    self.lift(rect);
+                    self.tool = Tool::Selected(rect.into());
+                } else {
+                    self.tool = Tool::Selection(None);
+                }
+            }
+            Tool::AddingSelection(AddState { selection, corner }) => {
+                let rect = Rectangle::new(*corner, corner2);
+                if self.has_text(rect) {
+                    let selection = selection.with(rect);
+                    self.snapshot();
+                    self.lift(rect);
+                    self.tool = Tool::Selected(selection);
+                } else {
+                    self.tool = Tool::Selected(selection.clone());
+                }
+            }
+            Tool::MovingText(MoveState {
+                selection,
+                origin,
+                move_pos,
+            }) => {
+                let selection_shifted = selection.shifted(*origin, *move_pos);
+                let (origin, move_pos) = (*origin, *move_pos);
+                self.snapshot();
+                self.shift_selected_text(origin, move_pos);
+                self.tool = Tool::Selected(selection_shifted);
+            }
+            Tool::Line(Some(LineState { start, end: _ })) => {
+                let route = connect(*start, corner2);
+                if !route.is_empty() {
+                    self.snapshot();
+                    for (pos, ch) in route {
+                        self.set_char(ch, &pos);
+                    }
+                }
+                self.tool = Tool::Line(None);
+            }
+            Tool::Rect(Some(rect)) => {
+                let border = outline(Rectangle::new(rect.corner_1, corner2));
+                if !border.is_empty() {
+                    self.snapshot();
+                    for (pos, ch) in border {
+                        self.set_char(ch, &pos);
+                    }
+                }
+                self.tool = Tool::Rect(None);
+            }
+            _ => {}
+        }
+    }
+    fn on_click(&mut self, pos: TextCoordinate) {
+        match &self.tool {
+            Tool::Text(_) => {
+                self.snapshot();
+                self.tool = Tool::Text(Some(TextState {
+                    origin: pos,
+                    cursor: pos,
+                }))
+            }
+            Tool::Selected(_) => {
+                self.snapshot();
+                self.drop_selection();
+            }
+            Tool::Selection(None) => {
+                self.tool = Tool::Text(Some(TextState {
+                    origin: pos,
+                    cursor: pos,
+                }));
+            }
+            Tool::Pin(_) => {
+                if let Some((rect, side, offset)) = pin_site(&self.text, pos) {
+                    self.tool = Tool::Pin(Some(PinState {
+                        rect,
+                        pin: Pin {
+                            name: String::new(),
+                            side,
+                            direction: PinDirection::Out,
+                            offset,
+                        },
+                    }));
+                }
+            }
+            _ => {}
+        }
+    }
+    // Keys typed while a pin is being placed: the name is typed in,
+    // the arrow keys flip it between an input and an output, and
+    // Enter writes it onto the canvas.
+    fn on_action_with_pin(&mut self, mut state: PinState, action: Action) {
+        match action {
+            Action::Char(ch) => state.pin.name.push(ch),
+            Action::Backspace => {
+                state.pin.name.pop();
+            }
+            Action::UpArrow | Action::DownArrow | Action::LeftArrow | Action::RightArrow => {
+                state.pin.direction = match state.pin.direction {
+                    PinDirection::In => PinDirection::Out,
+                    PinDirection::Out => PinDirection::In,
+                };
+            }
+            Action::Enter => {
+                self.snapshot();
+                for (pos, ch) in pin_cells(state.rect, &state.pin) {
+                    self.set_char(ch, &pos);
+                }
+                self.tool = Tool::Pin(None);
+                return;
+            }
+            Action::Escape => {
+                self.tool = Tool::Pin(None);
+                return;
+            }
+            _ => {}
+        }
+        self.tool = Tool::Pin(Some(state));
+    }
+    fn on_action_with_text(&mut self, text_state: TextState, action: Action) {
+        let TextState { cursor, origin } = text_state;
+        match action.clone() {
+            Action::Paste(txt) => {
+                self.grow_to_paste(&txt, cursor);
+                self.text.paste(&txt, cursor);
+            }
+            Action::Backspace => {
+                self.clear_char(&cursor);
+                self.tool = Tool::Text(Some(TextState {
+                    origin,
+                    cursor: cursor.left(),
+                }));
+            }
+            Action::Char(ch) => {
+                self.set_char(ch, &cursor);
+                self.tool = Tool::Text(Some(TextState {
+                    origin,
+                    cursor: cursor.right(),
+                }));
+            }
+            Action::RightControlArrow => {
+                let char = if (self.prev_action != Some(Action::RightControlArrow))
+                    && (self.prev_action != Some(Action::LeftControlArrow))
+                {
+                    '+'
+                } else {
+                    '-'
+                };
+                self.set_char(char, &cursor);
+                self.tool = Tool::Text(Some(TextState {
+                    origin,
+                    cursor: cursor.right(),
+                }));
+            }
+            Action::RightArrow => {
+                self.tool = Tool::Text(Some(TextState {
+                    origin,
+                    cursor: cursor.right(),
+                }));
+            }
+            Action::LeftControlArrow => {
+                let char = if (self.prev_action != Some(Action::RightControlArrow))
+                    && (self.prev_action != Some(Action::LeftControlArrow))
+                {
+                    '+'
+                } else {
+                    '-'
+                };
+                self.set_char(char, &cursor);
+                self.tool = Tool::Text(Some(TextState {
+                    origin,
+                    cursor: cursor.left(),
+                }));
+            }
+            Action::LeftArrow => {
+                self.tool = Tool::Text(Some(TextState {
+                    origin,
+                    cursor: cursor.left(),
+                }));
+            }
+            Action::UpControlArrow => {
+                let char = if (self.prev_action != Some(Action::UpControlArrow))
+                    && (self.prev_action != Some(Action::DownControlArrow))
+                {
+                    '+'
+                } else {
+                    '|'
+                };
+                self.set_char(char, &cursor);
+                self.tool = Tool::Text(Some(TextState {
+                    origin,
+                    cursor: cursor.up(),
+                }));
+            }
+            Action::UpArrow => {
+                self.tool = Tool::Text(Some(TextState {
+                    origin,
+                    cursor: cursor.up(),
+                }));
+            }
+            Action::DownControlArrow => {
+                let char = if (self.prev_action != Some(Action::DownControlArrow))
+                    && (self.prev_action != Some(Action::UpControlArrow))
+                {
+                    '+'
+                } else {
+                    '|'
+                };
+                self.set_char(char, &cursor);
+                self.tool = Tool::Text(Some(TextState {
+                    origin,
+                    cursor: cursor.down(),
+                }));
+            }
+            Action::DownArrow => {
+                self.tool = Tool::Text(Some(TextState {
+                    origin,
+                    cursor: cursor.down(),
+                }));
+            }
+            Action::Escape => {
+                self.tool = Tool::Selection(None);
+            }
+            Action::Enter => {
+                let origin = origin.down();
+                self.tool = Tool::Text(Some(TextState {
+                    origin,
+                    cursor: origin,
+                }));
+            }
+            Action::Copy => {
+                self.copy_buffer = Some(self.text.render());
+            }
+            // Like the tool shortcuts, undo and redo only apply once
+            // the text entry is finished.
+            Action::Undo | Action::Redo | Action::SelectAll | Action::Duplicate => {}
+        }
+        self.prev_action = Some(action);
+    }
+    fn on_action(&mut self, action: Action) {
+        match &self.tool {
+            Tool::Text(Some(text_state)) => {
+                self.on_action_with_text(*text_state, action);
+            }
+            Tool::Pin(Some(state)) => {
+                let state = state.clone();
+                self.on_action_with_pin(state, action);
+            }
+            _ if action == Action::Undo => self.undo(),
+            _ if action == Action::Redo => self.redo(),
+            _ if action == Action::SelectAll => self.select_all(),
+            Tool::Selection(None) => match action {
+                Action::Char('t') => self.tool = Tool::Text(None),
+                Action::Char('l') => self.tool = Tool::Line(None),
+                Action::Char('r') => self.tool = Tool::Rect(None),
+                Action::Char('p') => self.tool = Tool::Pin(None),
+                Action::Copy => {
+                    self.copy_buffer = Some(self.text.render());
+                }
+                Action::Paste(txt) => {
+                    self.snapshot();
+                    let hover_pos = self.hover_pos.unwrap_or_default();
+                    self.grow_to_paste(&txt, hover_pos);
+                    let rect = self.selected_text.paste(&txt, hover_pos);
+                    self.tool = Tool::Selected(rect.into());
+                }
+                _ => {}
+            },
+            Tool::Selected(selection) if action == Action::Copy => {
+                let selection = self.selected_text.window(&selection.bounding_box());
+                self.copy_buffer = Some(selection.render());
+            }
+            Tool::Selected(_) if action == Action::Escape => self.drop_selection(),
+            Tool::Selected(selection) if action == Action::Duplicate => {
+                let selection = selection.clone();
+                self.duplicate(selection);
+            }
+            Tool::Selected(selection)
+                if matches!(
+                    action,
+                    Action::UpArrow | Action::DownArrow | Action::LeftArrow | Action::RightArrow
+                ) =>
+            {
+                let selection = selection.clone();
+                self.nudge(selection, &action);
+            }
+            Tool::Selected(_) if action == Action::Backspace => {
+                self.selected_text.clear_all();
+                self.tool = Tool::Selection(None);
+            }
+            _ if action == Action::Escape => self.tool = Tool::Selection(None),
+            _ => {}
+        }
+    }
+    // Lift everything on the canvas into the selection, as if it
+    // had all been dragged over.  Any selection already floating
+    // is dropped onto the canvas first.
+    fn select_all(&mut self) {
+        match &self.tool {
+            Tool::MovingText(_) | Tool::AddingSelection(_) => return,
+            Tool::Selected(_) => self.drop_selection(),
+            _ => {}
+        }
+        let Some(rect) = self.text.bounding_box() else {
+            return;
+        };
+        self.snapshot();
+        self.lift(rect);
+        self.tool = Tool::Selected(rect.into());
+    }
+    fn has_text(&self, rect: Rectangle) -> bool {
+        rect.iter_interior().any(|pos| self.text.get(pos).is_some())
+    }
+    // Move the text in `rect` off the canvas and into the
+    // selection, which only ever holds the lifted text.
+    fn lift(&mut self, rect: Rectangle) {
+        for pos in rect.iter_interior() {
+            self.selected_text.merge_text(&pos, self.text.get(pos));
+        }
+        self.text.clear_rectangle(rect);
+    }
+    fn shift_selected_text(&mut self, origin: TextCoordinate, move_pos: TextCoordinate) {
+        let mut swap_buf = TextBuffer::new(self.num_rows, self.num_cols);
+        for (pos, ch) in self.selected_text.iter() {
+            swap_buf.merge_text(&pos.shifted(origin, move_pos), Some(ch));
+        }
+        self.selected_text = swap_buf;
+    }
+    // Stamp a copy of the selection onto the canvas, and move the
+    // selection along so it can be stamped again.  The step shrinks
+    // near the edges, so the selection stays on the canvas.
+    fn duplicate(&mut self, selection: Selection) {
+        let bbox = selection.bounding_box();
+        let origin = bbox.left_top();
+        let room_x = self.num_cols.saturating_sub(bbox.right_bottom().x + 1);
+        let room_y = self.num_rows.saturating_sub(bbox.right_bottom().y + 1);
+        let move_pos = TextCoordinate {
+            x: origin.x + DUPLICATE_STEP.0.min(room_x),
+            y: origin.y + DUPLICATE_STEP.1.min(room_y),
+        };
+        self.snapshot();
+        self.text
+            .overlay(&self.selected_text, TextCoordinate::default());
+        self.shift_selected_text(origin, move_pos);
+        self.tool = Tool::Selected(selection.shifted(origin, move_pos));
+    }
+    // Move the selection one cell in the direction of the arrow key,
+    // unless it already sits against that edge of the canvas.
+    fn nudge(&mut self, selection: Selection, arrow: &Action) {
+        let bbox = selection.bounding_box();
+        let origin = bbox.left_top();
+        let right_bottom = bbox.right_bottom();
+        let move_pos = match arrow {
+            Action::LeftArrow if origin.x > 0 => origin.left(),
+            Action::RightArrow if right_bottom.x + 1 < self.num_cols => origin.right(),
+            Action::UpArrow if origin.y > 0 => origin.up(),
+            Action::DownArrow if right_bottom.y + 1 < self.num_rows => origin.down(),
+            _ => return,
+        };
+        self.snapshot();
+        self.shift_selected_text(origin, move_pos);
+        self.tool = Tool::Selected(selection.shifted(origin, move_pos));
+    }
+    // Put the selected text back onto the canvas where it sits.
+    fn drop_selection(&mut self) {
+        self.text
+            .overlay(&self.selected_text, TextCoordinate::default());
+        self.selected_text.clear_all();
+        self.tool = Tool::Selection(None);
+    }
+    // Templates are pasted as a floating selection, so they can
+    // be dragged into place before being dropped onto the canvas.
+    pub fn insert_template(&mut self, template: &str) {
+        self.snapshot();
+        let pos = self.hover_pos.unwrap_or_default();
+        self.selected_text.clear_all();
+        let template = template.trim_matches('\n');
+        self.grow_to_paste(template, pos);
+        let rect = self.selected_text.paste(template, pos);
+        self.tool = Tool::Selected(rect.into());
+    }
+    // The cells covered by each match of the find panel's text.
+    fn find_matches(&self) -> Vec<TextCoordinate> {
+        let Some(find) = &self.find else {
+            return vec![];
+        };
+        let len = find.needle.chars().count() as u32;
+        self.text
+            .find(&find.needle)
+            .into_iter()
+            .flat_map(|pos| {
+                (0..len).map(move |dx| TextCoordinate {
+                    x: pos.x + dx,
+                    ..pos
+                })
+            })
+            .collect()
+    }
+    // Replace every match of the find panel's text, and return the
+    // number of matches replaced.  A shorter replacement blanks the
+    // rest of the match, and a longer one overwrites the cells to
+    // the right of it.
+    pub(crate) fn replace_all(&mut self) -> usize {
+        let Some(find) = &self.find else {
+            return 0;
+        };
+        let matches = self.text.find(&find.needle);
+        if matches.is_empty() {
+            return 0;
+        }
+        let len = find.needle.chars().count();
+        let replacement = find.replacement.chars().collect::<Vec<_>>();
+        self.snapshot();
+        for pos in &matches {
+            for dx in 0..len.max(replacement.len()) {
+                let pos = TextCoordinate {
+                    x: pos.x + dx as u32,
+                    ..*pos
+                };
+                self.text.set_text(&pos, replacement.get(dx).copied());
+            }
+        }
+        matches.len()
+    }
+    fn on_hover(&mut self, tc: Option<TextCoordinate>) {
+        self.hover_pos = tc;
+    }
+    /// Undo the last edit, if there is one.
+    pub fn undo(&mut self) {
+        if let Some(snapshot) = self.snapshots.pop_back() {
+            self.futures.push(self.current());
+            self.restore(snapshot);
+        }
+    }
+    /// Redo the last undone edit, if there is one.
+    pub fn redo(&mut self) {
+        if let Some(snapshot) = self.futures.pop() {
+            if self.snapshots.len() >= self.max_undo_depth {
+                self.snapshots.pop_front();
+            }
+            self.snapshots.push_back(self.current());
+            self.restore(snapshot);
+        }
+    }
+    // The grid is purely visual, so the spacing does not
+    // affect the cell layout of the underlying buffer.
+    fn grid_lines(&self, count: u32) -> impl Iterator<Item = u32> {
+        let show_grid = self.show_grid;
+        (0..=count)
+            .step_by(self.grid_spacing.max(1) as usize)
+            .filter(move |_| show_grid)
+    }
+    fn draw_grid(&mut self, canvas: &Rect, painter: &Painter, grid_color: Color32) {
+        let delta_x = canvas.width() / self.num_cols as f32;
+        let delta_y = canvas.height() / self.num_rows as f32;
+        let top_left = canvas.left_top();
+        for column in self.grid_lines(self.num_cols) {
+            let col_x = column as f32 * delta_x;
+            let p0 = top_left + vec2(col_x, 0.0);
+            let p1 = top_left + vec2(col_x, canvas.height());
+            painter.line(vec![p0, p1], PathStroke::new(1.0, grid_color));
+        }
+        for row in self.grid_lines(self.num_rows) {
+            let row_y = row as f32 * delta_y;
+            let p0 = top_left + vec2(0.0, row_y);
+            let p1 = top_left + vec2(canvas.width(), row_y);
+            painter.line(vec![p0, p1], PathStroke::new(1.0, grid_color));
+        }
+    }
+    fn draw_text_buffer(&mut self, canvas: &Rect, painter: &Painter, text_color: Color32) {
+        let delta_x = canvas.width() / self.num_cols as f32;
+        let delta_y = canvas.height() / self.num_rows as f32;
+        let text_size = delta_x.min(delta_y) * TEXT_SCALE_FACTOR;
+        let monospace = FontId::monospace(text_size);
+        for (coord, ch) in self.text.iter() {
+            let center = self.map_text_coordinate_to_cell_center(canvas, &coord);
+            painter.text(
+                center,
+                Align2::CENTER_CENTER,
+                ch,
+                monospace.clone(),
+                text_color,
+            );
+        }
+    }
+    fn draw_find_matches(&self, canvas: &Rect, painter: &Painter) {
+        let delta_x = canvas.width() / self.num_cols as f32;
+        let delta_y = canvas.height() / self.num_rows as f32;
+        for coord in self.find_matches() {
+            let center = self.map_text_coordinate_to_cell_center(canvas, &coord);
+            painter.rect_filled(
+                Rect::from_center_size(center, vec2(delta_x, delta_y)),
+                1.0,
+                Color32::YELLOW.linear_multiply(0.3),
+            );
+        }
+    }
+    // The ends of the wires, to draw faintly over the raw text.
+    fn wire_end_ops(&self, canvas: &Rect) -> Vec<Drawable<f32>> {
+        if !self.show_wire_ends {
+            return vec![];
+        }
+        canvas_job(canvas, self.text.clone(), self.rough_mode).invoke_wire_ends()
+    }
+    fn draw_wire_ends(&self, canvas: &Rect, painter: &Painter, color: Color32) {
+        for op in self.wire_end_ops(canvas) {
+            stroke_opset(op, painter, color.linear_multiply(0.4));
+        }
+    }
+    fn show_hover(&mut self, canvas: &Rect, pos: Pos2, painter: &Painter) {
+        let top_left = canvas.left_top();
+        let delta_x = canvas.width() / self.num_cols as f32;
+        let delta_y = canvas.height() / self.num_rows as f32;
+        if let Some(text_coordinate) = self.map_pos_to_coords(canvas, pos) {
+            let col = text_coordinate.x as f32;
+            let row = text_coordinate.y as f32;
+            let top_left_corner = top_left + vec2(col * delta_x, row * delta_y);
+            let bottom_right_corner = top_left_corner + vec2(delta_x, delta_y);
+            painter.rect_filled(
+                Rect::from_two_pos(top_left_corner, bottom_right_corner),
+                1.0,
+                Color32::LIGHT_BLUE.linear_multiply(0.3),
+            );
+            self.on_hover(Some(text_coordinate));
+        } else {
+            self.on_hover(None);
+        }
+    }
+    fn on_handle_interaction(
+        &mut self,
+        resp: &Response,
+        canvas: &Rect,
+        pos: Pos2,
+        painter: &Painter,
+    ) {
+        if let Some(text_coordinate) = self.map_pos_to_coords(canvas, pos) {
+            if resp.drag_started() {
+                self.on_drag_start(text_coordinate, resp);
+            } else if resp.dragged() {
+                self.on_drag(text_coordinate, canvas, painter);
+            } else if resp.drag_stopped() {
+                self.on_drag_stop(text_coordinate);
+            } else if resp.clicked() {
+                self.on_click(text_coordinate);
+            }
+        }
+    }
+    fn tool_specific_drawing(&self, canvas: &Rect, painter: &Painter) {
+        let delta_x = canvas.width() / self.num_cols as f32;
+        let delta_y = canvas.height() / self.num_rows as f32;
+        let text_size = delta_x.min(delta_y) * TEXT_SCALE_FACTOR;
+        let monospace = FontId::monospace(text_size);
+        match &self.tool {
+            Tool::Text(Some(TextState { origin: _, cursor })) => {
+                let center = self.map_text_coordinate_to_cell_center(canvas, cursor);
+                let rect = Rect::from_center_size(center, vec2(delta_x, delta_y));
+                painter.rect_stroke(
+                    rect,
+                    0.5,
+                    (1.0, Color32::LIGHT_YELLOW),
+                    egui::StrokeKind::Middle,
+                );
+            }
+            Tool::Selected(selection) | Tool::AddingSelection(AddState { selection, .. }) => {
+                for (coord, ch) in self.selected_text.iter() {
+                    if selection.contains(&coord) {
+                        let center = self.map_text_coordinate_to_cell_center(canvas, &coord);
+                        painter.text(
+                            center,
+                            Align2::CENTER_CENTER,
+                            ch,
+                            monospace.clone(),
+                            Color32::GREEN.linear_multiply(0.5),
+                        );
+                    }
+                }
+            }
+            Tool::MovingText(MoveState {
+                selection,
+                origin,
+                move_pos,
+            }) => {
+                let bbox_shifted = selection.shifted(*origin, *move_pos);
+                for (coord, ch) in self.selected_text.iter() {
+                    let coord = coord.shifted(*origin, *move_pos);
+                    if bbox_shifted.contains(&coord) {
+                        let center = self.map_text_coordinate_to_cell_center(canvas, &coord);
+                        painter.text(
+                            center,
+                            Align2::CENTER_CENTER,
+                            ch,
+                            monospace.clone(),
+                            Color32::GREEN,
+                        );
+                    }
+                }
+            }
+            Tool::Line(Some(LineState { start, end })) => {
+                for (coord, ch) in connect(*start, *end) {
+                    let center = self.map_text_coordinate_to_cell_center(canvas, &coord);
+                    painter.text(
+                        center,
+                        Align2::CENTER_CENTER,
+                        ch,
+                        monospace.clone(),
+                        Color32::GREEN,
+                    );
+                }
+            }
+            Tool::Rect(Some(rect)) => {
+                for (coord, ch) in outline(*rect) {
+                    let center = self.map_text_coordinate_to_cell_center(canvas, &coord);
+                    painter.text(
+                        center,
+                        Align2::CENTER_CENTER,
+                        ch,
+                        monospace.clone(),
+                        Color32::GREEN,
+                    );
+                }
+            }
+            Tool::Pin(Some(PinState { rect, pin })) => {
+                for (coord, ch) in pin_cells(*rect, pin) {
+                    let center = self.map_text_coordinate_to_cell_center(canvas, &coord);
+                    painter.text(
+                        center,
+                        Align2::CENTER_CENTER,
+                        ch,
+                        monospace.clone(),
+                        Color32::GREEN,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+    fn process_actions(&mut self, ui: &mut Ui) {
+        if let Some(action) = ui.input(|i| {
+            i.events.iter().find_map(|x| match x {
+                Event::Text(string) => {
+                    if string.len() == 1 {
+                        Some(Action::Char(string.chars().nth(0).unwrap()))
+                    } else {
+                        None
+                    }
+                }
+                Event::Key {
+                    key,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } => map_key(key, modifiers),
+                Event::Paste(string) => Some(Action::Paste(string.clone())),
+                Event::Copy => Some(Action::Copy),
+                _ => None,
+            })
+        }) {
+            self.on_action(action);
+        }
+    }
+    /// Draw the canvas, and handle the mouse and keyboard input
+    /// for it.  Keys are ignored while the `ui` is disabled, or when
+    /// a text field has the focus.
+    pub fn show(&mut self, ui: &mut Ui) -> Response {
+        egui::Frame::canvas(ui.style())
+            .show(ui, |ui| {
+                let desired_size = ui.available_size();
+                let (resp, painter) = ui.allocate_painter(desired_size, Sense::click_and_drag());
+                let canvas = resp.rect;
+                let text_color = ui.style().visuals.strong_text_color();
+                let grid_color = ui.style().visuals.code_bg_color;
+                self.draw_grid(&canvas, &painter, grid_color);
+                self.draw_find_matches(&canvas, &painter);
+                self.draw_text_buffer(&canvas, &painter, text_color);
+                self.draw_wire_ends(&canvas, &painter, text_color);
+                if let Some(pos) = resp.hover_pos() {
+                    self.show_hover(&canvas, pos, &painter);
+                    match &self.tool {
+                        Tool::Text(_) => {
+                            ui.ctx().set_cursor_icon(CursorIcon::Text);
+                        }
+                        Tool::Selected(..) => {
+                            ui.ctx().set_cursor_icon(CursorIcon::Grab);
+                        }
+                        Tool::MovingText(..) => {
+                            ui.ctx().set_cursor_icon(CursorIcon::Grabbing);
+                        }
+                        Tool::Line(..) | Tool::Rect(..) | Tool::Pin(..) => {
+                            ui.ctx().set_cursor_icon(CursorIcon::Crosshair);
+                        }
+                        _ => {
+                            ui.ctx().set_cursor_icon(CursorIcon::Default);
+                        }
+                    }
+                }
+                if let Some(pos) = resp.interact_pointer_pos() {
+                    self.on_handle_interaction(&resp, &canvas, pos, &painter);
+                }
+                // Keys typed into a text field (like the find panel)
+                // are not meant for the canvas.
+                if ui.is_enabled() && !ui.ctx().wants_keyboard_input() {
+                    self.process_actions(ui);
+                }
+                if let Some(txt) = self.copy_buffer.take() {
+                    ui.ctx().copy_text(txt);
+                }
+                self.tool_specific_drawing(&canvas, &painter);
+                resp
+            })
+            .inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::templates::TEMPLATES;
+
+    use super::*;
+
+    #[test]
+    fn test_grid_visibility() {
+        let mut app = AsciiCanvas::default();
+        assert_eq!(app.grid_lines(app.num_cols).count(), 101);
+        app.grid_spacing = 10;
+        assert_eq!(
+            app.grid_lines(app.num_cols).collect::<Vec<_>>(),
+            vec![0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 100]
+        );
+        app.show_grid = false;
+        assert_eq!(app.grid_lines(app.num_cols).count(), 0);
+        assert_eq!(app.grid_lines(app.num_rows).count(), 0);
+    }
+
+    #[test]
+    fn test_wire_ends_toggle() {
+        let mut app = AsciiCanvas::default();
+        app.text.paste("+--->", TextCoordinate { x: 1, y: 1 });
+        let canvas = Rect::from_min_size(Pos2::ZERO, vec2(1000.0, 600.0));
+        assert!(app.wire_end_ops(&canvas).is_empty());
+        app.show_wire_ends = true;
+        assert_eq!(app.wire_end_ops(&canvas).len(), 1);
+        app.text.clear_all();
+        assert!(app.wire_end_ops(&canvas).is_empty());
+    }
+
+    #[test]
+    fn test_line_tool_writes_l_route() {
+        let mut app = AsciiCanvas {
+            tool: Tool::Line(Some(LineState {
+                start: TextCoordinate { x: 2, y: 1 },
+                end: TextCoordinate { x: 2, y: 1 },
+            })),
+            ..Default::default()
+        };
+        app.on_drag_stop(TextCoordinate { x: 7, y: 4 });
+        assert!(matches!(app.tool, Tool::Line(None)));
+        let expect = expect_test::expect![[r#"
+            +----+
+                 |
+                 |
+                 +
+        "#]];
+        expect.assert_eq(&app.text.render());
+    }
+
+    #[test]
+    fn test_undo_redo_shortcuts() {
+        assert_eq!(map_key(&Key::Z, &Modifiers::COMMAND), Some(Action::Undo));
+        assert_eq!(map_key(&Key::Y, &Modifiers::COMMAND), Some(Action::Redo));
+        assert_eq!(
+            map_key(&Key::Z, &(Modifiers::COMMAND | Modifiers::SHIFT)),
+            Some(Action::Redo)
+        );
+        assert_eq!(map_key(&Key::Z, &Modifiers::NONE), None);
+        let mut app = AsciiCanvas::new(5, 8);
+        app.text.paste("ab", TextCoordinate { x: 0, y: 0 });
+        app.snapshot();
+        app.text.clear_all();
+        app.on_action(Action::Undo);
+        assert_eq!(app.text.render(), "ab\n");
+        app.on_action(Action::Redo);
+        assert!(app.text.iter().next().is_none());
+        app.tool = Tool::Text(Some(TextState {
+            origin: TextCoordinate { x: 0, y: 0 },
+            cursor: TextCoordinate { x: 0, y: 0 },
+        }));
+        app.on_action(Action::Char('z'));
+        app.on_action(Action::Undo);
+        assert_eq!(app.text.render(), "z\n");
+    }
+
+    #[test]
+    fn test_select_all() {
+        let mut app = AsciiCanvas::new(5, 8);
+        app.on_action(Action::SelectAll);
+        assert!(matches!(app.tool, Tool::Selection(None)));
+        assert!(!app.can_undo());
+        app.text.paste("ab", TextCoordinate { x: 2, y: 1 });
+        app.text.paste("c", TextCoordinate { x: 1, y: 3 });
+        app.on_action(Action::SelectAll);
+        let Tool::Selected(selection) = &app.tool else {
+            panic!("nothing selected");
+        };
+        assert_eq!(
+            *selection,
+            Rectangle::new(TextCoordinate { x: 1, y: 1 }, TextCoordinate { x: 3, y: 3 }).into()
+        );
+        assert!(app.text.iter().next().is_none());
+        app.on_action(Action::Backspace);
+        assert!(app.selected_text.iter().next().is_none());
+        assert!(app.text.iter().next().is_none());
+        app.undo();
+        assert_eq!(app.current().combined().render(), " ab\n   \nc  \n");
+    }
+
+    #[test]
+    fn test_selections_move_together() {
+        let mut app = AsciiCanvas::new(6, 10);
+        app.text.paste("ab", TextCoordinate { x: 0, y: 0 });
+        app.text.paste("cd", TextCoordinate { x: 6, y: 2 });
+        app.text.paste("zz", TextCoordinate { x: 3, y: 4 });
+        app.tool = Tool::Selection(Some(TextCoordinate { x: 0, y: 0 }));
+        app.on_drag_stop(TextCoordinate { x: 1, y: 0 });
+        let Tool::Selected(first) = app.tool.clone() else {
+            panic!("nothing selected");
+        };
+        // Shift-drag over the second region
+        app.tool = Tool::AddingSelection(AddState {
+            selection: first,
+            corner: TextCoordinate { x: 6, y: 2 },
+        });
+        app.on_drag_stop(TextCoordinate { x: 7, y: 2 });
+        let Tool::Selected(both) = app.tool.clone() else {
+            panic!("the selection was lost");
+        };
+        assert_eq!(both.0.len(), 2);
+        assert_eq!(app.text.iter().count(), 2);
+        app.on_action(Action::Copy);
+        assert_eq!(
+            app.copy_buffer.take().unwrap(),
+            "ab      \n        \n      cd\n"
+        );
+        // Drag both down a row and to the right
+        app.tool = Tool::MovingText(MoveState {
+            selection: both.clone(),
+            origin: TextCoordinate { x: 0, y: 0 },
+            move_pos: TextCoordinate { x: 1, y: 1 },
+        });
+        app.on_drag_stop(TextCoordinate { x: 1, y: 1 });
+        assert!(matches!(&app.tool, Tool::Selected(selection) if selection.0.len() == 2));
+        app.on_action(Action::Escape);
+        assert_eq!(
+            app.text.render(),
+            "ab      \n        \n      cd\n  zz    \n"
+        );
+        // An empty shift-drag leaves the selection alone
+        app.undo();
+        app.tool = Tool::AddingSelection(AddState {
+            selection: both.clone(),
+            corner: TextCoordinate { x: 0, y: 5 },
+        });
+        app.on_drag_stop(TextCoordinate { x: 2, y: 5 });
+        assert!(matches!(&app.tool, Tool::Selected(selection) if *selection == both));
+    }
+
+    #[test]
+    fn test_duplicate_selection() {
+        let mut app = AsciiCanvas::new(4, 8);
+        app.hover_pos = Some(TextCoordinate { x: 0, y: 0 });
+        app.on_action(Action::Paste("ab".into()));
+        app.on_action(Action::Duplicate);
+        app.on_action(Action::Duplicate);
+        assert_eq!(app.text.render(), "ab  \n  ab\n");
+        assert_eq!(
+            app.current().combined().render(),
+            "ab    \n  ab  \n    ab\n"
+        );
+        // At the edge, the copy only moves as far as there is room
+        app.on_action(Action::Duplicate);
+        app.on_action(Action::Duplicate);
+        let Tool::Selected(selection) = &app.tool else {
+            panic!("the duplicate should be selected");
+        };
+        assert_eq!(
+            *selection,
+            Rectangle::new(TextCoordinate { x: 6, y: 3 }, TextCoordinate { x: 7, y: 3 }).into()
+        );
+        app.on_action(Action::Escape);
+        assert_eq!(
+            app.text.render(),
+            "ab      \n  ab    \n    ab  \n      ab\n"
+        );
+        app.undo();
+        assert_eq!(app.text.iter().count(), 6);
+    }
+
+    #[test]
+    fn test_arrow_keys_nudge_selection() {
+        let mut app = AsciiCanvas::new(3, 4);
+        app.hover_pos = Some(TextCoordinate { x: 1, y: 1 });
+        app.on_action(Action::Paste("ab".into()));
+        let selected = |app: &AsciiCanvas| match &app.tool {
+            Tool::Selected(selection) => selection.bounding_box().left_top(),
+            _ => panic!("the selection should stay selected"),
+        };
+        let moves = [
+            (Action::RightArrow, (2, 1)),
+            (Action::UpArrow, (2, 0)),
+            (Action::LeftArrow, (1, 0)),
+            (Action::DownArrow, (1, 1)),
+            (Action::DownArrow, (1, 2)),
+        ];
+        for (arrow, (x, y)) in moves {
+            app.on_action(arrow);
+            assert_eq!(selected(&app), TextCoordinate { x, y });
+            assert_eq!(app.selected_text.find("ab"), vec![TextCoordinate { x, y }]);
+        }
+        // Against the edge, the selection stays put
+        for arrow in [Action::DownArrow, Action::RightArrow, Action::RightArrow] {
+            app.on_action(arrow);
+        }
+        assert_eq!(selected(&app), TextCoordinate { x: 2, y: 2 });
+        assert!(app.text.iter().next().is_none());
+        // Each nudge is a step of its own in the undo history
+        app.undo();
+        assert_eq!(selected(&app), TextCoordinate { x: 1, y: 2 });
+        app.undo();
+        assert_eq!(selected(&app), TextCoordinate { x: 1, y: 1 });
+        app.on_action(Action::Escape);
+        assert_eq!(app.text.render(), "ab\n");
+        assert_eq!(app.text.find("ab"), vec![TextCoordinate { x: 1, y: 1 }]);
+    }
+
+    #[test]
+    fn test_paste_past_the_edge_grows_canvas() {
+        let mut app = AsciiCanvas::new(3, 4);
+        app.hover_pos = Some(TextCoordinate { x: 2, y: 2 });
+        app.on_action(Action::Paste("+--+\n|  |\n+--+".into()));
+        app.on_action(Action::Escape);
+        assert_eq!((app.num_rows, app.num_cols), (5, 6));
+        assert_eq!(app.text.size(), app.selected_text.size());
+        assert_eq!(app.text.render(), "+--+\n|  |\n+--+\n");
+        // Typing off the right edge grows it again
+        app.tool = Tool::Text(Some(TextState {
+            origin: TextCoordinate { x: 5, y: 0 },
+            cursor: TextCoordinate { x: 5, y: 0 },
+        }));
+        for ch in "abc".chars() {
+            app.on_action(Action::Char(ch));
+        }
+        assert_eq!((app.num_rows, app.num_cols), (5, 8));
+        assert_eq!(app.text.iter().count(), 13);
+        // Undoing the paste takes the canvas back to its old size
+        app.undo();
+        assert_eq!((app.num_rows, app.num_cols), (3, 4));
+    }
+
+    #[test]
+    fn test_line_tool_can_be_undone() {
+        let mut app = AsciiCanvas::new(5, 8);
+        app.set_line_tool(true);
+        assert!(app.line_tool());
+        let start = TextCoordinate { x: 1, y: 1 };
+        app.tool = Tool::Line(Some(LineState { start, end: start }));
+        app.on_drag_stop(TextCoordinate { x: 6, y: 1 });
+        assert_eq!(app.text.render(), "+----+\n");
+        assert!(app.line_tool());
+        app.undo();
+        assert!(app.text.iter().next().is_none());
+        app.set_line_tool(false);
+        assert!(!app.line_tool());
+    }
+
+    #[test]
+    fn test_rect_tool_draws_box() {
+        let mut app = AsciiCanvas::new(5, 8);
+        app.text.paste("abcdefgh", TextCoordinate { x: 0, y: 2 });
+        app.set_rect_tool(true);
+        let corner = TextCoordinate { x: 5, y: 3 };
+        app.tool = Tool::Rect(Some(Rectangle::new(corner, corner)));
+        app.on_drag_stop(TextCoordinate { x: 1, y: 1 });
+        assert!(matches!(app.tool, Tool::Rect(None)));
+        let expect = expect_test::expect![[r#"
+             +---+  
+            a|cde|gh
+             +---+  
+        "#]];
+        expect.assert_eq(&app.text.render());
+        app.undo();
+        assert_eq!(app.text.render(), "abcdefgh\n");
+        // A drag along a single row or column is not a box
+        app.tool = Tool::Rect(Some(Rectangle::new(corner, corner)));
+        app.on_drag_stop(TextCoordinate { x: 1, y: 3 });
+        assert!(!app.can_undo());
+        assert_eq!(app.text.render(), "abcdefgh\n");
+    }
+
+    #[test]
+    fn test_pin_cells_on_each_side() {
+        let rect = Rectangle::new(
+            TextCoordinate { x: 4, y: 2 },
+            TextCoordinate { x: 11, y: 6 },
+        );
+        let mut tb = TextBuffer::new(9, 16);
+        for (pos, ch) in outline(rect) {
+            tb.set_text(&pos, Some(ch));
+        }
+        let pins = [
+            (Side::Left, PinDirection::In, 2, "clk"),
+            (Side::Right, PinDirection::Out, 2, "q"),
+            (Side::Top, PinDirection::Out, 2, "en"),
+            (Side::Bottom, PinDirection::In, 5, "a_long_name"),
+        ];
+        for (side, direction, offset, name) in pins {
+            let pin = Pin {
+                name: name.into(),
+                side,
+                direction,
+                offset,
+            };
+            for (pos, ch) in pin_cells(rect, &pin) {
+                tb.set_text(&pos, Some(ch));
+            }
+        }
+        assert_eq!(
+            tb.render_exact(),
+            [
+                "      ^         ",
+                "      |         ",
+                "    +-+----+    ",
+                "    | en   |    ",
+                " +->|clk  q+--> ",
+                "    |    a_|    ",
+                "    +------+    ",
+                "         ^      ",
+                "         +      ",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_pin_tool_adds_pin() {
+        let mut app = AsciiCanvas::new(5, 12);
+        app.text
+            .paste("+----+\n|    |\n+----+", TextCoordinate { x: 4, y: 1 });
+        app.set_pin_tool(true);
+        assert!(app.pin_tool());
+        // Corners and empty cells are not places for a pin
+        app.on_click(TextCoordinate { x: 4, y: 1 });
+        app.on_click(TextCoordinate { x: 1, y: 2 });
+        assert!(matches!(app.tool, Tool::Pin(None)));
+        app.on_click(TextCoordinate { x: 4, y: 2 });
+        for ch in "ab".chars() {
+            app.on_action(Action::Char(ch));
+        }
+        app.on_action(Action::Backspace);
+        app.on_action(Action::LeftArrow);
+        app.on_action(Action::Enter);
+        assert!(matches!(app.tool, Tool::Pin(None)));
+        assert_eq!(
+            app.text.render_exact(),
+            [
+                "            ",
+                "    +----+  ",
+                " +->|a   |  ",
+                "    +----+  ",
+                "            ",
+            ]
+            .join("\n")
+        );
+        app.undo();
+        assert_eq!(app.text.get(TextCoordinate { x: 5, y: 2 }), None);
+        // Escape gives up on a pin, and then leaves the tool
+        app.on_click(TextCoordinate { x: 9, y: 2 });
+        app.on_action(Action::Char('x'));
+        app.on_action(Action::Escape);
+        assert!(matches!(app.tool, Tool::Pin(None)));
+        assert!(app.can_redo());
+        app.on_action(Action::Escape);
+        assert!(!app.pin_tool());
+    }
+
+    #[test]
+    fn test_connect_routes_backwards() {
+        let route = connect(TextCoordinate { x: 5, y: 3 }, TextCoordinate { x: 2, y: 0 });
+        let mut tb = TextBuffer::new(4, 6);
+        for (pos, ch) in route {
+            tb.set_text(&pos, Some(ch));
+        }
+        let expect = expect_test::expect![[r#"
+            +   
+            |   
+            |   
+            +--+
+        "#]];
+        expect.assert_eq(&tb.render());
+        assert!(connect(TextCoordinate { x: 1, y: 1 }, TextCoordinate { x: 1, y: 1 }).is_empty());
+    }
+
+    #[test]
+    fn test_insert_template() {
+        let mut app = AsciiCanvas::default();
+        let (_, template) = TEMPLATES[0];
+        app.insert_template(template);
+        assert!(matches!(app.tool, Tool::Selected(_)));
+        app.on_action(Action::Escape);
+        assert_eq!(app.text.render(), template.trim_start_matches('\n'));
+    }
+
+    #[test]
+    fn test_max_undo_depth() {
+        let mut app = AsciiCanvas::default().with_max_undo_depth(3);
+        for ch in ['a', 'b', 'c', 'd', 'e'] {
+            app.set_char(ch, &TextCoordinate { x: 0, y: 0 });
+            app.snapshot();
+        }
+        assert_eq!(app.snapshots.len(), 3);
+        app.undo();
+        assert_eq!(app.text.get(TextCoordinate { x: 0, y: 0 }), Some('e'));
+        app.undo();
+        app.undo();
+        assert_eq!(app.text.get(TextCoordinate { x: 0, y: 0 }), Some('c'));
+        assert!(app.snapshots.is_empty());
+        assert_eq!(
+            AsciiCanvas::default().with_max_undo_depth(0).max_undo_depth,
+            1
+        );
+        // A snapshot of an unchanged canvas keeps the full history
+        let mut app = AsciiCanvas::default().with_max_undo_depth(2);
+        for ch in ['a', 'b'] {
+            app.set_char(ch, &TextCoordinate { x: 0, y: 0 });
+            app.snapshot();
+        }
+        app.snapshot();
+        app.set_char('c', &TextCoordinate { x: 0, y: 0 });
+        app.undo();
+        app.undo();
+        assert_eq!(app.text.get(TextCoordinate { x: 0, y: 0 }), Some('a'));
+    }
+
+    #[test]
+    fn test_undo_restores_selection() {
+        let mut app = AsciiCanvas::new(5, 10);
+        app.text.paste("abc", TextCoordinate { x: 0, y: 0 });
+        app.hover_pos = Some(TextCoordinate { x: 2, y: 2 });
+        app.on_action(Action::Paste("xy".into()));
+        let pasted = match &app.tool {
+            Tool::Selected(selection) => selection.clone(),
+            _ => panic!("the paste should be selected"),
+        };
+        // Move the selection down a row
+        app.tool = Tool::MovingText(MoveState {
+            selection: pasted.clone(),
+            origin: TextCoordinate { x: 2, y: 2 },
+            move_pos: TextCoordinate { x: 2, y: 3 },
+        });
+        app.on_drag_stop(TextCoordinate { x: 2, y: 3 });
+        assert_eq!(
+            app.text_with_selection().render(),
+            "abc \n    \n    \n  xy\n"
+        );
+        // Undoing the move leaves the paste selected where it was
+        app.undo();
+        assert!(matches!(app.tool, Tool::Selected(ref selection) if *selection == pasted));
+        assert_eq!(app.text_with_selection().render(), "abc \n    \n  xy\n");
+        assert_eq!(app.text.render(), "abc\n");
+        // Undoing the paste leaves nothing selected
+        app.undo();
+        assert!(matches!(app.tool, Tool::Selection(None)));
+        assert_eq!(app.text_with_selection().render(), "abc\n");
+        assert!(!app.can_undo());
+        // And redo brings both back
+        app.redo();
+        assert!(matches!(app.tool, Tool::Selected(ref selection) if *selection == pasted));
+        app.redo();
+        assert_eq!(
+            app.text_with_selection().render(),
+            "abc \n    \n    \n  xy\n"
+        );
+        assert!(!app.can_redo());
+        // A new edit drops the undone ones
+        app.undo();
+        app.on_action(Action::Escape);
+        app.tool = Tool::Line(Some(LineState {
+            start: TextCoordinate { x: 5, y: 0 },
+            end: TextCoordinate { x: 5, y: 0 },
+        }));
+        app.on_drag_stop(TextCoordinate { x: 8, y: 0 });
+        assert!(!app.can_redo());
+    }
+
+    #[test]
+    fn test_replace_all_changes_every_label() {
+        let mut app = AsciiCanvas::default();
+        app.text.paste(
+            "+------+   +------+\n| data |-->| data |\n+------+   +------+\n  data",
+            TextCoordinate { x: 0, y: 0 },
+        );
+        app.find = Some(FindReplace {
+            needle: "data".into(),
+            replacement: "ok".into(),
+        });
+        assert_eq!(app.find_matches().len(), 12);
+        assert_eq!(app.replace_all(), 3);
+        let expect = expect_test::expect![[r#"
+            +------+   +------+
+            | ok   |-->| ok   |
+            +------+   +------+
+              ok               
+        "#]];
+        expect.assert_eq(&app.text.render());
+        assert!(app.find_matches().is_empty());
+        app.undo();
+        assert_eq!(app.text.find("data").len(), 3);
+    }
+
+    #[test]
+    fn test_click_on_wide_glyph() {
+        let mut app = AsciiCanvas::default();
+        app.text.paste("a漢 b", TextCoordinate { x: 2, y: 1 });
+        let canvas = Rect::from_min_size(
+            Pos2::ZERO,
+            vec2(app.num_cols as f32 * 10.0, app.num_rows as f32 * 10.0),
+        );
+        let click = |x: u32| {
+            app.map_pos_to_coords(&canvas, Pos2::new(x as f32 * 10.0 + 5.0, 15.0))
+                .map(|pos| pos.x)
+        };
+        // The glyph itself, and the second half of it
+        assert_eq!(click(3), Some(3));
+        assert_eq!(click(4), Some(3));
+        // Narrow glyphs and blank cells map as usual
+        assert_eq!(click(2), Some(2));
+        assert_eq!(click(5), Some(5));
+        assert_eq!(click(6), Some(6));
+    }
+
+    #[test]
+    fn test_set_text_takes_its_size() {
+        let mut app = AsciiCanvas::default();
+        app.set_text(TextBuffer::with_text("+--+\n|ok|\n+--+"));
+        assert_eq!((app.num_rows, app.num_cols), (3, 4));
+        app.on_action(Action::Char('t'));
+        app.on_click(TextCoordinate { x: 1, y: 1 });
+        app.on_action(Action::Char('O'));
+        assert_eq!(app.text().get(TextCoordinate { x: 1, y: 1 }), Some('O'));
+        app.undo();
+        assert_eq!(app.text().render(), "+--+\n|ok|\n+--+\n");
+    }
+}
@@ -1,3 +1,7 @@
 pub mod action;
 pub mod app;
+pub mod canvas;
 pub mod roughr_egui;
+pub mod templates;
+
+pub use canvas::AsciiCanvas;
@@ -1,11 +1,11 @@
 use egui::{
-    Color32, Painter, Pos2, Shape,
+    Color32, Painter, Pos2, Shape, Stroke,
     epaint::{CubicBezierShape, PathStroke},
     pos2,
 };
 use roughr::{
     PathSegment,
-    core::{Drawable, OpSetType, OpType},
+    core::{Drawable, Op, OpSetType, OpType},
 };
 
 pub fn move_to(p: Pos2) -> PathSegment {
@@ -28,8 +28,48 @@ pub fn close_path() -> PathSegment {
     PathSegment::ClosePath { abs: true }
 }
 
+// Fill each closed shape of the op set (the solid marks at the
+// ends of wires are all convex).
+fn fill_opset(ops: Vec<Op<f32>>, painter: &Painter, color: Color32) {
+    let mut shapes: Vec<Vec<Pos2>> = vec![];
+    for op in ops {
+        match op.op {
+            OpType::Move => shapes.push(vec![pos2(op.data[0], op.data[1])]),
+            OpType::LineTo => {
+                if let Some(shape) = shapes.last_mut() {
+                    shape.push(pos2(op.data[0], op.data[1]));
+                }
+            }
+            OpType::BCurveTo => {
+                if let Some(shape) = shapes.last_mut() {
+                    let pos = shape.last().copied().unwrap_or(pos2(0.0, 0.0));
+                    let curve = CubicBezierShape {
+                        points: [
+                            pos,
+                            pos2(op.data[0], op.data[1]),
+                            pos2(op.data[2], op.data[3]),
+                            pos2(op.data[4], op.data[5]),
+                        ],
+                        closed: false,
+                        fill: Color32::TRANSPARENT,
+                        stroke: PathStroke::NONE,
+                    };
+                    shape.extend(curve.flatten(None).into_iter().skip(1));
+                }
+            }
+        }
+    }
+    for shape in shapes {
+        painter.add(Shape::convex_polygon(shape, color, Stroke::NONE));
+    }
+}
+
 pub fn stroke_opset(ops: Drawable<f32>, painter: &Painter, color: Color32) {
     for op_set in ops.sets {
+        if op_set.op_set_type == OpSetType::FillPath {
+            fill_opset(op_set.ops, painter, color);
+            continue;
+        }
         if op_set.op_set_type != OpSetType::Path {
             continue;
         }
@@ -0,0 +1,47 @@
+//! Diagram skeletons offered in the "Templates" menu, so
+//! new users do not have to start from a blank canvas.
+
+const SINGLE_BOX: &str = "
++-------------+
+|             |
+|    Block    |
+|             |
++-------------+
+";
+
+const TWO_BOX_FLOW: &str = "
++-------------+        +-------------+
+|             |        |             |
+|   Source    +------->|    Sink     |
+|             |        |             |
++-------------+        +-------------+
+";
+
+const STATE_MACHINE: &str = "
+      +--------+    go     +--------+
+ o--->|  Idle  +---------->|  Busy  |
+      +--------+           +---+----+
+          ^                    |
+          |       done         |
+          +--------------------+
+";
+
+const REGISTER: &str = "
+     +---------------------+
+     |                     |
++--->| data           data |o--+
+|    |                     |   |
+|   o| full           next |>  |
+v    |                     |   |
+    o| overflow  underflow |o--+
+     |                     |
+     +---------------------+
+";
+
+/// The available templates, as `(name, diagram)` pairs.
+pub const TEMPLATES: &[(&str, &str)] = &[
+    ("Single box", SINGLE_BOX),
+    ("Two box flow", TWO_BOX_FLOW),
+    ("State machine", STATE_MACHINE),
+    ("Register", REGISTER),
+];
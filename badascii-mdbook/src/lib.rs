@@ -4,21 +4,113 @@ use mdbook::{
     errors::Error,
     preprocess::{Preprocessor, PreprocessorContext},
 };
-use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag, TagEnd};
+use pulldown_cmark::{CodeBlockKind, Event, Tag, TagEnd};
 
 // The BadAscii preprocessor.
 pub struct BadAscii;
 
-fn create_svg_html(formal_mode: bool, s: &str) -> String {
+// The CSS class of the `<pre>` that wraps each diagram.
+const WRAPPER_CLASS: &str = "badascii";
+
+// Follow the mdbook theme colors.  CSS variables cannot be used
+// in SVG presentation attributes, so the colors are applied with
+// a stylesheet that targets the classes on the SVG elements, and
+// `currentColor` remains as the fallback.
+const THEME_CSS: &str = ".badascii .badascii-stroke { stroke: var(--fg); } \
+.badascii .badascii-mark { fill: var(--fg); } \
+.badascii .badascii-label { fill: var(--fg); }";
+
+// The per diagram options, taken from the info string of the
+// fenced block, e.g. ```badascii formal width=400 caption="A bus"```.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct BlockOptions {
+    formal_mode: bool,
+    width: Option<f32>,
+    caption: Option<String>,
+}
+
+// Split the info string on commas and spaces, except for those
+// inside double quotes.
+fn info_tokens(info: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut quoted = false;
+    for ch in info.chars() {
+        match ch {
+            '"' => {
+                quoted = !quoted;
+                token.push(ch);
+            }
+            ',' | ' ' | '\t' if !quoted => tokens.push(std::mem::take(&mut token)),
+            _ => token.push(ch),
+        }
+    }
+    tokens.push(token);
+    tokens.retain(|token| !token.is_empty());
+    tokens
+}
+
+// Returns `None` if the info string is not for a badascii block.
+// `formal_mode` is the book wide default.
+fn parse_info_string(info: &str, formal_mode: bool) -> Option<BlockOptions> {
+    let tokens = info_tokens(info);
+    let (first, tokens) = tokens.split_first()?;
+    if first != "badascii" {
+        return None;
+    }
+    let mut options = BlockOptions {
+        formal_mode,
+        ..Default::default()
+    };
+    for token in tokens {
+        match token.as_str() {
+            "formal" => options.formal_mode = true,
+            "rough" => options.formal_mode = false,
+            _ => {
+                if let Some(width) = token.strip_prefix("width=") {
+                    options.width = width.parse().ok();
+                } else if let Some(caption) = token.strip_prefix("caption=") {
+                    options.caption = Some(caption.trim_matches('"').to_string());
+                }
+            }
+        }
+    }
+    Some(options)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn create_svg_html(options: &BlockOptions, s: &str) -> String {
     let tb = badascii::TextBuffer::with_text(s);
-    let job = if !formal_mode {
+    let mut job = if !options.formal_mode {
         badascii::RenderJob::rough(tb)
     } else {
         badascii::RenderJob::formal(tb)
     };
-    // TODO - figure out light vs dark mode for MDBook?
+    if let Some(width) = options.width {
+        job.scale_to_width(width);
+    }
     let svg = badascii::svg::render(&job, "currentColor", "none");
-    format!("\n\n<pre>{svg}</pre>\n")
+    let Some(caption) = &options.caption else {
+        return format!(
+            "\n\n<pre class=\"{WRAPPER_CLASS}\"><style>{THEME_CSS}</style>{svg}</pre>\n"
+        );
+    };
+    let caption = escape_html(caption);
+    let svg = svg.replacen(
+        "<svg ",
+        &format!("<svg aria-label=\"{caption}\" role=\"img\" "),
+        1,
+    );
+    format!(
+        "\n\n<figure><pre class=\"{WRAPPER_CLASS}\"><style>{THEME_CSS}</style>{svg}</pre>\
+         <figcaption>{caption}</figcaption></figure>\n"
+    )
 }
 impl BadAscii {
     fn process_chapter(formal_mode: bool, chapter: &mut Chapter) {
@@ -28,14 +120,18 @@ impl BadAscii {
 
         let mut in_block = false;
         let mut diagram = String::new();
+        let mut options = BlockOptions::default();
         let events = parser.filter_map(|event| match (&event, in_block) {
-            (
-                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(CowStr::Borrowed("badascii")))),
-                false,
-            ) => {
-                in_block = true;
-                diagram.clear();
-                None
+            (Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))), false) => {
+                match parse_info_string(info, formal_mode) {
+                    Some(block_options) => {
+                        options = block_options;
+                        in_block = true;
+                        diagram.clear();
+                        None
+                    }
+                    None => Some(event),
+                }
             }
             (Event::Text(content), true) => {
                 diagram.push_str(content);
@@ -43,7 +139,7 @@ impl BadAscii {
             }
             (Event::End(TagEnd::CodeBlock), true) => {
                 in_block = false;
-                Some(Event::Html(create_svg_html(formal_mode, &diagram).into()))
+                Some(Event::Html(create_svg_html(&options, &diagram).into()))
             }
             _ => Some(event),
         });
@@ -136,6 +232,100 @@ Here is a diagram of the mascot.
         expect.assert_eq(&chapter.content);
     }
 
+    #[test]
+    fn test_theme_hooks() {
+        let html = create_svg_html(&BlockOptions::default(), "+--> ok");
+        assert!(html.contains(r#"<pre class="badascii"><style>"#));
+        assert!(html.contains("stroke: var(--fg)"));
+        assert!(html.contains("fill: var(--fg)"));
+        assert!(html.contains(r#"class="badascii-stroke""#));
+        assert!(html.contains(r#"class="badascii-label""#));
+    }
+
+    #[test]
+    fn test_per_block_options() {
+        let md = r##"
+```badascii,formal
++----+
+|  OO|
++----+
+```
+
+```badascii rough width=120
++----+
+```
+
+```rust
+let x = 1;
+```
+"##;
+        let mut chapter = Chapter {
+            name: "Test".into(),
+            content: md.into(),
+            number: None,
+            sub_items: vec![],
+            path: None,
+            source_path: None,
+            parent_names: vec![],
+        };
+        BadAscii::process_chapter(false, &mut chapter);
+        let svgs = chapter.content.split("<svg").skip(1).collect::<Vec<_>>();
+        assert_eq!(svgs.len(), 2);
+        // The formal render is deterministic, unlike the rough one
+        let formal = badascii::svg::render(
+            &badascii::RenderJob::formal(badascii::TextBuffer::with_text(
+                "+----+\n|  OO|\n+----+\n",
+            )),
+            "currentColor",
+            "none",
+        );
+        assert!(chapter.content.contains(&formal));
+        assert!(svgs[1].contains(r#"viewBox="0 0 120 30""#));
+        assert!(chapter.content.contains("let x = 1;"));
+        assert_eq!(
+            parse_info_string("badascii formal,width=300", false),
+            Some(BlockOptions {
+                formal_mode: true,
+                width: Some(300.0),
+                caption: None,
+            })
+        );
+        assert_eq!(parse_info_string("badasciix", true), None);
+    }
+
+    #[test]
+    fn test_caption() {
+        let md = r##"
+```badascii formal caption="The <data> bus, annotated"
++--> ok
+```
+"##;
+        let mut chapter = Chapter {
+            name: "Test".into(),
+            content: md.into(),
+            number: None,
+            sub_items: vec![],
+            path: None,
+            source_path: None,
+            parent_names: vec![],
+        };
+        BadAscii::process_chapter(false, &mut chapter);
+        let caption = "The &lt;data&gt; bus, annotated";
+        assert!(
+            chapter
+                .content
+                .contains(&format!("<figcaption>{caption}</figcaption></figure>"))
+        );
+        assert!(
+            chapter
+                .content
+                .contains(&format!(r#"<svg aria-label="{caption}" role="img" "#))
+        );
+        let plain = create_svg_html(&BlockOptions::default(), "+--> ok");
+        assert!(!plain.contains("<figure>"));
+        assert!(!plain.contains("aria-label"));
+    }
+
     #[test]
     fn badascii_preprocessor_run() {
         let input_json = r##"[
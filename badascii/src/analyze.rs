@@ -1,4 +1,6 @@
-use crate::{tc::TextCoordinate, text_buffer::TextBuffer};
+use std::collections::HashSet;
+
+use crate::{rect::Rectangle, tc::TextCoordinate, text_buffer::TextBuffer};
 
 #[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
 pub struct LineSegment {
@@ -22,6 +24,20 @@ impl LineSegment {
         let ey = self.end.y & 0xFF;
         (ey << 24) | (ex << 16) | (sy << 8) | (sx)
     }
+    pub fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.start, self.end).normalize()
+    }
+    /// True if the wire is drawn with the dashed glyphs
+    /// (`=` horizontally, `:` vertically).  This goes by the first
+    /// edge cell along the wire, however short it is, so `+=+` is
+    /// dashed.
+    pub fn is_dashed(&self, tb: &TextBuffer) -> bool {
+        self.iter()
+            .chain([self.end])
+            .filter_map(|pos| tb.get(pos))
+            .find(|ch| matches!(ch, '-' | '=' | '|' | ':' | '/' | '\\'))
+            .is_some_and(|ch| ch == '=' || ch == ':')
+    }
     fn kind(&self) -> Kind {
         let del_x = (self.end.x as i32) - (self.start.x as i32);
         let del_y = (self.end.y as i32) - (self.start.y as i32);
@@ -73,12 +89,15 @@ impl LineSegment {
         let del_y = (self.end.y as i32 - self.start.y as i32).abs();
         del_x.max(del_y) as u32
     }
+    // True if the point lies on the segment, including both ends.
+    fn covers(&self, pos: TextCoordinate) -> bool {
+        pos == self.end || self.iter().any(|p| p == pos)
+    }
+    // Segments of the same kind that touch or overlap can be
+    // drawn as a single stroke.
     fn is_colinear(&self, other: &LineSegment) -> bool {
         (self.kind() == other.kind())
-            && ((self.start == other.start)
-                || (self.end == other.start)
-                || (self.end == other.end)
-                || (self.start == other.end))
+            && (self.covers(other.start) || self.covers(other.end) || other.covers(self.start))
     }
     fn extend(&mut self, other: &LineSegment) {
         assert!(self.is_colinear(other));
@@ -139,25 +158,102 @@ enum Class {
     End,
 }
 
+// The arrows for the other axis are terminals too, so that a
+// wire can turn a corner at an arrow head.
 fn classify_horiz(ch: char) -> Option<Class> {
     match ch {
-        '+' | '<' | '>' => Some(Class::Term),
+        '+' | '<' | '>' | '^' | 'v' | 'x' | '*' | '#' => Some(Class::Term),
         '-' => Some(Class::Edge),
         _ => None,
     }
 }
 
+fn classify_horiz_dashed(ch: char) -> Option<Class> {
+    match ch {
+        '=' => Some(Class::Edge),
+        '-' => None,
+        _ => classify_horiz(ch),
+    }
+}
+
 fn classify_vert(ch: char) -> Option<Class> {
     match ch {
-        '+' | '^' | 'v' => Some(Class::Term),
+        '+' | '^' | 'v' | '<' | '>' | 'x' | '*' | '#' => Some(Class::Term),
         '|' => Some(Class::Edge),
         _ => None,
     }
 }
 
+fn classify_vert_dashed(ch: char) -> Option<Class> {
+    match ch {
+        ':' => Some(Class::Edge),
+        '|' => None,
+        _ => classify_vert(ch),
+    }
+}
+
+// The neighbors of `pos` along a horizontal (or vertical) wire.
+fn neighbors(tb: &TextBuffer, pos: TextCoordinate, horiz: bool) -> [Option<char>; 2] {
+    let (before, after) = if horiz {
+        ((pos.x > 0).then(|| pos.left()), pos.right())
+    } else {
+        ((pos.y > 0).then(|| pos.up()), pos.down())
+    };
+    [before.and_then(|pos| tb.get(pos)), tb.get(after)]
+}
+
+fn is_edge(ch: Option<char>, horiz: bool) -> bool {
+    match ch {
+        Some('-' | '=') => horiz,
+        Some('|' | ':') => !horiz,
+        _ => false,
+    }
+}
+
+// Marks like `x`, `*` and `#` also turn up in ordinary text, so
+// they only end a wire when a line runs into them along the wire,
+// and never when they touch a letter or another terminal.  That
+// keeps `**bold**`, `Tx--->` and `box#12` as labels.  An arrow
+// for the other axis only ends a wire at a corner, so it needs a
+// line of its own axis next to it, or `rcv---->` would lose its `v`.
+fn is_term_in_context(tb: &TextBuffer, pos: TextCoordinate, ch: char, horiz: bool) -> bool {
+    match ch {
+        'x' | '*' | '#' => {
+            let around = neighbors(tb, pos, horiz);
+            around.iter().any(|&ch| is_edge(ch, horiz))
+                && !around
+                    .iter()
+                    .flatten()
+                    .any(|&ch| ch.is_alphanumeric() || classify_horiz(ch) == Some(Class::Term))
+        }
+        '^' | 'v' if horiz => neighbors(tb, pos, false)
+            .iter()
+            .any(|&ch| is_edge(ch, false)),
+        '<' | '>' if !horiz => neighbors(tb, pos, true).iter().any(|&ch| is_edge(ch, true)),
+        _ => true,
+    }
+}
+
+// Classify each cell of the buffer for a horizontal (or vertical)
+// wire, dropping the terminals that the text around them rules out.
+fn classify_in_context(
+    tb: &TextBuffer,
+    cells: impl Iterator<Item = (TextCoordinate, char)>,
+    classify: fn(char) -> Option<Class>,
+    horiz: bool,
+) -> impl Iterator<Item = (TextCoordinate, Class)> {
+    cells.filter_map(move |(pos, ch)| {
+        classify(ch)
+            .filter(|class| *class != Class::Term || is_term_in_context(tb, pos, ch, horiz))
+            .map(|class| (pos, class))
+    })
+}
+
+// A diagonal wire can end in any of the arrows, and the head
+// is drawn along the wire.
 fn classify_diag_down_left(ch: char) -> Option<Class> {
     match ch {
-        '+' => Some(Class::Term),
+        '+' | '<' | '>' | '^' | 'v' => Some(Class::Term),
         '/' => Some(Class::Edge),
         _ => None,
     }
@@ -165,18 +261,22 @@ fn classify_diag_down_left(ch: char) -> Option<Class> {
 
 fn classify_diag_down_right(ch: char) -> Option<Class> {
     match ch {
-        '+' => Some(Class::Term),
+        '+' | '<' | '>' | '^' | 'v' => Some(Class::Term),
         '\\' => Some(Class::Edge),
         _ => None,
     }
 }
 
-fn merge_line_segment(segments: &mut Vec<LineSegment>, segment: LineSegment) {
-    for candidate in segments.iter_mut() {
-        if candidate.is_colinear(&segment) {
-            candidate.extend(&segment);
-            return;
-        }
+// Merging may make a segment reach one that it did not touch
+// before, so the merged segment is merged again until nothing
+// else touches it.
+fn merge_line_segment(segments: &mut Vec<LineSegment>, mut segment: LineSegment) {
+    while let Some(ndx) = segments
+        .iter()
+        .position(|candidate| candidate.is_colinear(&segment))
+    {
+        let candidate = segments.swap_remove(ndx);
+        segment.extend(&candidate);
     }
     segments.push(segment);
 }
@@ -198,11 +298,805 @@ pub fn get_wires(tb: &TextBuffer) -> Vec<LineSegment> {
     segments.extend(get_vertical_line_segments(tb));
     segments.extend(get_diag_up_right_segments(tb));
     segments.extend(get_diag_down_right_segments(tb));
-    let mut segments = merge_colinear(segments);
+    segments.extend(get_open_frames(tb).iter().flat_map(OpenFrame::legs));
+    // Solid and dashed wires that meet end to end are
+    // still separate wires, so merge them separately.
+    let (dashed, solid): (Vec<_>, Vec<_>) = segments.into_iter().partition(|l| l.is_dashed(tb));
+    let mut segments = merge_colinear(solid);
+    segments.extend(merge_colinear(dashed));
     segments.sort_by_key(|l| l.id());
     segments
 }
 
+/// Returns the bounding box of each wire in the buffer, in the
+/// same order as [`get_wires`].
+pub fn bounding_boxes_of_wires(tb: &TextBuffer) -> Vec<Rectangle> {
+    get_wires(tb)
+        .iter()
+        .map(LineSegment::bounding_box)
+        .collect()
+}
+
+/// Returns the bounding box that encloses all of the wires in
+/// the buffer, or `None` if there are no wires.
+pub fn bounding_box_of_wires(tb: &TextBuffer) -> Option<Rectangle> {
+    bounding_boxes_of_wires(tb).into_iter().reduce(|acc, rect| {
+        Rectangle::new(
+            TextCoordinate {
+                x: acc.left().min(rect.left()),
+                y: acc.top().min(rect.top()),
+            },
+            TextCoordinate {
+                x: acc.right_bottom().x.max(rect.right_bottom().x),
+                y: acc.right_bottom().y.max(rect.right_bottom().y),
+            },
+        )
+    })
+}
+
+/// How a wire ends at one of its endpoints.
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
+pub enum Endpoint {
+    /// The wire ends in a free `+` that touches nothing else.
+    Open,
+    /// The wire ends in an arrow head, a junction dot `*`, an
+    /// inversion bubble `o`, a no-connect `x` or a square `#`.
+    Terminated,
+    /// The wire ends on another wire (or the edge of a box).
+    Connected,
+}
+
+/// A wire together with the classification of its endpoints.
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
+pub struct ClassifiedWire {
+    pub wire: LineSegment,
+    pub start: Endpoint,
+    pub end: Endpoint,
+}
+
+fn classify_endpoint(
+    tb: &TextBuffer,
+    wires: &[LineSegment],
+    wire: &LineSegment,
+    pos: TextCoordinate,
+) -> Endpoint {
+    if matches!(
+        tb.get(pos),
+        Some('<' | '>' | '^' | 'v' | 'x' | '#' | '*' | 'o')
+    ) {
+        return Endpoint::Terminated;
+    }
+    let touches = |other: &LineSegment| other.end == pos || other.iter().any(|p| p == pos);
+    if wires.iter().filter(|w| *w != wire).any(touches) {
+        Endpoint::Connected
+    } else {
+        Endpoint::Open
+    }
+}
+
+/// Returns the wires in the buffer (in the same order as
+/// [`get_wires`]), with each endpoint classified as open,
+/// terminated or connected.
+pub fn get_classified_wires(tb: &TextBuffer) -> Vec<ClassifiedWire> {
+    let wires = get_wires(tb);
+    wires
+        .iter()
+        .map(|wire| ClassifiedWire {
+            wire: *wire,
+            start: classify_endpoint(tb, &wires, wire, wire.start),
+            end: classify_endpoint(tb, &wires, wire, wire.end),
+        })
+        .collect()
+}
+
+/// Splits `wires` into the networks they form.  Two wires are
+/// connected when an end of one lies on the other, whatever their
+/// directions, so a diagonal that leaves the end of a horizontal
+/// wire at a `+` is in the same group as it.  Each group keeps the
+/// order the wires had in `wires`.
+pub fn group_wires(wires: &[LineSegment]) -> Vec<Vec<LineSegment>> {
+    let touches = |a: &LineSegment, b: &LineSegment| {
+        a.covers(b.start) || a.covers(b.end) || b.covers(a.start) || b.covers(a.end)
+    };
+    // The index of the group of each wire, merged as links are found.
+    let mut group: Vec<usize> = (0..wires.len()).collect();
+    for ndx in 0..wires.len() {
+        for other in 0..ndx {
+            if group[ndx] != group[other] && touches(&wires[ndx], &wires[other]) {
+                let (from, to) = (group[ndx], group[other]);
+                group
+                    .iter_mut()
+                    .filter(|g| **g == from)
+                    .for_each(|g| *g = to);
+            }
+        }
+    }
+    let mut groups: Vec<(usize, Vec<LineSegment>)> = vec![];
+    for (wire, id) in wires.iter().zip(group) {
+        match groups.iter_mut().find(|(g, _)| *g == id) {
+            Some((_, members)) => members.push(*wire),
+            None => groups.push((id, vec![*wire])),
+        }
+    }
+    groups.into_iter().map(|(_, members)| members).collect()
+}
+
+/// The wires in the buffer, grouped into connected networks.  See
+/// [`group_wires`].
+pub fn get_wire_groups(tb: &TextBuffer) -> Vec<Vec<LineSegment>> {
+    group_wires(&get_wires(tb))
+}
+
+/// The kind of junction formed at a `+`, based on how many of
+/// its up, down, left and right neighbors continue a wire.
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
+pub enum Junction {
+    /// Two wires meet (this includes a `+` in a straight run).
+    Corner,
+    /// Three wires meet.
+    Tee,
+    /// Four wires meet, i.e., two wires cross.
+    Cross,
+}
+
+/// Classify the `+` at `pos` by the number of neighbors that
+/// continue a wire.  Returns `None` if `pos` is not a `+` or
+/// fewer than two neighbors are wires.
+pub fn junction_at(tb: &TextBuffer, pos: TextCoordinate) -> Option<Junction> {
+    if tb.get(pos) != Some('+') {
+        return None;
+    }
+    let horiz = |pos: Option<TextCoordinate>| {
+        pos.and_then(|pos| tb.get(pos))
+            .is_some_and(|ch| classify_horiz(ch).is_some())
+    };
+    let vert = |pos: Option<TextCoordinate>| {
+        pos.and_then(|pos| tb.get(pos))
+            .is_some_and(|ch| classify_vert(ch).is_some())
+    };
+    let degree = [
+        horiz((pos.x > 0).then(|| pos.left())),
+        horiz(Some(pos.right())),
+        vert((pos.y > 0).then(|| pos.up())),
+        vert(Some(pos.down())),
+    ]
+    .into_iter()
+    .filter(|x| *x)
+    .count();
+    match degree {
+        2 => Some(Junction::Corner),
+        3 => Some(Junction::Tee),
+        4 => Some(Junction::Cross),
+        _ => None,
+    }
+}
+
+/// Returns every junction in the buffer, in row major order.
+pub fn get_junctions(tb: &TextBuffer) -> Vec<(TextCoordinate, Junction)> {
+    tb.iter()
+        .filter_map(|(pos, _)| junction_at(tb, pos).map(|j| (pos, j)))
+        .collect()
+}
+
+// The sides of a box may be solid or dashed, or a mix of both.
+const HORIZ: [char; 3] = ['-', '=', '+'];
+const VERT: [char; 3] = ['|', ':', '+'];
+
+/// Finds the boxes in the buffer.  A box is a `+` corner with
+/// a run of `-` (or `+`) to the right and `|` (or `+`) below,
+/// which closes into a rectangle.  If a box is subdivided, only
+/// the smallest box at each top left corner is returned.  When
+/// several lines leave a corner, the box that closes with the
+/// nearest top right corner wins, and then the nearest bottom left,
+/// so the boxes found only depend on the text.
+///
+/// ```
+/// use badascii::{TextBuffer, get_rectangles};
+///
+/// let tb = TextBuffer::with_text("+--+\n|  |\n+--+");
+/// let boxes = get_rectangles(&tb);
+/// assert_eq!(boxes.len(), 1);
+/// assert_eq!(boxes[0].width(), 4);
+/// assert_eq!(boxes[0].height(), 3);
+/// ```
+pub fn get_rectangles(tb: &TextBuffer) -> Vec<Rectangle> {
+    let is =
+        |pos: TextCoordinate, edge: [char; 3]| tb.get(pos).is_some_and(|ch| edge.contains(&ch));
+    // The `+` corners reachable from `start` by following `edge`
+    let corners =
+        |start: TextCoordinate, step: fn(&TextCoordinate) -> TextCoordinate, edge: [char; 3]| {
+            std::iter::successors(Some(step(&start)), move |pos| Some(step(pos)))
+                .take_while(move |pos| is(*pos, edge))
+                .filter(|pos| tb.get(*pos) == Some('+'))
+        };
+    tb.iter()
+        .filter(|(_, ch)| *ch == '+')
+        .filter_map(|(top_left, _)| {
+            corners(top_left, TextCoordinate::right, HORIZ).find_map(|top_right| {
+                corners(top_left, TextCoordinate::down, VERT).find_map(|bottom_left| {
+                    let bottom_right = TextCoordinate {
+                        x: top_right.x,
+                        y: bottom_left.y,
+                    };
+                    let closes = corners(bottom_left, TextCoordinate::right, HORIZ)
+                        .any(|pos| pos == bottom_right)
+                        && corners(top_right, TextCoordinate::down, VERT)
+                            .any(|pos| pos == bottom_right);
+                    closes.then(|| Rectangle::new(top_left, bottom_right))
+                })
+            })
+        })
+        .collect()
+}
+
+/// A problem spotted in a diagram, such as a box that does not
+/// quite close, found by [`diagnose`].
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+pub struct Diagnostic {
+    pub pos: TextCoordinate,
+    pub message: String,
+}
+
+type Step = fn(&TextCoordinate) -> TextCoordinate;
+
+// The boxes that would be outlined if the corners found by walking
+// from `corner` along `horiz` and `vert` were joined up, whether or
+// not the far sides are actually drawn.
+fn candidate_boxes(
+    tb: &TextBuffer,
+    corner: TextCoordinate,
+    horiz: Step,
+    vert: Step,
+) -> Vec<Rectangle> {
+    let arm = |step: Step, edge: [char; 3]| {
+        std::iter::successors(Some(step(&corner)), move |pos| {
+            // Stop at the edge of the buffer rather than wrapping
+            let next = step(pos);
+            (next != *pos).then_some(next)
+        })
+        .take_while(move |pos| tb.get(*pos).is_some_and(|ch| edge.contains(&ch)))
+        .filter(|pos| tb.get(*pos) == Some('+'))
+        .collect::<Vec<_>>()
+    };
+    let across = arm(horiz, HORIZ);
+    let down = arm(vert, VERT);
+    across
+        .iter()
+        .flat_map(|x| {
+            down.iter().map(move |y| {
+                let far = TextCoordinate { x: x.x, y: y.y };
+                Rectangle::new(corner, far).normalize()
+            })
+        })
+        .collect()
+}
+
+// The cells on the outline of `rect` that are not what a box needs
+// there: a `+` at the corners, and the side characters between.
+fn outline_gaps(tb: &TextBuffer, rect: &Rectangle) -> Vec<TextCoordinate> {
+    let (left, top) = (rect.left(), rect.top());
+    let right_bottom = rect.right_bottom();
+    rect.iter_interior()
+        .filter(|pos| {
+            let side = pos.x == left || pos.x == right_bottom.x;
+            let end = pos.y == top || pos.y == right_bottom.y;
+            let ch = tb.get(*pos);
+            match (side, end) {
+                (true, true) => ch != Some('+'),
+                (true, false) => !ch.is_some_and(|ch| VERT.contains(&ch)),
+                (false, true) => !ch.is_some_and(|ch| HORIZ.contains(&ch)),
+                (false, false) => false,
+            }
+        })
+        .collect()
+}
+
+/// Looks for the likely mistakes in a diagram, which would
+/// otherwise just render as something other than intended:
+///
+/// * Boxes that do not quite close, because a single cell of the
+///   outline is missing or wrong (such as a corner drawn with `-`
+///   instead of `+`, or a gap in a side).  These are not found by
+///   [`get_rectangles`], so they are drawn as loose wires.
+/// * Lines that leave a `+` but trail off without ending in
+///   another `+` or an arrow.  These are not wires, so they are
+///   drawn as text.
+///
+/// The diagnostics are in row major order of their positions.
+///
+/// ```
+/// use badascii::{TextBuffer, analyze::diagnose};
+///
+/// let tb = TextBuffer::with_text("+--+\n|  |\n+---");
+/// let diagnostics = diagnose(&tb);
+/// assert_eq!(diagnostics[0].pos.x, 3);
+/// assert_eq!(diagnostics[0].pos.y, 2);
+/// ```
+pub fn diagnose(tb: &TextBuffer) -> Vec<Diagnostic> {
+    let boxes = get_rectangles(tb);
+    // A bracket whose open side is a single cell long looks just
+    // like a box with a gap in that side, but is open on purpose.
+    let brackets = get_open_frames(tb)
+        .into_iter()
+        .filter(|frame| match frame.open {
+            Side::Left | Side::Right => frame.rect.height() == 3,
+            Side::Top | Side::Bottom => frame.rect.width() == 3,
+        })
+        .map(|frame| frame.rect)
+        .collect::<Vec<_>>();
+    let turns: [(Step, Step); 4] = [
+        (TextCoordinate::right, TextCoordinate::down),
+        (TextCoordinate::left, TextCoordinate::down),
+        (TextCoordinate::right, TextCoordinate::up),
+        (TextCoordinate::left, TextCoordinate::up),
+    ];
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+    let mut near_misses = vec![];
+    let mut report = |pos: TextCoordinate, message: &str| {
+        if !diagnostics.iter().any(|d| d.pos == pos) {
+            diagnostics.push(Diagnostic {
+                pos,
+                message: message.into(),
+            });
+        }
+    };
+    for (corner, _) in tb.iter().filter(|(_, ch)| *ch == '+') {
+        for (horiz, vert) in turns {
+            for rect in candidate_boxes(tb, corner, horiz, vert) {
+                // Only boxes with room for a label inside are likely
+                let tiny = rect.width() < 3 || rect.height() < 3;
+                if tiny || boxes.contains(&rect) {
+                    continue;
+                }
+                if let [gap] = outline_gaps(tb, &rect)[..] {
+                    let at_corner = [
+                        rect.left_top(),
+                        rect.right_top(),
+                        rect.left_bottom(),
+                        rect.right_bottom(),
+                    ]
+                    .contains(&gap);
+                    if !at_corner && brackets.contains(&rect) {
+                        continue;
+                    }
+                    near_misses.push(rect);
+                    if at_corner {
+                        report(gap, "box is missing a `+` corner");
+                    } else {
+                        report(gap, "box has a gap in its side");
+                    }
+                }
+            }
+        }
+    }
+    // A line that leaves a `+` but never reaches another `+` or an
+    // arrow is not a wire, so its characters are drawn as text.  The
+    // sides of a box that does not close were already reported.
+    let wired = get_wires(tb)
+        .iter()
+        .flat_map(|wire| wire.iter().chain([wire.end]))
+        .collect::<HashSet<_>>();
+    let runs: [(Step, [char; 2]); 4] = [
+        (TextCoordinate::right, ['-', '=']),
+        (TextCoordinate::left, ['-', '=']),
+        (TextCoordinate::down, ['|', ':']),
+        (TextCoordinate::up, ['|', ':']),
+    ];
+    for (start, _) in tb.iter().filter(|(_, ch)| *ch == '+') {
+        for (step, edge) in runs {
+            let is_edge = |pos: &TextCoordinate| {
+                *pos != start
+                    && !wired.contains(pos)
+                    && tb.get(*pos).is_some_and(|ch| edge.contains(&ch))
+            };
+            let tail = std::iter::successors(Some(step(&start)), |pos| {
+                let next = step(pos);
+                (next != *pos).then_some(next)
+            })
+            .take_while(is_edge)
+            .last();
+            if let Some(tail) = tail
+                && !near_misses.iter().any(|rect| near_boundary(rect, tail))
+            {
+                report(tail, "line does not end in a `+` or an arrow");
+            }
+        }
+    }
+    diagnostics.sort_by_key(|d| (d.pos.y, d.pos.x));
+    diagnostics
+}
+
+/// A side of a box.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Side {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+/// A box with one side left open, like a bracket that groups a few
+/// lines of text.  The `rect` spans the three drawn sides, out to
+/// the ends of the two legs.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OpenFrame {
+    pub rect: Rectangle,
+    pub open: Side,
+}
+
+impl OpenFrame {
+    /// The sides that are drawn, clockwise from the top.
+    pub fn sides(&self) -> Vec<Side> {
+        [Side::Top, Side::Right, Side::Bottom, Side::Left]
+            .into_iter()
+            .filter(|side| *side != self.open)
+            .collect()
+    }
+    /// The two legs, which run from the ends of the side opposite
+    /// the open one.
+    pub fn legs(&self) -> [LineSegment; 2] {
+        let (left_top, right_bottom) = (self.rect.left_top(), self.rect.right_bottom());
+        let (right_top, left_bottom) = (
+            TextCoordinate {
+                x: right_bottom.x,
+                y: left_top.y,
+            },
+            TextCoordinate {
+                x: left_top.x,
+                y: right_bottom.y,
+            },
+        );
+        let segment = |start, end| LineSegment { start, end };
+        match self.open {
+            Side::Top | Side::Bottom => [
+                segment(left_top, left_bottom),
+                segment(right_top, right_bottom),
+            ],
+            Side::Left | Side::Right => [
+                segment(left_top, right_top),
+                segment(left_bottom, right_bottom),
+            ],
+        }
+    }
+}
+
+/// Finds the boxes with one side missing.  An open frame is a side
+/// between two `+` corners, with legs of the same length leaving
+/// both corners in the same direction, whose ends are not joined.
+/// Two sides meeting at a corner are just a bent wire, so they are
+/// not reported.  The legs need not end in a terminal, and are
+/// included in [`get_wires`], so that the frame is drawn.
+///
+/// ```
+/// use badascii::{TextBuffer, analyze::{Side, get_open_frames}};
+///
+/// let tb = TextBuffer::with_text("+--+\n|  |\n|  |");
+/// let frames = get_open_frames(&tb);
+/// assert_eq!(frames.len(), 1);
+/// assert_eq!(frames[0].open, Side::Bottom);
+/// ```
+pub fn get_open_frames(tb: &TextBuffer) -> Vec<OpenFrame> {
+    type Direction = (Step, [char; 3]);
+    let is =
+        |pos: TextCoordinate, edge: [char; 3]| tb.get(pos).is_some_and(|ch| edge.contains(&ch));
+    // The cells past `start` in the direction of `step` along `edge`
+    let run = move |start: TextCoordinate, step: Step, edge: [char; 3]| {
+        std::iter::successors(Some(start), move |pos| {
+            let next = step(pos);
+            (next != *pos && is(next, edge)).then_some(next)
+        })
+        .skip(1)
+    };
+    // The side that is drawn, the direction of the legs, and the
+    // side that is left open.
+    let right = (TextCoordinate::right as Step, HORIZ);
+    let left = (TextCoordinate::left as Step, HORIZ);
+    let down = (TextCoordinate::down as Step, VERT);
+    let up = (TextCoordinate::up as Step, VERT);
+    let shapes: [(Direction, Direction, Side); 4] = [
+        (right, down, Side::Bottom),
+        (right, up, Side::Top),
+        (down, right, Side::Right),
+        (down, left, Side::Left),
+    ];
+    let mut frames = vec![];
+    for (corner, _) in tb.iter().filter(|(_, ch)| *ch == '+') {
+        for ((side_step, side_edge), (leg_step, leg_edge), open) in shapes {
+            let leg = run(corner, leg_step, leg_edge).count();
+            if leg == 0 {
+                continue;
+            }
+            let frame = run(corner, side_step, side_edge)
+                .filter(|pos| tb.get(*pos) == Some('+'))
+                .find_map(|other| {
+                    let end = run(corner, leg_step, leg_edge).last()?;
+                    let other_end = run(other, leg_step, leg_edge).last()?;
+                    if run(other, leg_step, leg_edge).count() != leg
+                        || run(end, side_step, side_edge).any(|pos| pos == other_end)
+                    {
+                        return None;
+                    }
+                    let xs = [corner.x, other.x, end.x, other_end.x];
+                    let ys = [corner.y, other.y, end.y, other_end.y];
+                    let rect = Rectangle::new(
+                        TextCoordinate {
+                            x: xs.into_iter().min()?,
+                            y: ys.into_iter().min()?,
+                        },
+                        TextCoordinate {
+                            x: xs.into_iter().max()?,
+                            y: ys.into_iter().max()?,
+                        },
+                    );
+                    Some(OpenFrame { rect, open })
+                });
+            frames.extend(frame);
+        }
+    }
+    frames
+}
+
+/// A box in a [`Graph`], along with the text inside of it.
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+pub struct Node {
+    pub rect: Rectangle,
+    pub label: String,
+}
+
+/// A wire in a [`Graph`], with the indices of the nodes its
+/// endpoints touch (or `None` if that end is dangling).
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
+pub struct Edge {
+    pub wire: LineSegment,
+    pub from: Option<usize>,
+    pub to: Option<usize>,
+}
+
+/// The boxes and connections of a diagram.
+#[derive(Debug, Default, Eq, PartialEq, Hash, Clone)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+// True if `pos` is on the boundary of `rect`, or in one of the
+// cells just outside it.
+fn near_boundary(rect: &Rectangle, pos: TextCoordinate) -> bool {
+    let (left, top) = (rect.left(), rect.top());
+    let right_bottom = rect.right_bottom();
+    let (right, bottom) = (right_bottom.x, right_bottom.y);
+    let near = pos.x + 1 >= left && pos.x <= right + 1 && pos.y + 1 >= top && pos.y <= bottom + 1;
+    let inside = pos.x > left && pos.x < right && pos.y > top && pos.y < bottom;
+    near && !inside
+}
+
+// True if a wire ending at `pos` is attached to `rect`, which is
+// the case when `pos` is on the border of the box, or is an arrow
+// just outside the box that points into it.
+fn attaches_to(tb: &TextBuffer, rect: &Rectangle, pos: TextCoordinate) -> bool {
+    let (left, top) = (rect.left(), rect.top());
+    let right_bottom = rect.right_bottom();
+    let (right, bottom) = (right_bottom.x, right_bottom.y);
+    let across = (left..=right).contains(&pos.x);
+    let down = (top..=bottom).contains(&pos.y);
+    let on_border = (across && (pos.y == top || pos.y == bottom))
+        || (down && (pos.x == left || pos.x == right));
+    on_border
+        || match tb.get(pos) {
+            Some('>') => down && pos.x + 1 == left,
+            Some('<') => down && pos.x == right + 1,
+            Some('v') => across && pos.y + 1 == top,
+            Some('^') => across && pos.y == bottom + 1,
+            _ => false,
+        }
+}
+
+// True if the wire runs along one of the sides of `rect`.
+pub(crate) fn is_side_of(rect: &Rectangle, wire: &LineSegment) -> bool {
+    let right_bottom = rect.right_bottom();
+    let on = |pos: TextCoordinate| rect.contains(&pos);
+    on(wire.start)
+        && on(wire.end)
+        && ((wire.start.x == wire.end.x
+            && (wire.start.x == rect.left() || wire.start.x == right_bottom.x))
+            || (wire.start.y == wire.end.y
+                && (wire.start.y == rect.top() || wire.start.y == right_bottom.y)))
+}
+
+/// The boxes found by [`get_rectangles`] whose interior is
+/// entirely made up of the `fill` character.
+pub fn get_filled_rectangles(tb: &TextBuffer, fill: char) -> Vec<Rectangle> {
+    get_rectangles(tb)
+        .into_iter()
+        .filter(|rect| {
+            if rect.width() < 3 || rect.height() < 3 {
+                return false;
+            }
+            let inside = Rectangle::new(
+                rect.left_top().right().down(),
+                rect.right_bottom().up().left(),
+            );
+            inside.iter_interior().all(|pos| tb.get(pos) == Some(fill))
+        })
+        .collect()
+}
+
+/// A box, together with the boxes drawn inside of it.
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+pub struct RectNode {
+    pub rect: Rectangle,
+    /// The boxes directly inside this one (boxes nested more
+    /// deeply are children of those).
+    pub children: Vec<RectNode>,
+}
+
+impl RectNode {
+    fn encloses(&self, rect: &Rectangle) -> bool {
+        self.rect != *rect
+            && [
+                rect.left_top(),
+                rect.right_top(),
+                rect.left_bottom(),
+                rect.right_bottom(),
+            ]
+            .iter()
+            .all(|corner| self.rect.contains(corner))
+    }
+    fn insert(nodes: &mut Vec<RectNode>, rect: Rectangle) {
+        match nodes.iter_mut().find(|node| node.encloses(&rect)) {
+            Some(parent) => Self::insert(&mut parent.children, rect),
+            None => nodes.push(RectNode {
+                rect,
+                children: vec![],
+            }),
+        }
+    }
+}
+
+/// Arranges the boxes found by [`get_rectangles`] by containment.
+/// The outermost boxes are returned, with the boxes inside them
+/// as their children, so walking the tree visits outer boxes
+/// before the ones drawn on top of them.
+pub fn rectangle_tree(tb: &TextBuffer) -> Vec<RectNode> {
+    let mut rects = get_rectangles(tb);
+    // Larger boxes first, so a parent is always in the tree before
+    // any of its children.
+    rects.sort_by_key(|rect| std::cmp::Reverse(rect.width() * rect.height()));
+    let mut roots = vec![];
+    for rect in rects {
+        RectNode::insert(&mut roots, rect);
+    }
+    roots
+}
+
+/// A fingerprint of the diagram for finding duplicates.  It only
+/// depends on the contents, so the same diagram placed anywhere
+/// in a buffer of any size has the same fingerprint, while any
+/// change to its shape or labels changes it.  Like
+/// [`TextBuffer::checksum`], on which it is built, the value is
+/// stable across runs.
+pub fn fingerprint(tb: &TextBuffer) -> u64 {
+    tb.checksum()
+}
+
+/// Extracts the structure of the diagram, with one node for
+/// each box found by [`get_rectangles`], and one edge for each
+/// wire that is not part of a box outline.
+pub fn extract_graph(tb: &TextBuffer) -> Graph {
+    let rects = get_rectangles(tb);
+    let nodes = rects
+        .iter()
+        .map(|rect| {
+            let label = tb
+                .words_in(rect)
+                .into_iter()
+                .map(|(_, word)| word)
+                .collect::<Vec<_>>()
+                .join(" ");
+            Node { rect: *rect, label }
+        })
+        .collect();
+    // If a point touches nested boxes, it belongs to the smallest
+    let node_at = |pos: TextCoordinate| {
+        rects
+            .iter()
+            .enumerate()
+            .filter(|(_, rect)| attaches_to(tb, rect, pos))
+            .min_by_key(|(_, rect)| rect.width() * rect.height())
+            .map(|(ndx, _)| ndx)
+    };
+    let edges = get_wires(tb)
+        .into_iter()
+        .filter(|wire| !rects.iter().any(|rect| is_side_of(rect, wire)))
+        .map(|wire| Edge {
+            wire,
+            from: node_at(wire.start),
+            to: node_at(wire.end),
+        })
+        .collect();
+    Graph { nodes, edges }
+}
+
+/// A numbered callout, written as `(1)`, `(2)`, etc.
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
+pub struct Callout {
+    /// The position of the opening paren.
+    pub pos: TextCoordinate,
+    /// The number of digits inside the parens.
+    pub digits: u32,
+}
+
+/// Finds the numbered callouts in the buffer.  To avoid
+/// catching things like `f(1)` in a label, the opening paren
+/// must not follow an alphanumeric character.
+pub fn get_callouts(tb: &TextBuffer) -> Vec<Callout> {
+    tb.iter()
+        .filter(|(pos, ch)| {
+            *ch == '('
+                && !(pos.x > 0
+                    && tb
+                        .get(pos.left())
+                        .is_some_and(|ch| ch.is_ascii_alphanumeric()))
+        })
+        .filter_map(|(pos, _)| {
+            let mut digits = 0;
+            let mut next = pos.right();
+            while tb.get(next).is_some_and(|ch| ch.is_ascii_digit()) {
+                digits += 1;
+                next = next.right();
+            }
+            (digits > 0 && tb.get(next) == Some(')')).then_some(Callout { pos, digits })
+        })
+        .collect()
+}
+
+/// A heading underlined with a run of `-` that spans exactly
+/// the columns of the label above it:
+///
+/// ```text
+/// Title
+/// -----
+/// ```
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
+pub struct Heading {
+    /// The run of `-` under the label.
+    pub rule: LineSegment,
+}
+
+/// Finds the underlined headings in the buffer.  The rule must be
+/// a bare run of `-` with nothing on either side, so that it is
+/// never part of a wire, and the label above it must start and end
+/// in the same columns as the rule.
+pub fn get_headings(tb: &TextBuffer) -> Vec<Heading> {
+    let is_label = |pos: TextCoordinate| {
+        tb.get(pos)
+            .is_some_and(|ch| !HORIZ.contains(&ch) && !VERT.contains(&ch))
+    };
+    let mut headings = vec![];
+    for (start, ch) in tb.iter() {
+        if ch != '-' || start.y == 0 || (start.x > 0 && tb.get(start.left()).is_some()) {
+            continue;
+        }
+        let mut end = start;
+        while tb.get(end.right()) == Some('-') {
+            end = end.right();
+        }
+        let rule = LineSegment { start, end };
+        let above = |x: u32| TextCoordinate { x, y: start.y - 1 };
+        if end.x > start.x
+            && tb.get(end.right()).is_none()
+            && is_label(above(start.x))
+            && is_label(above(end.x))
+            && (start.x..=end.x).all(|x| tb.get(above(x)).is_none() || is_label(above(x)))
+            && (start.x == 0 || tb.get(above(start.x - 1)).is_none())
+            && tb.get(above(end.x + 1)).is_none()
+        {
+            headings.push(Heading { rule });
+        }
+    }
+    headings
+}
+
 const EOB: (TextCoordinate, Class) = (
     TextCoordinate {
         x: 100_000,
@@ -268,19 +1162,33 @@ where
 }
 
 fn get_vertical_line_segments(tb: &TextBuffer) -> Vec<LineSegment> {
-    line_segment_finder(
-        tb.iter_vert()
-            .filter_map(|(pos, ch)| classify_vert(ch).map(|k| (pos, k))),
-        |track, candidate| track.x == candidate.x && track.y + 1 == candidate.y,
-    )
+    let valid_next = |track: &TextCoordinate, candidate: &TextCoordinate| {
+        track.x == candidate.x && track.y + 1 == candidate.y
+    };
+    let mut lines = line_segment_finder(
+        classify_in_context(tb, tb.iter_vert(), classify_vert, false),
+        valid_next,
+    );
+    lines.extend(line_segment_finder(
+        classify_in_context(tb, tb.iter_vert(), classify_vert_dashed, false),
+        valid_next,
+    ));
+    lines
 }
 
 fn get_horizontal_line_segments(tb: &TextBuffer) -> Vec<LineSegment> {
-    line_segment_finder(
-        tb.iter()
-            .filter_map(|(pos, ch)| classify_horiz(ch).map(|k| (pos, k))),
-        |track, candidate| track.y == candidate.y && track.x + 1 == candidate.x,
-    )
+    let valid_next = |track: &TextCoordinate, candidate: &TextCoordinate| {
+        track.y == candidate.y && track.x + 1 == candidate.x
+    };
+    let mut lines = line_segment_finder(
+        classify_in_context(tb, tb.iter(), classify_horiz, true),
+        valid_next,
+    );
+    lines.extend(line_segment_finder(
+        classify_in_context(tb, tb.iter(), classify_horiz_dashed, true),
+        valid_next,
+    ));
+    lines
 }
 
 fn get_diag_down_right_segments(tb: &TextBuffer) -> Vec<LineSegment> {
@@ -377,4 +1285,542 @@ mod tests {
         "#]];
         expect.assert_debug_eq(&wires);
     }
+
+    #[test]
+    fn test_diagonal_bounding_box() {
+        const INITIAL_TEXT: &str = "
++
+ \\
+  \\
+   +
+";
+        let mut buffer = TextBuffer::new(20, 20);
+        buffer.paste(INITIAL_TEXT, TextCoordinate { x: 2, y: 2 });
+        let boxes = bounding_boxes_of_wires(&buffer);
+        assert_eq!(
+            boxes,
+            vec![Rectangle::new(
+                TextCoordinate { x: 2, y: 3 },
+                TextCoordinate { x: 5, y: 6 }
+            )]
+        );
+        assert_eq!(boxes[0].width(), 4);
+        assert_eq!(boxes[0].height(), 4);
+    }
+
+    #[test]
+    fn test_aggregate_bounding_box() {
+        const INITIAL_TEXT: &str = "
++--+
+    +
+   /
+  /
+ +
+";
+        let mut buffer = TextBuffer::new(20, 20);
+        buffer.paste(INITIAL_TEXT, TextCoordinate { x: 0, y: 0 });
+        assert_eq!(bounding_boxes_of_wires(&buffer).len(), 2);
+        assert_eq!(
+            bounding_box_of_wires(&buffer),
+            Some(Rectangle::new(
+                TextCoordinate { x: 0, y: 1 },
+                TextCoordinate { x: 4, y: 5 }
+            ))
+        );
+        assert_eq!(bounding_box_of_wires(&TextBuffer::new(5, 5)), None);
+    }
+
+    #[test]
+    fn test_marks_in_labels_are_not_wire_ends() {
+        assert_eq!(get_wires(&TextBuffer::with_text("**bold**")), vec![]);
+        assert_eq!(get_wires(&TextBuffer::with_text("Tx--->")), vec![]);
+        assert_eq!(get_wires(&TextBuffer::with_text("box#12")), vec![]);
+        assert_eq!(get_wires(&TextBuffer::with_text("see ## notes")), vec![]);
+        // The same marks still end a wire that runs into them
+        let wires = get_wires(&TextBuffer::with_text("+--x Tx\n\n*-->"));
+        assert_eq!(
+            wires,
+            vec![
+                LineSegment {
+                    start: TextCoordinate { x: 0, y: 0 },
+                    end: TextCoordinate { x: 3, y: 0 },
+                },
+                LineSegment {
+                    start: TextCoordinate { x: 0, y: 2 },
+                    end: TextCoordinate { x: 3, y: 2 },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_classified_endpoints() {
+        const CUP_EXAMPLE: &str = "
++-----+
+      |
+      |<-----+ 
+      |
++-----+
+";
+        let mut text_buffer = TextBuffer::new(20, 20);
+        text_buffer.paste(CUP_EXAMPLE, TextCoordinate { x: 1, y: 1 });
+        let endpoints = get_classified_wires(&text_buffer)
+            .into_iter()
+            .map(|w| (w.start, w.end))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            endpoints,
+            vec![
+                (Endpoint::Open, Endpoint::Connected),
+                (Endpoint::Terminated, Endpoint::Open),
+                (Endpoint::Connected, Endpoint::Connected),
+                (Endpoint::Open, Endpoint::Connected),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dots_and_bubbles_terminate() {
+        let buffer = TextBuffer::with_text("*---+\n\n+---*");
+        let endpoints = get_classified_wires(&buffer)
+            .into_iter()
+            .map(|w| (w.start, w.end))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            endpoints,
+            vec![
+                (Endpoint::Terminated, Endpoint::Open),
+                (Endpoint::Open, Endpoint::Terminated),
+            ]
+        );
+        // An inversion bubble on a pin
+        let buffer = TextBuffer::with_text("o---+");
+        let wire = LineSegment {
+            start: TextCoordinate { x: 0, y: 0 },
+            end: TextCoordinate { x: 4, y: 0 },
+        };
+        assert_eq!(
+            classify_endpoint(&buffer, &[wire], &wire, wire.start),
+            Endpoint::Terminated
+        );
+    }
+
+    #[test]
+    fn test_tee_junction() {
+        const INITIAL_TEXT: &str = "
+    |
++---+---+
+";
+        let mut buffer = TextBuffer::new(20, 20);
+        buffer.paste(INITIAL_TEXT, TextCoordinate { x: 2, y: 2 });
+        assert_eq!(
+            junction_at(&buffer, TextCoordinate { x: 6, y: 4 }),
+            Some(Junction::Tee)
+        );
+        assert_eq!(junction_at(&buffer, TextCoordinate { x: 2, y: 4 }), None);
+    }
+
+    #[test]
+    fn test_cross_junction() {
+        const INITIAL_TEXT: &str = "
+    +
+    |
++---+---+
+    |
+    +
+";
+        let mut buffer = TextBuffer::new(20, 20);
+        buffer.paste(INITIAL_TEXT, TextCoordinate { x: 2, y: 2 });
+        assert_eq!(
+            get_junctions(&buffer),
+            vec![(TextCoordinate { x: 6, y: 5 }, Junction::Cross)]
+        );
+    }
+
+    #[test]
+    fn test_callouts() {
+        let buffer = TextBuffer::with_text("(1) f(2) (12) (a) ()");
+        assert_eq!(
+            get_callouts(&buffer),
+            vec![
+                Callout {
+                    pos: TextCoordinate { x: 0, y: 0 },
+                    digits: 1
+                },
+                Callout {
+                    pos: TextCoordinate { x: 9, y: 0 },
+                    digits: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_headings() {
+        let buffer = TextBuffer::with_text(
+            "
+ +---------+
+ | My Box  |
+ | ------  |
+ | Inputs  |
+ | -----+  |
+ | wider   |
+ | ------- |
+ +---------+
+",
+        );
+        // Only the rule that spans its label exactly is a heading.
+        // The one running into a `+` and the one overhanging `wider`
+        // are left alone.
+        assert_eq!(
+            get_headings(&buffer),
+            vec![Heading {
+                rule: LineSegment {
+                    start: TextCoordinate { x: 3, y: 3 },
+                    end: TextCoordinate { x: 8, y: 3 },
+                }
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diagonal_joins_orthogonal_group() {
+        let buffer = TextBuffer::with_text(
+            "
++---+
+     \\
+      +---+
+
++--+
+",
+        );
+        let groups = get_wire_groups(&buffer);
+        assert_eq!(groups.len(), 2);
+        let mut joined = groups
+            .into_iter()
+            .find(|group| group.len() == 3)
+            .expect("the diagonal joins the two horizontal wires");
+        joined.sort_by_key(|wire| (wire.start.y, wire.start.x));
+        assert_eq!(
+            joined,
+            vec![
+                LineSegment {
+                    start: TextCoordinate { x: 0, y: 1 },
+                    end: TextCoordinate { x: 4, y: 1 },
+                },
+                LineSegment {
+                    start: TextCoordinate { x: 4, y: 1 },
+                    end: TextCoordinate { x: 6, y: 3 },
+                },
+                LineSegment {
+                    start: TextCoordinate { x: 6, y: 3 },
+                    end: TextCoordinate { x: 10, y: 3 },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_rectangles() {
+        const INITIAL_TEXT: &str = "
++----+   +--+
+|    |---|  |
++----+   |  |
+         +--+
+  +--
+  |
+";
+        let mut buffer = TextBuffer::new(20, 20);
+        buffer.paste(INITIAL_TEXT, TextCoordinate { x: 1, y: 1 });
+        let boxes = get_rectangles(&buffer);
+        assert_eq!(
+            boxes,
+            vec![
+                Rectangle::new(TextCoordinate { x: 1, y: 2 }, TextCoordinate { x: 6, y: 4 }),
+                Rectangle::new(
+                    TextCoordinate { x: 10, y: 2 },
+                    TextCoordinate { x: 13, y: 5 }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shared_corners_pick_nearest_box() {
+        const INITIAL_TEXT: &str = "
++--+--+-->
+|  |  |
++--+--+
+|     |
++-----+
+";
+        let expected = |dx: u32, dy: u32| {
+            let rect = |x0, y0, x1, y1| {
+                Rectangle::new(
+                    TextCoordinate {
+                        x: x0 + dx,
+                        y: y0 + dy,
+                    },
+                    TextCoordinate {
+                        x: x1 + dx,
+                        y: y1 + dy,
+                    },
+                )
+            };
+            vec![rect(0, 1, 3, 3), rect(3, 1, 6, 3), rect(0, 3, 6, 5)]
+        };
+        let buffer = TextBuffer::with_text(INITIAL_TEXT);
+        assert_eq!(get_rectangles(&buffer), expected(0, 0));
+        assert_eq!(get_rectangles(&buffer), get_rectangles(&buffer.clone()));
+        let mut moved = TextBuffer::new(20, 30);
+        moved.paste(INITIAL_TEXT, TextCoordinate { x: 7, y: 4 });
+        assert_eq!(get_rectangles(&moved), expected(7, 4));
+    }
+
+    #[test]
+    fn test_diagnose_unclosed_box() {
+        const INITIAL_TEXT: &str = "
++-----+      +----+
+| in  +----->| ok |
++-----       +----+
+
+  +----   +--+
+  |
+";
+        let buffer = TextBuffer::with_text(INITIAL_TEXT);
+        let found = diagnose(&buffer)
+            .into_iter()
+            .map(|d| ((d.pos.x, d.pos.y), d.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                ((6, 3), "box is missing a `+` corner".to_string()),
+                ((6, 5), "line does not end in a `+` or an arrow".to_string()),
+                ((2, 6), "line does not end in a `+` or an arrow".to_string()),
+            ]
+        );
+        // Closing the box leaves only the stray line below it
+        let mut fixed = buffer.clone();
+        fixed.set_text(&TextCoordinate { x: 6, y: 3 }, Some('+'));
+        assert_eq!(diagnose(&fixed).len(), 2);
+    }
+
+    #[test]
+    fn test_extract_graph() {
+        const INITIAL_TEXT: &str = "
++-----+        +-----+
+| src +------->| dst |
++-----+        +-----+
+   +----+
+";
+        let buffer = TextBuffer::with_text(INITIAL_TEXT);
+        let graph = extract_graph(&buffer);
+        assert_eq!(
+            graph
+                .nodes
+                .iter()
+                .map(|n| n.label.as_str())
+                .collect::<Vec<_>>(),
+            vec!["src", "dst"]
+        );
+        assert_eq!(
+            graph
+                .edges
+                .iter()
+                .map(|e| (e.from, e.to))
+                .collect::<Vec<_>>(),
+            vec![(Some(0), Some(1)), (None, None)]
+        );
+    }
+
+    #[test]
+    fn test_extract_graph_startup_screen() {
+        let buffer = TextBuffer::with_text(include_str!("startup_screen.txt"));
+        let graph = extract_graph(&buffer);
+        // The frame, the zoom help and the little `OO` box.  The
+        // boxes with a title in their top edge are not closed by
+        // `+` corners all the way round, so they are only wires.
+        assert_eq!(
+            graph
+                .nodes
+                .iter()
+                .map(|n| (n.rect.left_top(), n.rect.right_bottom()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    TextCoordinate { x: 0, y: 0 },
+                    TextCoordinate { x: 96, y: 39 }
+                ),
+                (
+                    TextCoordinate { x: 79, y: 11 },
+                    TextCoordinate { x: 96, y: 16 }
+                ),
+                (
+                    TextCoordinate { x: 79, y: 26 },
+                    TextCoordinate { x: 83, y: 28 }
+                ),
+            ]
+        );
+        assert_eq!(graph.nodes[2].label, "OO");
+        // Every other wire is an edge: the outlines of the titled
+        // boxes and the examples inside them, the `Move Me` shape,
+        // and the free standing arrows.  None of them touch a box,
+        // even where they pass close to one.
+        assert!(
+            graph
+                .edges
+                .iter()
+                .all(|edge| edge.from.is_none() && edge.to.is_none())
+        );
+        assert_eq!(
+            graph
+                .edges
+                .iter()
+                .map(|edge| (
+                    edge.wire.start.x,
+                    edge.wire.start.y,
+                    edge.wire.end.x,
+                    edge.wire.end.y
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (4, 4, 7, 4),
+                (17, 4, 24, 4),
+                (27, 4, 31, 4),
+                (42, 4, 46, 4),
+                (52, 4, 55, 4),
+                (64, 4, 90, 4),
+                (60, 5, 62, 5),
+                (83, 5, 87, 5),
+                (77, 5, 77, 6),
+                (54, 7, 55, 7),
+                (60, 7, 61, 7),
+                (66, 5, 66, 7),
+                (73, 5, 73, 7),
+                (83, 7, 87, 7),
+                (57, 7, 57, 9),
+                (56, 9, 58, 9),
+                (69, 7, 69, 9),
+                (77, 8, 77, 9),
+                (77, 9, 80, 9),
+                (4, 4, 4, 10),
+                (24, 4, 24, 10),
+                (4, 10, 24, 10),
+                (27, 4, 27, 10),
+                (46, 4, 46, 10),
+                (27, 10, 46, 10),
+                (52, 4, 52, 10),
+                (90, 4, 90, 10),
+                (52, 10, 90, 10),
+                (12, 14, 24, 14),
+                (22, 16, 24, 14),
+                (29, 15, 38, 15),
+                (12, 14, 14, 16),
+                (14, 16, 22, 16),
+                (29, 23, 31, 23),
+                (42, 23, 48, 23),
+                (21, 23, 21, 24),
+                (62, 23, 62, 24),
+                (63, 30, 66, 30),
+                (61, 35, 65, 35),
+                (76, 35, 79, 35),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_placement() {
+        let diagram = "+--+\n|ok|-->\n+--+";
+        let mut near = TextBuffer::new(10, 20);
+        near.paste(diagram, TextCoordinate { x: 0, y: 0 });
+        let mut far = TextBuffer::new(30, 60);
+        far.paste(diagram, TextCoordinate { x: 17, y: 9 });
+        assert_eq!(fingerprint(&near), fingerprint(&far));
+        assert_eq!(
+            fingerprint(&near),
+            fingerprint(&TextBuffer::with_text(diagram))
+        );
+        far.set_text(&TextCoordinate { x: 18, y: 10 }, Some('k'));
+        assert_ne!(fingerprint(&near), fingerprint(&far));
+        let arrow = TextBuffer::with_text("+--+\n|ok|--<\n+--+");
+        assert_ne!(fingerprint(&near), fingerprint(&arrow));
+    }
+
+    #[test]
+    fn test_short_wires_are_dashed() {
+        let dashed = |text: &str| {
+            let tb = TextBuffer::with_text(text);
+            get_wires(&tb)
+                .iter()
+                .map(|wire| wire.is_dashed(&tb))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(dashed("+=+"), vec![true]);
+        assert_eq!(dashed("+\n:\n+"), vec![true]);
+        assert_eq!(dashed("+=>"), vec![true]);
+        assert_eq!(dashed("+==+"), vec![true]);
+        assert_eq!(dashed("+-+"), vec![false]);
+        assert_eq!(dashed("+\n|\n+"), vec![false]);
+    }
+
+    #[test]
+    fn test_get_dashed_rectangles() {
+        let tb = TextBuffer::with_text("+==+  +==+\n:  :  |  |\n+==+  +--+");
+        assert_eq!(
+            get_rectangles(&tb),
+            vec![
+                Rectangle::new(TextCoordinate { x: 0, y: 0 }, TextCoordinate { x: 3, y: 2 }),
+                Rectangle::new(TextCoordinate { x: 6, y: 0 }, TextCoordinate { x: 9, y: 2 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_open_frames() {
+        let tb = TextBuffer::with_text("+------+\n| a  b |\n|      |\n\n+--+\n|  |\n+--+");
+        let frames = get_open_frames(&tb);
+        assert_eq!(
+            frames,
+            vec![OpenFrame {
+                rect: Rectangle::new(TextCoordinate { x: 0, y: 0 }, TextCoordinate { x: 7, y: 2 }),
+                open: Side::Bottom,
+            }]
+        );
+        assert_eq!(frames[0].sides(), vec![Side::Top, Side::Right, Side::Left]);
+        // The closed box is not an open frame, and the frame is
+        // drawn as its three edges.
+        assert_eq!(get_rectangles(&tb).len(), 1);
+        let top = TextBuffer::with_text("+------+\n| a  b |\n|      |");
+        assert_eq!(get_wires(&top).len(), 3);
+        let bracket = TextBuffer::with_text("+--\n|\n|\n+--");
+        assert_eq!(get_open_frames(&bracket)[0].open, Side::Right);
+    }
+
+    #[test]
+    fn test_rectangle_tree() {
+        let tb = TextBuffer::with_text(
+            "
++----------+  +--+
+| +------+ |  |  |
+| | +--+ | |  +--+
+| | |  | | |
+| | +--+ | |
+| +------+ |
++----------+
+",
+        );
+        let tree = rectangle_tree(&tb);
+        assert_eq!(tree.len(), 2);
+        let outer = &tree[0];
+        assert_eq!(outer.rect.left_top(), TextCoordinate { x: 0, y: 1 });
+        assert_eq!(outer.children.len(), 1);
+        let middle = &outer.children[0];
+        assert_eq!(middle.rect.left_top(), TextCoordinate { x: 2, y: 2 });
+        assert_eq!(middle.children.len(), 1);
+        let inner = &middle.children[0];
+        assert_eq!(inner.rect.left_top(), TextCoordinate { x: 4, y: 3 });
+        assert!(inner.children.is_empty());
+        assert!(tree[1].children.is_empty());
+    }
 }
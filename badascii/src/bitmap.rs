@@ -1,20 +1,44 @@
 use std::sync::Arc;
 
-use ab_glyph::ScaleFont;
 use rasterize::{
-    ActiveEdgeRasterizer, BBox, Color, Image, ImageMut, LinColor, LineCap, LineJoin, Scene,
-    StrokeStyle, Transform,
+    ActiveEdgeRasterizer, BBox, Color, FillRule, Image, ImageMut, LinColor, LineCap, LineJoin,
+    Scene, StrokeStyle, Transform,
 };
 use roughr::core::{Drawable, OpSetType, OpType};
 
-use crate::{RenderJob, render::vec2, tc::TextCoordinate};
+use crate::{RenderJob, TextBuffer, render::vec2};
 
 type Error = Box<dyn std::error::Error>;
 
+// Opacity used for the parts of the diagram outside of the focus.
+const DIMMED_OPACITY: f32 = 0.3;
+
+// The color faded to `DIMMED_OPACITY`.  The colors are
+// premultiplied, so every channel is scaled.
+fn dimmed(color: LinColor) -> LinColor {
+    LinColor::new(
+        color.red() * DIMMED_OPACITY,
+        color.green() * DIMMED_OPACITY,
+        color.blue() * DIMMED_OPACITY,
+        color.alpha() * DIMMED_OPACITY,
+    )
+}
+
 pub fn stroke_opset(ops: Drawable<f32>, color: LinColor) -> Scene {
+    let line_cap = match ops.options.line_cap {
+        Some(roughr::core::LineCap::Butt) => LineCap::Butt,
+        Some(roughr::core::LineCap::Square) => LineCap::Square,
+        Some(roughr::core::LineCap::Round) | None => LineCap::Round,
+    };
+    let line_join = match ops.options.line_join {
+        Some(roughr::core::LineJoin::Miter { limit }) => LineJoin::Miter(limit),
+        Some(roughr::core::LineJoin::Round) => LineJoin::Round,
+        Some(roughr::core::LineJoin::Bevel) => LineJoin::Bevel,
+        None => LineJoin::default(),
+    };
     let mut scenes = vec![];
     for op_set in ops.sets {
-        if op_set.op_set_type != OpSetType::Path {
+        if !matches!(op_set.op_set_type, OpSetType::Path | OpSetType::FillPath) {
             continue;
         }
         let mut path = rasterize::PathBuilder::new();
@@ -35,74 +59,210 @@ pub fn stroke_opset(ops: Drawable<f32>, color: LinColor) -> Scene {
                 }
             }
         }
+        if op_set.op_set_type == OpSetType::FillPath {
+            scenes.push(Scene::fill(
+                path.build().into(),
+                Arc::new(color),
+                FillRule::NonZero,
+            ));
+            continue;
+        }
         scenes.push(Scene::stroke(
             path.build().into(),
             Arc::new(color),
             StrokeStyle {
                 width: 1.0,
-                line_join: LineJoin::default(),
-                line_cap: LineCap::Round,
+                line_join,
+                line_cap,
             },
         ));
     }
     Scene::group(scenes)
 }
 
+/// Render the job with the bundled Hack font.  A `background` of
+/// `"none"` (or an empty one) leaves the image transparent where
+/// nothing is drawn.
+///
+/// The image is `scale` device pixels for each pixel of the job, so
+/// a `scale` of 2 gives a sharp image for a high density display.
+/// Strokes and labels grow together, and the job itself is unchanged.
 pub fn render(
     job: &RenderJob,
     color: &str,
     background: &str,
+    scale: f32,
 ) -> Result<rasterize::Layer<LinColor>, Error> {
-    use ab_glyph::{Font, FontRef, Glyph, point};
+    render_with_font(job, color, background, scale, None)
+}
 
-    let font = FontRef::try_from_slice(include_bytes!("../font/Hack-Regular.ttf"))?;
+/// Render the job using the given TTF font data for the labels.
+/// If `font` is `None`, the bundled Hack font is used.
+pub fn render_with_font(
+    job: &RenderJob,
+    color: &str,
+    background: &str,
+    scale: f32,
+    font: Option<&[u8]>,
+) -> Result<rasterize::Layer<LinColor>, Error> {
+    check_scale(scale)?;
+    let font = load_font(font)?;
     let color = color.parse::<LinColor>()?;
-    let delta_x = job.width / job.text.size().num_cols as f32;
-    let delta_y = job.height / job.text.size().num_rows as f32;
-    let (labels, drawables) = job.invoke();
-    let pos_map = |pos: TextCoordinate| {
-        vec2(pos.x as f32 * delta_x, pos.y as f32 * delta_y) + vec2(0.5 * delta_x, 0.5 * delta_y)
+    let text_color = match &job.text_color {
+        Some(text_color) => text_color.parse::<LinColor>()?,
+        None => color,
+    };
+    let (labels, drawables, out_of_focus) = match &job.focus {
+        Some(focus) => job.invoke_focused(focus),
+        None => {
+            let (labels, drawables) = job.invoke();
+            (labels, drawables, vec![])
+        }
     };
-    let elements = drawables
-        .into_iter()
-        .map(|op| stroke_opset(op, color))
-        .collect::<Vec<_>>();
-    let background = background.parse::<LinColor>().ok();
-    let scene = Scene::group(elements);
-    let mut image = scene.render(
+    let mut elements = vec![];
+    if let Some(fill_color) = &job.fill_color {
+        let fill_color = fill_color.parse::<LinColor>()?;
+        for (left_top, right_bottom) in job.fills() {
+            let mut path = rasterize::PathBuilder::new();
+            path.move_to((left_top.x as f64, left_top.y as f64))
+                .line_to((right_bottom.x as f64, left_top.y as f64))
+                .line_to((right_bottom.x as f64, right_bottom.y as f64))
+                .line_to((left_top.x as f64, right_bottom.y as f64))
+                .close();
+            elements.push(Scene::fill(
+                path.build().into(),
+                Arc::new(fill_color),
+                FillRule::NonZero,
+            ));
+        }
+    }
+    elements.extend(drawables.into_iter().map(|op| stroke_opset(op, color)));
+    elements.extend(
+        out_of_focus
+            .into_iter()
+            .map(|op| stroke_opset(op, dimmed(color))),
+    );
+    // As in the SVG, a background of "none" is left transparent,
+    // and anything else must be a color.
+    let background = match background {
+        "" | "none" => None,
+        background => Some(background.parse::<LinColor>()?),
+    };
+    let mut image = rasterize_scene(job, Scene::group(elements), background, scale);
+    draw_labels(job, &labels, &font, &mut image, text_color, scale);
+    Ok(image)
+}
+
+/// Render only the labels of the job (in the bundled Hack font) as
+/// a mask.  Each pixel is white, with an alpha that is the coverage
+/// of the glyphs, and the wires, boxes and fills are left out.  The
+/// mask lines up with the output of [`render`] at the same `scale`,
+/// so the text can be colored and composited on its own.
+pub fn render_text_mask(job: &RenderJob, scale: f32) -> Result<rasterize::Layer<LinColor>, Error> {
+    check_scale(scale)?;
+    let font = load_font(None)?;
+    let mut image = rasterize_scene(job, Scene::group(vec![]), None, scale);
+    let white = LinColor::new(1.0, 1.0, 1.0, 1.0);
+    draw_labels(job, &job.labels(), &font, &mut image, white, scale);
+    Ok(image)
+}
+
+fn check_scale(scale: f32) -> Result<(), Error> {
+    if scale.is_finite() && scale > 0.0 {
+        Ok(())
+    } else {
+        Err(format!("The scale must be a positive number, not {scale}").into())
+    }
+}
+
+fn load_font(font: Option<&[u8]>) -> Result<ab_glyph::FontRef<'_>, Error> {
+    Ok(ab_glyph::FontRef::try_from_slice(
+        font.unwrap_or(include_bytes!("../font/Hack-Regular.ttf")),
+    )?)
+}
+
+// The image covers the canvas and its padding, with the origin of
+// the job at the top left of the canvas, and everything magnified
+// by `scale`.
+fn rasterize_scene(
+    job: &RenderJob,
+    scene: Scene,
+    background: Option<LinColor>,
+    scale: f32,
+) -> rasterize::Layer<LinColor> {
+    let scale = scale as f64;
+    scene.render(
         &ActiveEdgeRasterizer::default(),
-        Transform::identity(),
-        Some(BBox::new((0.0, 0.0), (job.width as f64, job.height as f64))),
+        Transform::new_scale(scale, scale)
+            .pre_translate((job.padding - job.x0) as f64, (job.padding - job.y0) as f64),
+        Some(BBox::new(
+            (0.0, 0.0),
+            (
+                ((job.width + 2.0 * job.padding) as f64 * scale).round(),
+                ((job.height + 2.0 * job.padding) as f64 * scale).round(),
+            ),
+        )),
         background,
-    );
+    )
+}
+
+fn draw_labels(
+    job: &RenderJob,
+    labels: &TextBuffer,
+    font: &ab_glyph::FontRef,
+    image: &mut rasterize::Layer<LinColor>,
+    text_color: LinColor,
+    scale: f32,
+) {
+    use ab_glyph::{Font, Glyph, ScaleFont, point};
+
+    let delta_x = job.width / job.text.size().num_cols as f32;
     let shape = image.shape();
     let mut im_mut = image.as_mut();
     let data_mut = im_mut.data_mut();
-    let text_size = delta_x.min(delta_y) * 1.6;
-    let ascent = font.as_scaled(text_size).ascent();
-    for (coord, word) in labels.iter() {
-        let center = pos_map(coord);
-        let glyph: Glyph = font.glyph_id(word).with_scale_and_position(
-            text_size,
-            point(center.x - delta_x / 2.0, center.y - delta_y / 2.0 + ascent),
+    let text_size = job.label_size() * scale;
+    let scaled = font.as_scaled(text_size);
+    // Glyphs sit one per cell, unless the labels are proportional,
+    // in which case they are spaced by their own advance.
+    let advance = |ch: char| {
+        if job.proportional_labels {
+            scaled.h_advance(font.glyph_id(ch))
+        } else {
+            delta_x * scale
+        }
+    };
+    for (coord, word, baseline) in job.label_runs(labels) {
+        let opacity = if job.focus.is_some_and(|focus| !focus.contains(&coord)) {
+            DIMMED_OPACITY
+        } else {
+            1.0
+        };
+        let baseline = vec2(
+            (baseline.x + job.padding - job.x0) * scale,
+            (baseline.y + job.padding - job.y0) * scale,
         );
-        if let Some(q) = font.outline_glyph(glyph) {
-            let bound = q.px_bounds();
-            q.draw(|x, y, c| {
-                let x = bound.min.x + x as f32;
-                let y = bound.min.y + y as f32;
-                let ndx = shape.offset(y as usize, x as usize);
-                data_mut[ndx] = data_mut[ndx].lerp(color, c);
-            })
+        let width = word.chars().map(advance).sum::<f32>();
+        let mut x = baseline.x - width / 2.0;
+        for ch in word.chars() {
+            let glyph: Glyph = font
+                .glyph_id(ch)
+                .with_scale_and_position(text_size, point(x, baseline.y));
+            x += advance(ch);
+            if let Some(q) = font.outline_glyph(glyph) {
+                let bound = q.px_bounds();
+                q.draw(|x, y, c| {
+                    let x = bound.min.x + x as f32;
+                    let y = bound.min.y + y as f32;
+                    let ndx = shape.offset(y as usize, x as usize);
+                    data_mut[ndx] = data_mut[ndx].lerp(text_color, c * opacity);
+                })
+            }
         }
     }
-    Ok(image)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::TextBuffer;
-
     use super::*;
 
     #[test]
@@ -112,7 +272,187 @@ mod tests {
         let path = std::path::Path::new(r"image.png");
         let file = std::fs::File::create(path).unwrap();
         let w = std::io::BufWriter::new(file);
-        let img = render(&job, "#FFFFFF", "#000000").unwrap();
+        let img = render(&job, "#FFFFFF", "#000000", 1.0).unwrap();
         img.write_png(w).unwrap();
     }
+
+    #[test]
+    fn test_custom_font() {
+        let tb = TextBuffer::with_text("+--> ok");
+        let job = RenderJob::formal(tb);
+        let font = include_bytes!("../font/Hack-Regular.ttf");
+        assert!(render_with_font(&job, "#FFFFFF", "#000000", 1.0, Some(font)).is_ok());
+        assert!(render_with_font(&job, "#FFFFFF", "#000000", 1.0, Some(b"not a font")).is_err());
+    }
+
+    #[test]
+    fn test_text_mask_covers_only_labels() {
+        // A box with a label in the middle cell of the top row, and
+        // an arrow leaving it that must not show up in the mask.
+        let tb = TextBuffer::with_text("+---+\n| A +--->\n+---+");
+        let job = RenderJob::formal(tb);
+        let mask = render_text_mask(&job, 1.0).unwrap();
+        let shape = mask.shape();
+        let delta_x = job.width / job.text.size().num_cols as f32;
+        let delta_y = job.height / job.text.size().num_rows as f32;
+        let label = |row: usize, col: usize| {
+            (row as f32 / delta_y) as u32 == 1 && (col as f32 / delta_x) as u32 == 2
+        };
+        let mut ink = 0.0;
+        for row in 0..job.height as usize {
+            for col in 0..job.width as usize {
+                let alpha = mask.data()[shape.offset(row, col)].alpha();
+                if label(row, col) {
+                    ink += alpha;
+                } else {
+                    assert_eq!(alpha, 0.0, "ink at ({row}, {col})");
+                }
+            }
+        }
+        assert!(ink > 0.0);
+    }
+
+    #[test]
+    fn test_transparent_background() {
+        let job = RenderJob::formal(TextBuffer::with_text("+--+\n|  |\n+--+"));
+        let corners = |background: &str| {
+            let image = render(&job, "#808080", background, 1.0).unwrap();
+            let shape = image.shape();
+            let (last_row, last_col) = (shape.height - 1, shape.width - 1);
+            [(0, 0), (0, last_col), (last_row, 0), (last_row, last_col)]
+                .map(|(row, col)| image.data()[shape.offset(row, col)].alpha())
+        };
+        assert_eq!(corners("none"), [0.0; 4]);
+        assert_eq!(corners(""), [0.0; 4]);
+        assert_eq!(corners("#ffffff"), [1.0; 4]);
+        assert!(render(&job, "#808080", "transparent-ish", 1.0).is_err());
+    }
+
+    #[test]
+    fn test_scale_multiplies_image_size() {
+        let mut job = RenderJob::formal(TextBuffer::with_text("+--+\n|  |\n+--+ ok"));
+        job.padding = 5.0;
+        let shape = |scale: f32| render(&job, "#808080", "#000000", scale).unwrap().shape();
+        let (one, two) = (shape(1.0), shape(2.0));
+        assert_eq!(one.width as f32, job.width + 10.0);
+        assert_eq!(one.height as f32, job.height + 10.0);
+        assert_eq!((two.width, two.height), (2 * one.width, 2 * one.height));
+        assert!(render(&job, "#808080", "#000000", 0.0).is_err());
+        assert!(render_text_mask(&job, -1.0).is_err());
+    }
+
+    #[test]
+    fn test_scale_keeps_ink_in_place() {
+        // The ink, in job pixels, sits in the same place at any
+        // scale, padding included.
+        let mut job = RenderJob::formal(TextBuffer::with_text("+--+\n|AB|\n+--+ ok"));
+        job.padding = 7.0;
+        let centroid = |scale: f32| {
+            let img = render(&job, "#FFFFFF", "#000000", scale).unwrap();
+            let shape = img.shape();
+            let (mut total, mut x, mut y) = (0.0, 0.0, 0.0);
+            for row in 0..shape.height {
+                for col in 0..shape.width {
+                    let ink = img.data()[shape.offset(row, col)].red();
+                    total += ink;
+                    x += ink * (col as f32 + 0.5);
+                    y += ink * (row as f32 + 0.5);
+                }
+            }
+            assert!(total > 0.0);
+            (x / total / scale, y / total / scale)
+        };
+        let (one, two) = (centroid(1.0), centroid(2.0));
+        assert!(
+            (one.0 - two.0).abs() < 0.5 && (one.1 - two.1).abs() < 0.5,
+            "ink centroid {one:?} at scale 1 vs {two:?} at scale 2"
+        );
+    }
+
+    #[test]
+    fn test_focus_dims_labels_outside() {
+        // Two labels, with the focus on the left one
+        let mut job = RenderJob::formal(TextBuffer::with_text("A   B"));
+        let ink = |job: &RenderJob| {
+            let img = render(job, "#FFFFFF", "#000000", 1.0).unwrap();
+            let shape = img.shape();
+            let half = shape.width / 2;
+            let (mut left, mut right) = (0.0, 0.0);
+            for row in 0..shape.height {
+                for col in 0..shape.width {
+                    let ink = img.data()[shape.offset(row, col)].red();
+                    if col < half {
+                        left += ink;
+                    } else {
+                        right += ink;
+                    }
+                }
+            }
+            (left, right)
+        };
+        let (left, right) = ink(&job);
+        job.focus = Some(crate::rect::Rectangle::new(
+            crate::tc::TextCoordinate { x: 0, y: 0 },
+            crate::tc::TextCoordinate { x: 1, y: 0 },
+        ));
+        let (focused_left, focused_right) = ink(&job);
+        assert_eq!(focused_left, left);
+        assert!(
+            (focused_right - DIMMED_OPACITY * right).abs() < 0.01 * right,
+            "{focused_right} vs {right}"
+        );
+    }
+
+    #[test]
+    fn test_offset_canvas_keeps_labels() {
+        // Moving the origin of the job moves everything with it, so
+        // the image is the same.
+        let tb = TextBuffer::with_text("+--+\n|ok|\n+--+");
+        let aligned = RenderJob::formal(tb.clone());
+        let mut job = RenderJob::formal(tb);
+        job.x0 = 250.0;
+        job.y0 = -75.0;
+        let ink = |job: &RenderJob| {
+            let img = render(job, "#FFFFFF", "#000000", 1.0).unwrap();
+            img.data()
+                .iter()
+                .map(|pixel| pixel.red())
+                .collect::<Vec<_>>()
+        };
+        let expected = ink(&aligned);
+        assert!(expected.iter().sum::<f32>() > 0.0);
+        let shifted = ink(&job);
+        assert_eq!(shifted.len(), expected.len());
+        assert!(
+            shifted
+                .iter()
+                .zip(&expected)
+                .all(|(a, b)| (a - b).abs() < 1e-3)
+        );
+    }
+
+    #[test]
+    fn test_label_vertically_centered() {
+        // The SVG places the label baseline at `label_baseline`, so if
+        // the rasterized glyph is centered on the cell, the two
+        // backends agree on where the text sits.
+        let tb = TextBuffer::with_text("   \n H \n   ");
+        let job = RenderJob::formal(tb);
+        let img = render(&job, "#FFFFFF", "#000000", 1.0).unwrap();
+        let shape = img.shape();
+        let (mut total, mut weighted) = (0.0, 0.0);
+        for row in 0..job.height as usize {
+            for col in 0..job.width as usize {
+                let ink = img.data()[shape.offset(row, col)].red();
+                total += ink;
+                weighted += ink * (row as f32 + 0.5);
+            }
+        }
+        let ink_center = weighted / total;
+        let cell_center = job.height / 2.0;
+        assert!(
+            (ink_center - cell_center).abs() < 1.0,
+            "ink center {ink_center} vs cell center {cell_center}"
+        );
+    }
 }
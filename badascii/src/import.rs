@@ -0,0 +1,54 @@
+use crate::text_buffer::TextBuffer;
+
+pub(crate) const SOURCE_CLASS: &str = "badascii-source";
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Recover the diagram from an SVG that was rendered with
+/// [`RenderJob::embed_source`](crate::RenderJob::embed_source) set.
+/// Returns `None` if the SVG does not carry the source.
+pub fn from_svg(svg: &str) -> Option<TextBuffer> {
+    let open_tag = format!("<metadata class=\"{SOURCE_CLASS}\">");
+    let start = svg.find(&open_tag)? + open_tag.len();
+    let len = svg[start..].find("</metadata>")?;
+    let source = unescape(&svg[start..start + len]);
+    let source = source.strip_suffix('\n').unwrap_or(&source);
+    Some(TextBuffer::with_text(source))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::RenderJob;
+
+    use super::*;
+
+    const INITIAL_TEXT: &str = "
+  +------+    +------+
+  | a<b  |--->| a&b  |
+  +------+    +------+
+";
+
+    #[test]
+    fn test_round_trip_embedded_source() {
+        let tb = TextBuffer::with_text(INITIAL_TEXT);
+        let mut job = RenderJob::rough(tb);
+        job.embed_source = true;
+        let svg = crate::svg::render(&job, "white", "none");
+        let recovered = from_svg(&svg).unwrap();
+        assert_eq!(recovered.size().num_rows, job.text.size().num_rows);
+        assert_eq!(recovered.size().num_cols, job.text.size().num_cols);
+        assert_eq!(recovered.render(), job.text.render());
+    }
+
+    #[test]
+    fn test_no_embedded_source() {
+        let tb = TextBuffer::with_text(INITIAL_TEXT);
+        let job = RenderJob::rough(tb);
+        let svg = crate::svg::render(&job, "white", "none");
+        assert!(from_svg(&svg).is_none());
+    }
+}
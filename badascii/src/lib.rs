@@ -1,11 +1,15 @@
-mod analyze;
+pub mod analyze;
+pub mod import;
+#[cfg(feature = "pdf")]
+pub mod pdf;
 pub mod rect;
 pub mod render;
 pub mod svg;
 pub mod tc;
 pub mod text_buffer;
-pub use render::RenderJob;
-pub use roughr::core::Options;
-pub use text_buffer::TextBuffer;
+pub use analyze::{LineSegment, extract_graph, get_rectangles, get_wires};
+pub use render::{ArrowStyle, RenderJob, SizeSpec, WireStyle};
+pub use roughr::core::{LineCap, LineJoin, Options};
+pub use text_buffer::{Size, TextBuffer};
 //pub mod png;
 pub mod bitmap;
@@ -0,0 +1,333 @@
+use ab_glyph::{Font, FontRef};
+use pdf_writer::{
+    Content, Finish, Name, Pdf, Rect, Ref, Str,
+    types::{FontFlags, LineCapStyle, LineJoinStyle},
+};
+use rasterize::LinColor;
+use roughr::core::{Drawable, LineCap, LineJoin, OpSetType, OpType};
+
+use crate::RenderJob;
+
+type Error = Box<dyn std::error::Error>;
+
+// Opacity used for the parts of the diagram outside of the focus.
+const DIMMED_OPACITY: f32 = 0.3;
+
+const HACK_FONT: &[u8] = include_bytes!("../font/Hack-Regular.ttf");
+
+// The embedded font is a simple (single byte) TrueType font, so
+// only the printable ASCII range is available for labels.
+const FIRST_CHAR: u8 = b' ';
+const LAST_CHAR: u8 = b'~';
+
+// Colors are parsed as in the bitmap backend, so the same names
+// work in both.  A `LinColor` is linear and premultiplied, and PDF
+// wants plain sRGB.
+fn parse_color(color: &str) -> Result<[f32; 3], Error> {
+    let color = color.parse::<LinColor>()?;
+    let alpha = color.alpha();
+    let srgb = |channel: f32| {
+        let channel = if alpha > 0.0 { channel / alpha } else { 0.0 };
+        if channel <= 0.003_130_8 {
+            12.92 * channel
+        } else {
+            1.055 * channel.powf(1.0 / 2.4) - 0.055
+        }
+    };
+    Ok([srgb(color.red()), srgb(color.green()), srgb(color.blue())])
+}
+
+pub fn stroke_opset(ops: Drawable<f32>, content: &mut Content) {
+    for op_set in ops.sets {
+        if !matches!(op_set.op_set_type, OpSetType::Path | OpSetType::FillPath) {
+            continue;
+        }
+        content.save_state();
+        if let Some(dash) = &ops.options.stroke_line_dash {
+            content.set_dash_pattern(dash.iter().map(|&d| d as f32), 0.0);
+        }
+        let cap = match ops.options.line_cap {
+            Some(LineCap::Butt) => LineCapStyle::ButtCap,
+            Some(LineCap::Square) => LineCapStyle::ProjectingSquareCap,
+            Some(LineCap::Round) | None => LineCapStyle::RoundCap,
+        };
+        content.set_line_cap(cap);
+        if let Some(join) = ops.options.line_join {
+            let join = match join {
+                LineJoin::Miter { limit } => {
+                    content.set_miter_limit(limit as f32);
+                    LineJoinStyle::MiterJoin
+                }
+                LineJoin::Round => LineJoinStyle::RoundJoin,
+                LineJoin::Bevel => LineJoinStyle::BevelJoin,
+            };
+            content.set_line_join(join);
+        }
+        for op in op_set.ops {
+            let d = op.data;
+            match op.op {
+                OpType::Move => {
+                    content.move_to(d[0], d[1]);
+                }
+                OpType::LineTo => {
+                    content.line_to(d[0], d[1]);
+                }
+                OpType::BCurveTo => {
+                    content.cubic_to(d[0], d[1], d[2], d[3], d[4], d[5]);
+                }
+            }
+        }
+        if op_set.op_set_type == OpSetType::FillPath {
+            content.fill_nonzero();
+        } else {
+            content.stroke();
+        }
+        content.restore_state();
+    }
+}
+
+/// Render the job as a single page vector PDF.  One pixel of the
+/// job maps to one point on the page.  The labels are set in the
+/// bundled Hack font, which is embedded in the document.
+pub fn render(job: &RenderJob, color: &str, background: &str) -> Result<Vec<u8>, Error> {
+    let font = FontRef::try_from_slice(HACK_FONT)?;
+    let color = parse_color(color)?;
+    let text_color = match &job.text_color {
+        Some(text_color) => parse_color(text_color)?,
+        None => color,
+    };
+    // A background of "none" (or an empty one) is left
+    // transparent, and anything else must be a color.
+    let background = match background {
+        "" | "none" => None,
+        background => Some(parse_color(background)?),
+    };
+
+    let catalog_id = Ref::new(1);
+    let pages_id = Ref::new(2);
+    let page_id = Ref::new(3);
+    let content_id = Ref::new(4);
+    let font_id = Ref::new(5);
+    let descriptor_id = Ref::new(6);
+    let font_file_id = Ref::new(7);
+    let dimmed_id = Ref::new(8);
+    let font_name = Name(b"F1");
+    let dimmed_name = Name(b"Dim");
+
+    let width = job.width + 2.0 * job.padding;
+    let height = job.height + 2.0 * job.padding;
+    let mut content = Content::new();
+    if let Some([r, g, b]) = background {
+        content.set_fill_rgb(r, g, b);
+        content.rect(0.0, 0.0, width, height);
+        content.fill_nonzero();
+    }
+    // PDF puts the origin in the bottom left corner, so flip the
+    // page to match the top-down coordinates of the render job,
+    // with `(x0, y0)` at the top left corner.
+    content.transform([
+        1.0,
+        0.0,
+        0.0,
+        -1.0,
+        job.padding - job.x0,
+        height - job.padding + job.y0,
+    ]);
+    if let Some(fill_color) = &job.fill_color {
+        let [r, g, b] = parse_color(fill_color)?;
+        content.set_fill_rgb(r, g, b);
+        for (left_top, right_bottom) in job.fills() {
+            content.rect(
+                left_top.x,
+                left_top.y,
+                right_bottom.x - left_top.x,
+                right_bottom.y - left_top.y,
+            );
+        }
+        content.fill_nonzero();
+    }
+    content.set_line_width(1.0);
+    content.set_stroke_rgb(color[0], color[1], color[2]);
+    // The solid marks at the ends of wires are filled in the
+    // color of the strokes.
+    content.set_fill_rgb(color[0], color[1], color[2]);
+
+    let (labels, drawables, dimmed) = match &job.focus {
+        Some(focus) => job.invoke_focused(focus),
+        None => {
+            let (labels, drawables) = job.invoke();
+            (labels, drawables, vec![])
+        }
+    };
+    for op in drawables {
+        stroke_opset(op, &mut content);
+    }
+    if !dimmed.is_empty() {
+        content.save_state();
+        content.set_parameters(dimmed_name);
+        for op in dimmed {
+            stroke_opset(op, &mut content);
+        }
+        content.restore_state();
+    }
+
+    let units_per_em = font.units_per_em().unwrap_or(1000.0);
+    let text_size = job.label_size();
+    content.set_fill_rgb(text_color[0], text_color[1], text_color[2]);
+    for (coord, word, baseline) in job.label_runs(&labels) {
+        let word = word
+            .chars()
+            .map(|ch| {
+                if (FIRST_CHAR as char..=LAST_CHAR as char).contains(&ch) {
+                    ch as u8
+                } else {
+                    b'?'
+                }
+            })
+            .collect::<Vec<_>>();
+        let advance = word
+            .iter()
+            .map(|&ch| {
+                font.h_advance_unscaled(font.glyph_id(ch as char)) / units_per_em * text_size
+            })
+            .sum::<f32>();
+        let is_dimmed = job.focus.is_some_and(|focus| !focus.contains(&coord));
+        if is_dimmed {
+            content.save_state();
+            content.set_parameters(dimmed_name);
+        }
+        content.begin_text();
+        content.set_font(font_name, text_size);
+        // Flip the glyphs back upright within the flipped page.
+        content.set_text_matrix([1.0, 0.0, 0.0, -1.0, baseline.x - advance / 2.0, baseline.y]);
+        content.show(Str(&word));
+        content.end_text();
+        if is_dimmed {
+            content.restore_state();
+        }
+    }
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(pages_id);
+    pdf.pages(pages_id).kids([page_id]).count(1);
+    let mut page = pdf.page(page_id);
+    page.media_box(Rect::new(0.0, 0.0, width, height));
+    page.parent(pages_id);
+    page.contents(content_id);
+    let mut resources = page.resources();
+    resources.fonts().pair(font_name, font_id);
+    resources.ext_g_states().pair(dimmed_name, dimmed_id);
+    resources.finish();
+    page.finish();
+    pdf.stream(content_id, &content.finish());
+
+    pdf.ext_graphics(dimmed_id)
+        .stroking_alpha(DIMMED_OPACITY)
+        .non_stroking_alpha(DIMMED_OPACITY);
+
+    // Glyph space in PDF is 1000 units per em.
+    let scale = 1000.0 / units_per_em;
+    let mut font_dict = pdf.indirect(font_id).dict();
+    font_dict.pair(Name(b"Type"), Name(b"Font"));
+    font_dict.pair(Name(b"Subtype"), Name(b"TrueType"));
+    font_dict.pair(Name(b"BaseFont"), Name(b"Hack-Regular"));
+    font_dict.pair(Name(b"FirstChar"), FIRST_CHAR as i32);
+    font_dict.pair(Name(b"LastChar"), LAST_CHAR as i32);
+    font_dict.pair(Name(b"Encoding"), Name(b"WinAnsiEncoding"));
+    font_dict.pair(Name(b"FontDescriptor"), descriptor_id);
+    font_dict.insert(Name(b"Widths")).array().items(
+        (FIRST_CHAR..=LAST_CHAR)
+            .map(|ch| font.h_advance_unscaled(font.glyph_id(ch as char)) * scale),
+    );
+    font_dict.finish();
+
+    let ascent = font.ascent_unscaled() * scale;
+    let descent = font.descent_unscaled() * scale;
+    pdf.font_descriptor(descriptor_id)
+        .name(Name(b"Hack-Regular"))
+        .flags(FontFlags::FIXED_PITCH | FontFlags::NON_SYMBOLIC)
+        .bbox(Rect::new(0.0, descent, 1000.0, ascent))
+        .italic_angle(0.0)
+        .ascent(ascent)
+        .descent(descent)
+        .cap_height(ascent)
+        .stem_v(80.0)
+        .font_file2(font_file_id);
+    pdf.stream(font_file_id, HACK_FONT)
+        .pair(Name(b"Length1"), HACK_FONT.len() as i32);
+
+    Ok(pdf.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TextBuffer;
+
+    use super::*;
+
+    #[test]
+    fn test_pdf_header() {
+        let tb = TextBuffer::with_text("+--> ok");
+        let job = RenderJob::formal(tb);
+        let pdf = render(&job, "#808080", "#0A0A0A").unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+
+    #[test]
+    fn test_empty_background_is_transparent() {
+        let job = RenderJob::formal(TextBuffer::with_text("+--> ok"));
+        let none = render(&job, "#808080", "none").unwrap();
+        assert_eq!(render(&job, "#808080", "").unwrap(), none);
+        assert_ne!(render(&job, "#808080", "#0A0A0A").unwrap(), none);
+    }
+
+    #[test]
+    fn test_offset_canvas_keeps_labels() {
+        // The label origins (the last two operands of `Tm`), less
+        // the offset the page is moved by, are on the page.
+        let tb = TextBuffer::with_text("+--+\n|ok|\n+--+");
+        let mut job = RenderJob::formal(tb);
+        job.x0 = 250.0;
+        job.y0 = -75.0;
+        let pdf = render(&job, "#808080", "none").unwrap();
+        let pdf = String::from_utf8_lossy(&pdf);
+        let origins = pdf
+            .lines()
+            .filter_map(|line| line.strip_suffix(" Tm"))
+            .map(|operands| {
+                let operands = operands
+                    .split_whitespace()
+                    .map(|v| v.parse::<f32>().unwrap())
+                    .collect::<Vec<_>>();
+                (operands[4] - job.x0, operands[5] - job.y0)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(origins.len(), 2);
+        assert!(
+            origins
+                .iter()
+                .all(|(x, y)| (0.0..=job.width).contains(x) && (0.0..=job.height).contains(y)),
+            "{origins:?}"
+        );
+    }
+
+    #[test]
+    fn test_parse_color() {
+        let close = |color: &str, expected: [f32; 3]| {
+            let parsed = parse_color(color).unwrap();
+            assert!(
+                parsed
+                    .iter()
+                    .zip(expected)
+                    .all(|(a, b)| (a - b).abs() < 1e-3),
+                "{color} parsed as {parsed:?}"
+            );
+        };
+        close("#FFFFFF", [1.0, 1.0, 1.0]);
+        close("#000000", [0.0, 0.0, 0.0]);
+        close("#808080", [0.502, 0.502, 0.502]);
+        // Named colors work, as they do for bitmaps
+        close("teal", [0.0, 0.502, 0.502]);
+        assert!(parse_color("not a color").is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::tc::TextCoordinate;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rectangle {
     pub corner_1: TextCoordinate,
     pub corner_2: TextCoordinate,
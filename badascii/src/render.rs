@@ -1,31 +1,194 @@
 use roughr::{
     PathSegment,
-    core::{Drawable, Options},
+    core::{Drawable, LineCap, LineJoin, Op, OpSet, OpSetType, OpType, Options},
 };
 
-use crate::{analyze::get_wires, tc::TextCoordinate, text_buffer::TextBuffer};
+use crate::{
+    analyze::{
+        Callout, Heading, Junction, LineSegment, get_callouts, get_filled_rectangles, get_headings,
+        get_junctions, get_rectangles, get_wires, group_wires,
+    },
+    rect::Rectangle,
+    tc::TextCoordinate,
+    text_buffer::TextBuffer,
+};
+
+/// How the arrow heads at the ends of wires are drawn.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ArrowStyle {
+    /// A closed triangle.
+    #[default]
+    Closed,
+    /// An open V shape.
+    Open,
+    /// Nothing is drawn, but the arrow character is still
+    /// treated as the end of the wire.
+    None,
+}
+
+/// How the corners of the wires are drawn.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum WireStyle {
+    /// Wires meet in a sharp corner.
+    #[default]
+    Straight,
+    /// Each `+` where a horizontal and a vertical wire meet
+    /// is drawn as a quarter circle.
+    Rounded,
+}
+
+/// A part of the diagram that is drawn on its own by
+/// [`RenderJob::invoke_elements`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Element {
+    Wire(LineSegment),
+    Callout(Callout),
+    Heading(Heading),
+    /// The border drawn around the canvas by `frame`.
+    Frame,
+}
+
+impl Element {
+    /// The `id` of the element in the SVG, which depends only on
+    /// where it is in the text: `wire-<sx>-<sy>-<ex>-<ey>` (from its
+    /// end points), `callout-<x>-<y>`, `heading-<sx>-<sy>-<ex>-<ey>`
+    /// (from the end points of its rule) or `frame`.
+    pub fn id(&self) -> String {
+        let span = |line: &LineSegment| {
+            format!(
+                "{}-{}-{}-{}",
+                line.start.x, line.start.y, line.end.x, line.end.y
+            )
+        };
+        match self {
+            Element::Wire(wire) => format!("wire-{}", span(wire)),
+            Element::Callout(callout) => format!("callout-{}-{}", callout.pos.x, callout.pos.y),
+            Element::Heading(heading) => format!("heading-{}", span(&heading.rule)),
+            Element::Frame => "frame".to_string(),
+        }
+    }
+    /// True if the element lies within `focus`, as decided by
+    /// [`RenderJob::invoke_focused`].
+    pub fn within(&self, focus: &Rectangle) -> bool {
+        match self {
+            Element::Wire(wire) => focus.contains(&wire.start) && focus.contains(&wire.end),
+            Element::Callout(callout) => focus.contains(&callout.pos),
+            Element::Heading(heading) => {
+                focus.contains(&heading.rule.start) && focus.contains(&heading.rule.end)
+            }
+            Element::Frame => true,
+        }
+    }
+}
+
+/// The character that marks the interior of a filled box.
+pub const FILL_CHAR: char = '░';
+
+/// The width of the rendered SVG in the page that embeds it.  The
+/// drawing itself always uses the `width` and `height` of the job
+/// (through the `viewBox`), and is scaled to fit.  The height of
+/// the SVG follows the width, except for a percentage, where it is
+/// left to the `viewBox`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum SizeSpec {
+    /// The width of the canvas (plus padding), in pixels.
+    #[default]
+    Auto,
+    /// A fixed width in pixels.
+    Fixed(f32),
+    /// A percentage of the width of the containing element,
+    /// so the diagram resizes with the page.
+    Percent(f32),
+}
 
 /// Describes the parameters of the render from a text buffer
 /// to the target (usually SVG).  You can control the `width`
 /// and `height` of the virtual canvas, as well as the `x0`, `y0`
-/// for the origin of the rendering on that canvas.
+/// for the origin of the rendering on that canvas.  The exported
+/// SVG, PNG and PDF are always shifted so that `(x0, y0)` lands on
+/// their own `(0, 0)`.
 pub struct RenderJob {
     pub width: f32,
     pub height: f32,
     pub text: TextBuffer,
+    /// The options handed to `roughr` for the strokes.  Adjust
+    /// fields like `bowing` or `seed` here to fine-tune the
+    /// sketched look.  The `line_cap` and `line_join` (round by
+    /// default) are used by all of the backends.
     pub options: roughr::core::Options,
     pub x0: f32,
     pub y0: f32,
+    /// Color used for the labels.  If `None`, the labels
+    /// are drawn in the same color as the strokes.
+    pub text_color: Option<String>,
+    /// Embed the source diagram in the output so that it
+    /// can be recovered later with [`crate::import::from_svg`].
+    pub embed_source: bool,
+    /// The font family used for the labels in the SVG output.
+    pub font_family: String,
+    /// If set, only the part of the diagram inside this
+    /// rectangle is drawn normally, and the rest is dimmed.
+    pub focus: Option<Rectangle>,
+    /// Draw a small hop in horizontal wires where they cross
+    /// a vertical wire, so crossings are not mistaken for
+    /// connections.
+    pub hop_crossings: bool,
+    /// How the arrow heads at the ends of wires are drawn.
+    pub arrow_style: ArrowStyle,
+    /// Blank space (in pixels) added around all sides of the
+    /// canvas, so that strokes at the edges are not clipped.
+    pub padding: f32,
+    /// Draw a border around the whole canvas, including the
+    /// padding.
+    pub frame: bool,
+    /// Draw each phrase of a label as a single run of text with
+    /// natural spacing, instead of one glyph per cell.  Pair this
+    /// with a proportional `font_family` such as `sans-serif`.
+    pub proportional_labels: bool,
+    /// How the corners of the wires are drawn.
+    pub wire_style: WireStyle,
+    /// The width of the SVG element.  See [`SizeSpec`].
+    pub display_width: SizeSpec,
+    /// Color used to fill the boxes whose interior is entirely
+    /// [`FILL_CHAR`].  If `None`, the fill characters are drawn
+    /// as ordinary labels.
+    pub fill_color: Option<String>,
+    /// A short title for the SVG, for screen readers and tooltips.
+    pub title: Option<String>,
+    /// A longer description of the diagram for the SVG.
+    pub description: Option<String>,
+    /// Emit each word of the labels as a single `<text>` element
+    /// in the SVG, spaced so the glyphs stay on the grid, instead
+    /// of one element per cell.  This assumes a monospace font.
+    /// Has no effect with `proportional_labels`.
+    pub merged_labels: bool,
+    /// Fade the shapes of the SVG in one after the other, from the
+    /// top left to the bottom right, followed by the labels.
+    pub animate: bool,
+    /// Space (in cells) kept between the labels in a box and its
+    /// border.  The labels are squeezed toward the middle of the
+    /// innermost box around them to make room.  Zero leaves every
+    /// label on the grid.
+    pub label_padding: f32,
+    /// Draw each wire, callout and heading of the SVG as its own
+    /// group, with an `id` taken from where it sits in the text (see
+    /// [`Element::id`]), and gather the wires along the sides of each
+    /// box into a group of their own.  The same text always gives
+    /// the same ids, so scripts and stylesheets can find them.  Has
+    /// no effect with `animate`, which splits up the strokes.
+    pub element_ids: bool,
 }
 
 impl RenderJob {
-    /// Create a rendering job that uses rough lines for
-    /// the drawing to give it a more informal look.
-    pub fn rough(text: TextBuffer) -> Self {
-        let text = text.shrink_to_fit();
-        let width = (text.size().num_cols * 10) as f32;
-        let height = (text.size().num_rows * 15) as f32;
-        let options = Options::default();
+    /// Create a rendering job that draws `text` cell for cell over
+    /// a `width` by `height` canvas, with rough lines and every
+    /// other setting at its default.  The text is not trimmed.
+    pub fn new(text: TextBuffer, width: f32, height: f32) -> Self {
+        let options = Options {
+            line_cap: Some(LineCap::Round),
+            line_join: Some(LineJoin::Round),
+            ..Options::default()
+        };
         Self {
             width,
             height,
@@ -33,29 +196,59 @@ impl RenderJob {
             options,
             x0: 0.0,
             y0: 0.0,
+            text_color: None,
+            embed_source: false,
+            font_family: "monospace".into(),
+            focus: None,
+            hop_crossings: false,
+            arrow_style: ArrowStyle::Closed,
+            padding: 0.0,
+            frame: false,
+            proportional_labels: false,
+            wire_style: WireStyle::Straight,
+            display_width: SizeSpec::Auto,
+            fill_color: None,
+            title: None,
+            description: None,
+            merged_labels: false,
+            animate: false,
+            label_padding: 0.0,
+            element_ids: false,
         }
     }
-    /// Put on that suit and tie!  Time for a formal look.
-    /// Only clean straight lines here.
-    pub fn formal(text: TextBuffer) -> Self {
+    /// Create a rendering job that uses rough lines for
+    /// the drawing to give it a more informal look.
+    ///
+    /// The text is trimmed to its contents first, so the canvas
+    /// fits the diagram tightly, no matter how large the buffer.
+    pub fn rough(text: TextBuffer) -> Self {
         let text = text.shrink_to_fit();
         let width = (text.size().num_cols * 10) as f32;
         let height = (text.size().num_rows * 15) as f32;
-        let options = Options {
-            disable_multi_stroke: Some(true),
-            max_randomness_offset: Some(0.0),
-            roughness: Some(0.0),
-            ..Options::default()
-        };
+        Self::new(text, width, height)
+    }
+    /// Put on that suit and tie!  Time for a formal look.
+    /// Only clean straight lines here.  Like [`RenderJob::rough`],
+    /// the canvas is sized to fit the trimmed text.
+    pub fn formal(text: TextBuffer) -> Self {
         Self {
-            width,
-            height,
-            text,
-            options,
-            x0: 0.0,
-            y0: 0.0,
+            options: Options {
+                disable_multi_stroke: Some(true),
+                max_randomness_offset: Some(0.0),
+                roughness: Some(0.0),
+                line_cap: Some(LineCap::Round),
+                line_join: Some(LineJoin::Round),
+                ..Options::default()
+            },
+            ..Self::rough(text)
         }
     }
+    /// Resize the canvas to `width`, scaling the height along with
+    /// it, so the cells keep their shape.
+    pub fn scale_to_width(&mut self, width: f32) {
+        self.height *= width / self.width;
+        self.width = width;
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -79,6 +272,12 @@ impl std::ops::Add for Vec2 {
     }
 }
 
+/// The offset of the label baseline below the cell center,
+/// as a fraction of the font size.  This is half the cap height
+/// of a typical monospace font, so that capital letters are
+/// vertically centered in their cell.
+const LABEL_BASELINE_OFFSET: f32 = 0.35;
+
 fn move_to(p: Vec2) -> PathSegment {
     PathSegment::MoveTo {
         abs: true,
@@ -95,95 +294,1328 @@ fn line_to(p: Vec2) -> PathSegment {
     }
 }
 
+fn curve_to(c1: Vec2, c2: Vec2, p: Vec2) -> PathSegment {
+    PathSegment::CurveTo {
+        abs: true,
+        x1: c1.x as f64,
+        y1: c1.y as f64,
+        x2: c2.x as f64,
+        y2: c2.y as f64,
+        x: p.x as f64,
+        y: p.y as f64,
+    }
+}
+
 fn close_path() -> PathSegment {
     PathSegment::ClosePath { abs: true }
 }
 
+// Control point distance for approximating a quarter
+// circle with a cubic bezier
+const KAPPA: f32 = 0.552_284_8;
+
+// A circle of radius `r`, made of four cubic beziers.
+fn circle(center: Vec2, r: f32) -> Vec<PathSegment> {
+    let k = KAPPA * r;
+    vec![
+        move_to(center + vec2(r, 0.0)),
+        curve_to(
+            center + vec2(r, k),
+            center + vec2(k, r),
+            center + vec2(0.0, r),
+        ),
+        curve_to(
+            center + vec2(-k, r),
+            center + vec2(-r, k),
+            center + vec2(-r, 0.0),
+        ),
+        curve_to(
+            center + vec2(-r, -k),
+            center + vec2(-k, -r),
+            center + vec2(0.0, -r),
+        ),
+        curve_to(
+            center + vec2(k, -r),
+            center + vec2(r, -k),
+            center + vec2(r, 0.0),
+        ),
+    ]
+}
+
+// Both ends of each of the wires.
+fn ends_of(wires: &[LineSegment]) -> impl Iterator<Item = TextCoordinate> + '_ {
+    wires.iter().flat_map(|wire| [wire.start, wire.end])
+}
+
+// The arrow glyph to draw at `pos`.  Where a horizontal and a
+// vertical wire meet at an arrow, the glyph marks the direction of
+// flow through the corner, so the head points along the outgoing
+// wire, even if that is not the way the glyph itself points.
+fn turned_arrow(ch: char, pos: TextCoordinate, wires: &[LineSegment]) -> char {
+    let opposite = match ch {
+        '>' => '<',
+        '<' => '>',
+        '^' => 'v',
+        'v' => '^',
+        _ => return ch,
+    };
+    // The directions in which the wires leave `pos`.  Wires always
+    // run left to right and top to bottom.
+    let leaving = wires
+        .iter()
+        .filter_map(|wire| {
+            let horiz = wire.start.y == wire.end.y;
+            let vert = wire.start.x == wire.end.x;
+            match (horiz, vert) {
+                (true, false) if wire.start == pos => Some('>'),
+                (true, false) if wire.end == pos => Some('<'),
+                (false, true) if wire.start == pos => Some('v'),
+                (false, true) if wire.end == pos => Some('^'),
+                _ => None,
+            }
+        })
+        .collect::<Vec<_>>();
+    let is_horiz = |dir: &char| *dir == '<' || *dir == '>';
+    let is_corner = leaving.iter().any(is_horiz) && !leaving.iter().all(is_horiz);
+    if !is_corner || leaving.contains(&ch) || !leaving.contains(&opposite) {
+        return ch;
+    }
+    leaving
+        .into_iter()
+        .find(|dir| is_horiz(dir) != is_horiz(&ch))
+        .unwrap_or(ch)
+}
+
+// The unit step (in cells) from `pos` towards `other`.
+fn away(pos: TextCoordinate, other: TextCoordinate) -> (f32, f32) {
+    (
+        (other.x as f32 - pos.x as f32).signum() * (other.x != pos.x) as u8 as f32,
+        (other.y as f32 - pos.y as f32).signum() * (other.y != pos.y) as u8 as f32,
+    )
+}
+
+// The directions of the two wires leaving the corner at `pos`,
+// if exactly one horizontal and one vertical wire end there.
+fn corner_legs(pos: TextCoordinate, wires: &[LineSegment]) -> Option<[(f32, f32); 2]> {
+    let legs = wires
+        .iter()
+        .filter_map(|wire| {
+            if wire.start == pos {
+                Some(away(pos, wire.end))
+            } else if wire.end == pos {
+                Some(away(pos, wire.start))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    match legs[..] {
+        [first, second] if first.0 * second.0 == 0.0 && first.1 * second.1 == 0.0 => {
+            Some([first, second])
+        }
+        _ => None,
+    }
+}
+
+// The order to draw the wires in, so that each connected group is
+// traced as few separate strokes as possible.  Each wire is paired
+// with a flag that is set if it should be drawn from its end back to
+// its start, so that it picks up where the one before it left off.
+fn stroke_order(wires: &[LineSegment]) -> Vec<(LineSegment, bool)> {
+    let mut order = vec![];
+    for mut group in group_wires(wires) {
+        group.reverse();
+        let mut pen: Option<TextCoordinate> = None;
+        while !group.is_empty() {
+            let next = pen.and_then(|pen| {
+                group
+                    .iter()
+                    .position(|wire| wire.start == pen || wire.end == pen)
+            });
+            let wire = group.remove(next.unwrap_or(group.len() - 1));
+            let reversed = pen.is_some_and(|pen| wire.start != pen && wire.end == pen);
+            pen = Some(if reversed { wire.start } else { wire.end });
+            order.push((wire, reversed));
+        }
+    }
+    order
+}
+
+// The wire ending at `pos`, if every wire that ends there is
+// a diagonal one.
+fn diagonal_end(pos: TextCoordinate, wires: &[LineSegment]) -> Option<&LineSegment> {
+    let mut ending = wires
+        .iter()
+        .filter(|wire| wire.start == pos || wire.end == pos)
+        .peekable();
+    let first = *ending.peek()?;
+    ending
+        .all(|wire| wire.start.x != wire.end.x && wire.start.y != wire.end.y)
+        .then_some(first)
+}
+
+// The cell next to `pos` holding an arrow that points back at the
+// `ch` arrow in `pos`, as in `><`.  The two heads would overlap, so
+// they are drawn as a single marker.
+fn facing_arrow(ch: char, pos: TextCoordinate, text: &TextBuffer) -> Option<TextCoordinate> {
+    let (x, y, opposite) = match ch {
+        '>' => (pos.x.checked_add(1)?, pos.y, '<'),
+        '<' => (pos.x.checked_sub(1)?, pos.y, '>'),
+        'v' => (pos.x, pos.y.checked_add(1)?, '^'),
+        '^' => (pos.x, pos.y.checked_sub(1)?, 'v'),
+        _ => return None,
+    };
+    let next = TextCoordinate { x, y };
+    (text.get(next) == Some(opposite)).then_some(next)
+}
+
 impl RenderJob {
-    fn render_wire_end(&self, ch: char, pos: TextCoordinate) -> Vec<PathSegment> {
-        let delta_x = self.width / self.text.size().num_cols as f32;
-        let delta_y = self.height / self.text.size().num_rows as f32;
-        let pos_map = |pos: TextCoordinate| {
-            vec2(self.x0, self.y0)
-                + vec2(pos.x as f32 * delta_x, pos.y as f32 * delta_y)
-                + vec2(0.5 * delta_x, 0.5 * delta_y)
-        };
-        let p0 = pos_map(pos);
+    /// The font size used for the labels.
+    pub fn label_size(&self) -> f32 {
+        let cell = self.cell_size();
+        cell.x.min(cell.y) * 1.6
+    }
+    // The size of one cell of the text, in pixels.
+    fn cell_size(&self) -> Vec2 {
+        vec2(
+            self.width / self.text.size().num_cols as f32,
+            self.height / self.text.size().num_rows as f32,
+        )
+    }
+    // The center of the cell at `pos`, in pixels.
+    fn cell_center(&self, pos: TextCoordinate) -> Vec2 {
+        let cell = self.cell_size();
+        vec2(self.x0, self.y0)
+            + vec2(pos.x as f32 * cell.x, pos.y as f32 * cell.y)
+            + vec2(0.5 * cell.x, 0.5 * cell.y)
+    }
+    /// The point on the baseline of the label at `pos`, centered
+    /// horizontally in the cell.  All of the backends place their
+    /// text relative to this point, so labels line up the same way
+    /// in the SVG and bitmap outputs.
+    pub fn label_baseline(&self, pos: TextCoordinate) -> Vec2 {
+        self.cell_center(pos) + vec2(0.0, LABEL_BASELINE_OFFSET * self.label_size())
+    }
+    /// The labels as they should be drawn, in row major order.  Each
+    /// entry holds the cell the text starts in, the text, and the
+    /// point on the baseline that the text is centered on.  Normally
+    /// there is one entry per cell.  With `proportional_labels`,
+    /// the words on a row that are a single space apart are joined
+    /// into one phrase, centered on the cells it covers.
+    pub fn label_runs(&self, labels: &TextBuffer) -> Vec<(TextCoordinate, String, Vec2)> {
+        let boxes = self.padded_boxes();
+        if !self.proportional_labels {
+            let mut runs = labels
+                .iter()
+                .map(|(pos, ch)| {
+                    let baseline = self.inset_label(pos, self.label_baseline(pos), &boxes);
+                    (pos, ch.to_string(), baseline)
+                })
+                .collect::<Vec<_>>();
+            runs.sort_by_key(|(pos, _, _)| (pos.y, pos.x));
+            return runs;
+        }
+        let mut phrases: Vec<(TextCoordinate, String)> = vec![];
+        for (pos, word) in labels.words() {
+            if let Some((start, phrase)) = phrases.last_mut()
+                && start.y == pos.y
+                && start.x + phrase.chars().count() as u32 + 1 == pos.x
+            {
+                phrase.push(' ');
+                phrase.push_str(&word);
+                continue;
+            }
+            phrases.push((pos, word));
+        }
+        phrases
+            .into_iter()
+            .map(|(pos, phrase)| {
+                let first = self.label_baseline(pos);
+                let last = self.label_baseline(TextCoordinate {
+                    x: pos.x + phrase.chars().count() as u32 - 1,
+                    ..pos
+                });
+                let center = vec2((first.x + last.x) / 2.0, first.y);
+                (pos, phrase, self.inset_label(pos, center, &boxes))
+            })
+            .collect()
+    }
+    /// The boxes that labels are kept `label_padding` away from.
+    /// Empty when there is no padding.
+    pub(crate) fn padded_boxes(&self) -> Vec<Rectangle> {
+        if self.label_padding > 0.0 {
+            get_rectangles(&self.text)
+        } else {
+            vec![]
+        }
+    }
+    /// Moves `point`, placed for the label at `pos`, in from the
+    /// border of the innermost of `boxes` around `pos`.  The interior
+    /// of the box is scaled about its center, so labels keep their
+    /// order and spacing relative to each other.  The padding is at
+    /// most a quarter of the interior on each side.
+    pub(crate) fn inset_label(
+        &self,
+        pos: TextCoordinate,
+        point: Vec2,
+        boxes: &[Rectangle],
+    ) -> Vec2 {
+        let inside = |rect: &&Rectangle| {
+            (rect.left() + 1..rect.right_bottom().x).contains(&pos.x)
+                && (rect.top() + 1..rect.right_bottom().y).contains(&pos.y)
+        };
+        let Some(rect) = boxes
+            .iter()
+            .filter(inside)
+            .min_by_key(|rect| rect.width() * rect.height())
+        else {
+            return point;
+        };
+        let cell = self.cell_size();
+        let (delta_x, delta_y) = (cell.x, cell.y);
+        // Maps a coordinate in the interior `lo..hi` onto the same
+        // interior, inset by the padding.
+        let squeeze = |v: f32, lo: f32, hi: f32, cell: f32| {
+            let pad = (self.label_padding * cell).min((hi - lo) / 4.0);
+            lo + pad + (v - lo) * (hi - lo - 2.0 * pad) / (hi - lo)
+        };
+        let left = self.x0 + (rect.left() + 1) as f32 * delta_x;
+        let right = self.x0 + rect.right_bottom().x as f32 * delta_x;
+        let top = self.y0 + (rect.top() + 1) as f32 * delta_y;
+        let bottom = self.y0 + rect.right_bottom().y as f32 * delta_y;
+        // The baseline sits below the center of the cell, so the
+        // center is moved, and the baseline follows it.
+        let center_y = self.cell_center(pos).y;
+        vec2(
+            squeeze(point.x, left, right, delta_x),
+            point.y + squeeze(center_y, top, bottom, delta_y) - center_y,
+        )
+    }
+    // The outline of a solid mark (a junction dot or a square
+    // terminal) at the end of a wire, which is stroked like the
+    // other wire ends and also filled in by `wire_end_fills`.
+    fn solid_mark(&self, ch: char, pos: TextCoordinate) -> Option<Vec<PathSegment>> {
+        let cell = self.cell_size();
+        let p0 = self.cell_center(pos);
+        let r = 0.25 * cell.x.min(cell.y);
         match ch {
-            //  *  \
-            //  *  x  *
-            //  *  /
-            '>' => vec![
-                move_to(p0 + vec2(0.0, -0.3 * delta_y)),
-                line_to(p0 + vec2(1.0 * delta_x, 0.0)),
-                line_to(p0 + vec2(0.0, 0.3 * delta_y)),
-                close_path(),
-            ],
-            '<' => vec![
-                move_to(p0 + vec2(0.0 * delta_x, -0.3 * delta_y)),
-                line_to(p0 + vec2(-1.0 * delta_x, 0.0)),
-                line_to(p0 + vec2(0.0 * delta_x, 0.3 * delta_y)),
-                close_path(),
-            ],
-            'v' => vec![
-                move_to(p0 + vec2(-0.5 * delta_x, 0.0)),
-                line_to(p0 + vec2(0.0, 1.0 * delta_y)),
-                line_to(p0 + vec2(0.5 * delta_x, 0.0)),
+            // A junction dot
+            '*' => Some(circle(p0, r)),
+            // A square terminal (e.g., a bus stub)
+            '#' => Some(vec![
+                move_to(p0 + vec2(-r, -r)),
+                line_to(p0 + vec2(r, -r)),
+                line_to(p0 + vec2(r, r)),
+                line_to(p0 + vec2(-r, r)),
                 close_path(),
-            ],
-            '^' => vec![
-                move_to(p0 + vec2(-0.5 * delta_x, 0.0)),
-                line_to(p0 + vec2(0.0, -1.0 * delta_y)),
-                line_to(p0 + vec2(0.5 * delta_x, 0.0)),
-                close_path(),
-            ],
-            _ => Vec::default(),
+            ]),
+            _ => None,
         }
     }
 
-    pub fn invoke(&self) -> (TextBuffer, Vec<Drawable<f32>>) {
-        let delta_x = self.width / self.text.size().num_cols as f32;
-        let delta_y = self.height / self.text.size().num_rows as f32;
-        let mut labels = self.text.clone();
-        let pos_map = |pos: TextCoordinate| {
-            vec2(self.x0, self.y0)
-                + vec2(pos.x as f32 * delta_x, pos.y as f32 * delta_y)
-                + vec2(0.5 * delta_x, 0.5 * delta_y)
+    // The inside of the solid marks among the wire `ends`, as a
+    // path for the backends to fill rather than stroke.
+    fn wire_end_fills(&self, ends: impl IntoIterator<Item = TextCoordinate>) -> Option<OpSet<f32>> {
+        let mut unique = vec![];
+        for pos in ends {
+            if !unique.contains(&pos) {
+                unique.push(pos);
+            }
+        }
+        let ends = unique;
+        let ops = ends
+            .into_iter()
+            .filter_map(|pos| self.solid_mark(self.text.get(pos)?, pos))
+            .flatten()
+            .filter_map(|segment| match segment {
+                PathSegment::MoveTo { x, y, .. } => Some(Op {
+                    op: OpType::Move,
+                    data: vec![x as f32, y as f32],
+                }),
+                PathSegment::LineTo { x, y, .. } => Some(Op {
+                    op: OpType::LineTo,
+                    data: vec![x as f32, y as f32],
+                }),
+                PathSegment::CurveTo {
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                    x,
+                    y,
+                    ..
+                } => Some(Op {
+                    op: OpType::BCurveTo,
+                    data: [x1, y1, x2, y2, x, y].map(|v| v as f32).to_vec(),
+                }),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        (!ops.is_empty()).then_some(OpSet {
+            op_set_type: OpSetType::FillPath,
+            ops,
+            size: None,
+            path: None,
+        })
+    }
+
+    fn render_wire_end(&self, ch: char, pos: TextCoordinate) -> Vec<PathSegment> {
+        if let Some(mark) = self.solid_mark(ch, pos) {
+            return mark;
+        }
+        let cell = self.cell_size();
+        let (delta_x, delta_y) = (cell.x, cell.y);
+        let p0 = self.cell_center(pos);
+        //  \ /
+        //   x    A no-connect marker
+        //  / \
+        if ch == 'x' {
+            let r = 0.3 * delta_x.min(delta_y);
+            return vec![
+                move_to(p0 + vec2(-r, -r)),
+                line_to(p0 + vec2(r, r)),
+                move_to(p0 + vec2(-r, r)),
+                line_to(p0 + vec2(r, -r)),
+            ];
+        }
+        //  *  \
+        //  *  x  *
+        //  *  /
+        let (tip, wing) = match ch {
+            '>' => (vec2(1.0 * delta_x, 0.0), vec2(0.0, 0.3 * delta_y)),
+            '<' => (vec2(-delta_x, 0.0), vec2(0.0, 0.3 * delta_y)),
+            'v' => (vec2(0.0, 1.0 * delta_y), vec2(0.5 * delta_x, 0.0)),
+            '^' => (vec2(0.0, -delta_y), vec2(0.5 * delta_x, 0.0)),
+            _ => return Vec::default(),
         };
-        let wires = get_wires(&labels);
+        self.arrow_head(p0, tip, wing)
+    }
+
+    // An arrow head centered on `p0`, pointing along `tip`, with
+    // the back corners at `p0 - wing` and `p0 + wing`.
+    fn arrow_head(&self, p0: Vec2, tip: Vec2, wing: Vec2) -> Vec<PathSegment> {
+        let head = vec![
+            move_to(p0 + vec2(-wing.x, -wing.y)),
+            line_to(p0 + tip),
+            line_to(p0 + wing),
+        ];
+        match self.arrow_style {
+            ArrowStyle::Closed => head.into_iter().chain([close_path()]).collect(),
+            ArrowStyle::Open => head,
+            ArrowStyle::None => Vec::default(),
+        }
+    }
+
+    //  >< A pair of heads that point at each other, drawn as one
+    //     diamond running between the centers of the two cells.
+    fn meeting_heads(&self, a: TextCoordinate, b: TextCoordinate) -> Vec<PathSegment> {
+        let cell = self.cell_size();
+        let (delta_x, delta_y) = (cell.x, cell.y);
+        let (p0, p1) = (self.cell_center(a), self.cell_center(b));
+        let mid = vec2((p0.x + p1.x) / 2.0, (p0.y + p1.y) / 2.0);
+        let wing = if a.y == b.y {
+            vec2(0.0, 0.3 * delta_y)
+        } else {
+            vec2(0.5 * delta_x, 0.0)
+        };
+        let diamond = vec![
+            move_to(p0),
+            line_to(mid + wing),
+            line_to(p1),
+            line_to(mid + vec2(-wing.x, -wing.y)),
+        ];
+        match self.arrow_style {
+            ArrowStyle::Closed => diamond.into_iter().chain([close_path()]).collect(),
+            ArrowStyle::Open => diamond.into_iter().chain([line_to(p0)]).collect(),
+            ArrowStyle::None => Vec::default(),
+        }
+    }
+
+    // The arrow head at the end of a diagonal wire points along
+    // the wire, whichever way the glyph itself points.
+    fn diagonal_arrow_head(&self, pos: TextCoordinate, wire: &LineSegment) -> Vec<PathSegment> {
+        let cell = self.cell_size();
+        let (delta_x, delta_y) = (cell.x, cell.y);
+        let other = if wire.start == pos {
+            wire.end
+        } else {
+            wire.start
+        };
+        let step = |to: u32, from: u32| (to as f32 - from as f32).signum();
+        let tip = vec2(
+            step(pos.x, other.x) * delta_x,
+            step(pos.y, other.y) * delta_y,
+        );
+        let scale = 0.4 * delta_x.min(delta_y) / tip.x.hypot(tip.y);
+        let wing = vec2(-tip.y * scale, tip.x * scale);
+        self.arrow_head(self.cell_center(pos), tip, wing)
+    }
+
+    // Converts the wires into path segments (including the
+    // wire ends).
+    #[cfg(test)]
+    fn wire_path_segments(&self, wires: &[LineSegment]) -> Vec<PathSegment> {
+        self.wire_path_segments_among(wires, wires, &mut vec![])
+    }
+
+    // Draws `wires`, taking the corners and the wire ends from `all`
+    // (the wires they are drawn with).  The ends and fillets in
+    // `drawn` were already drawn with other wires, and are skipped.
+    fn wire_path_segments_among(
+        &self,
+        wires: &[LineSegment],
+        all: &[LineSegment],
+        drawn: &mut Vec<TextCoordinate>,
+    ) -> Vec<PathSegment> {
+        let cell = self.cell_size();
+        let (delta_x, delta_y) = (cell.x, cell.y);
+        let crossings: Vec<TextCoordinate> = if self.hop_crossings {
+            get_junctions(&self.text)
+                .into_iter()
+                .filter(|(_, junction)| *junction == Junction::Cross)
+                .map(|(pos, _)| pos)
+                .collect()
+        } else {
+            vec![]
+        };
+        // In rounded mode, the wires stop short of each corner, and
+        // a fillet joins them instead.
+        let radius = 0.5 * delta_x.min(delta_y);
+        let corners: Vec<(TextCoordinate, [(f32, f32); 2])> = match self.wire_style {
+            WireStyle::Straight => vec![],
+            WireStyle::Rounded => get_junctions(&self.text)
+                .into_iter()
+                .filter(|(_, junction)| *junction == Junction::Corner)
+                .filter_map(|(pos, _)| Some((pos, corner_legs(pos, all)?)))
+                .collect(),
+        };
+        let wire_end = |pos: TextCoordinate, other: TextCoordinate| {
+            if corners.iter().any(|(corner, _)| *corner == pos) {
+                let (dx, dy) = away(pos, other);
+                self.cell_center(pos) + vec2(dx * radius, dy * radius)
+            } else {
+                self.cell_center(pos)
+            }
+        };
+        // Convert the wires into a list of Path Segments.  A wire
+        // that starts where the last one ended carries on the same
+        // stroke, unless a fillet sits between them.
+        let mut path_segments: Vec<PathSegment> = vec![];
+        let mut pen = None;
+        for (wire, reversed) in stroke_order(wires) {
+            let (from, to) = if reversed {
+                (wire.end, wire.start)
+            } else {
+                (wire.start, wire.end)
+            };
+            if pen != Some(from) || corners.iter().any(|(corner, _)| *corner == from) {
+                path_segments.push(move_to(wire_end(from, to)));
+            }
+            if wire.start.y == wire.end.y {
+                //   _
+                // _/ \_  hop over each crossing along the wire
+                let dir = if reversed { -1.0 } else { 1.0 };
+                let mut hops = crossings
+                    .iter()
+                    .filter(|c| {
+                        c.y == wire.start.y && (wire.start.x + 1..wire.end.x).contains(&c.x)
+                    })
+                    .collect::<Vec<_>>();
+                if reversed {
+                    hops.reverse();
+                }
+                for &cross in hops {
+                    let p = self.cell_center(cross);
+                    path_segments.push(line_to(p + vec2(-0.5 * dir * delta_x, 0.0)));
+                    path_segments.push(curve_to(
+                        p + vec2(-0.5 * dir * delta_x, -0.6 * delta_y),
+                        p + vec2(0.5 * dir * delta_x, -0.6 * delta_y),
+                        p + vec2(0.5 * dir * delta_x, 0.0),
+                    ));
+                }
+            }
+            path_segments.push(line_to(wire_end(to, from)));
+            pen = Some(to);
+        }
+        let ends_here = |corner: &TextCoordinate| {
+            wires
+                .iter()
+                .any(|wire| wire.start == *corner || wire.end == *corner)
+        };
+        for &(corner, [first, second]) in &corners {
+            if drawn.contains(&corner) || !ends_here(&corner) {
+                continue;
+            }
+            let p = self.cell_center(corner);
+            let at = |(dx, dy): (f32, f32), r: f32| p + vec2(dx * r, dy * r);
+            let handle = radius * (1.0 - KAPPA);
+            path_segments.push(move_to(at(first, radius)));
+            path_segments.push(curve_to(
+                at(first, handle),
+                at(second, handle),
+                at(second, radius),
+            ));
+        }
+        path_segments.extend(self.wire_end_path_segments_among(wires, all, drawn));
+        path_segments
+    }
+
+    // Draws the ends of the wires (arrows, junction dots and
+    // no-connect markers).
+    fn wire_end_path_segments(&self, wires: &[LineSegment]) -> Vec<PathSegment> {
+        self.wire_end_path_segments_among(wires, wires, &mut vec![])
+    }
+
+    // An end shared by several wires (like a corner) is only drawn
+    // once, so the ends drawn are added to `drawn`.
+    fn wire_end_path_segments_among(
+        &self,
+        wires: &[LineSegment],
+        all: &[LineSegment],
+        drawn: &mut Vec<TextCoordinate>,
+    ) -> Vec<PathSegment> {
+        let mut path_segments = vec![];
+        for segment in wires {
+            for pos in [segment.start, segment.end] {
+                if drawn.contains(&pos) {
+                    continue;
+                }
+                drawn.push(pos);
+                if let Some(ch) = self.text.get(pos) {
+                    let ch = turned_arrow(ch, pos, all);
+                    match diagonal_end(pos, all) {
+                        Some(wire) if matches!(ch, '<' | '>' | '^' | 'v') => {
+                            path_segments.extend(self.diagonal_arrow_head(pos, wire))
+                        }
+                        _ => match facing_arrow(ch, pos, &self.text) {
+                            Some(other) => {
+                                drawn.push(other);
+                                path_segments.extend(self.meeting_heads(pos, other));
+                            }
+                            None => path_segments.extend(self.render_wire_end(ch, pos)),
+                        },
+                    }
+                }
+            }
+        }
+        path_segments
+    }
+
+    // Draws a circle around each callout.
+    fn callout_path_segments(&self, callouts: &[Callout]) -> Vec<PathSegment> {
+        let cell = self.cell_size();
+        let mut path_segments = vec![];
+        for callout in callouts {
+            // The digits follow the `(`, so the circle is centered
+            // half way along them.
+            let center = self.cell_center(callout.pos)
+                + vec2((0.5 + callout.digits as f32 / 2.0) * cell.x, 0.0);
+            let r = (0.55 * cell.y).max((callout.digits as f32 / 2.0 + 0.3) * cell.x);
+            path_segments.extend(circle(center, r));
+        }
+        path_segments
+    }
+
+    // Underlines each heading along the bottom of its label's row.
+    fn heading_path_segments(&self, headings: &[Heading]) -> Vec<PathSegment> {
+        let cell = self.cell_size();
+        let (delta_x, delta_y) = (cell.x, cell.y);
+        let mut path_segments = vec![];
+        for heading in headings {
+            let LineSegment { start, end } = heading.rule;
+            let y = self.y0 + start.y as f32 * delta_y;
+            path_segments.push(move_to(vec2(self.x0 + start.x as f32 * delta_x, y)));
+            path_segments.push(line_to(vec2(self.x0 + (end.x + 1) as f32 * delta_x, y)));
+        }
+        path_segments
+    }
+
+    // The options for dashed wires, which carry the dash
+    // pattern through to the backends in the drawable.
+    fn dashed_options(&self) -> Options {
+        let delta_x = self.cell_size().x;
+        Options {
+            stroke_line_dash: Some(vec![0.6 * delta_x as f64, 0.4 * delta_x as f64]),
+            ..self.options.clone()
+        }
+    }
+
+    // The border around the canvas, inset by half a pixel so the
+    // stroke is not clipped at the edges.
+    fn frame_path_segments(&self) -> Vec<PathSegment> {
+        let inset = 0.5 - self.padding;
+        let left_top = vec2(self.x0 + inset, self.y0 + inset);
+        let right_bottom = vec2(self.x0 + self.width - inset, self.y0 + self.height - inset);
+        vec![
+            move_to(left_top),
+            line_to(vec2(right_bottom.x, left_top.y)),
+            line_to(right_bottom),
+            line_to(vec2(left_top.x, right_bottom.y)),
+            close_path(),
+        ]
+    }
+
+    /// Draw only the ends of the wires (the arrow heads, junction
+    /// dots and no-connect markers), with the wires themselves
+    /// left out.  Useful as an overlay on the raw text.
+    pub fn invoke_wire_ends(&self) -> Vec<Drawable<f32>> {
+        let wires = get_wires(&self.text);
+        let path_segments = self.wire_end_path_segments(&wires);
+        if path_segments.is_empty() {
+            return vec![];
+        }
         let generator = roughr::generator::Generator::default();
-        let options = self.options.clone();
-        let options = Some(options);
-        let mut drawables = vec![];
-        // Convert the wires into a list of Path Segments
-        let mut path_segments: Vec<PathSegment> = wires
-            .iter()
-            .flat_map(|wire| {
-                let p0 = pos_map(wire.start);
-                let p1 = pos_map(wire.end);
-                [move_to(p0), line_to(p1)]
+        let mut ops = generator.path_from_segments(path_segments, &Some(self.options.clone()));
+        ops.sets.extend(self.wire_end_fills(ends_of(&wires)));
+        vec![ops]
+    }
+
+    /// The filled boxes, as the top left and bottom right corners
+    /// (in pixels) of the area to fill behind the strokes.  Empty
+    /// unless a `fill_color` is set.
+    pub fn fills(&self) -> Vec<(Vec2, Vec2)> {
+        if self.fill_color.is_none() {
+            return vec![];
+        }
+        get_filled_rectangles(&self.text, FILL_CHAR)
+            .into_iter()
+            .map(|rect| {
+                (
+                    self.cell_center(rect.left_top()),
+                    self.cell_center(rect.right_bottom()),
+                )
             })
-            .collect();
-        for segment in &wires {
-            for pt in segment.iter() {
-                labels.set_text(&pt, None);
+            .collect()
+    }
+
+    // The fill characters are drawn as a fill, not as labels.
+    fn clear_fill_chars(&self, labels: &mut TextBuffer) {
+        if self.fill_color.is_some() {
+            for rect in get_filled_rectangles(&self.text, FILL_CHAR) {
+                for pos in rect.iter_interior() {
+                    if labels.get(pos) == Some(FILL_CHAR) {
+                        labels.set_text(&pos, None);
+                    }
+                }
             }
         }
-        // Draw end things
-        for segment in wires {
-            let pos = segment.start;
-            if let Some(ch) = self.text.get(pos) {
-                path_segments.extend(self.render_wire_end(ch, pos));
+    }
+
+    /// The labels left over once the wires, wire ends, callout
+    /// parens, heading rules and fills are taken out.  This is the
+    /// same buffer [`RenderJob::invoke`] returns, without the cost
+    /// of generating the drawables.
+    pub fn labels(&self) -> TextBuffer {
+        let (labels, ..) = self.find_parts();
+        labels
+    }
+
+    // Finds the headings, wires and callouts in the text, and takes
+    // them (and the fills) out of the labels.
+    fn find_parts(&self) -> (TextBuffer, Vec<Heading>, Vec<LineSegment>, Vec<Callout>) {
+        let mut labels = self.text.clone();
+        self.clear_fill_chars(&mut labels);
+        let headings = get_headings(&labels);
+        let wires = get_wires(&labels);
+        let callouts = get_callouts(&labels);
+        for wire in &wires {
+            for pos in wire.iter() {
                 labels.set_text(&pos, None);
             }
-            let pos = segment.end;
-            if let Some(ch) = self.text.get(pos) {
-                path_segments.extend(self.render_wire_end(ch, pos));
+            for pos in [wire.start, wire.end] {
+                let Some(ch) = self.text.get(pos) else {
+                    continue;
+                };
+                let ch = turned_arrow(ch, pos, &wires);
+                let diagonal_head =
+                    diagonal_end(pos, &wires).is_some() && matches!(ch, '<' | '>' | '^' | 'v');
+                if !diagonal_head && let Some(other) = facing_arrow(ch, pos, &self.text) {
+                    labels.set_text(&other, None);
+                }
                 labels.set_text(&pos, None);
             }
         }
-        let ops = generator.path_from_segments(path_segments, &options);
-        drawables.push(ops);
+        for callout in &callouts {
+            let close = TextCoordinate {
+                x: callout.pos.x + callout.digits + 1,
+                y: callout.pos.y,
+            };
+            labels.set_text(&callout.pos, None);
+            labels.set_text(&close, None);
+        }
+        for heading in &headings {
+            for pos in heading.rule.bounding_box().iter_interior() {
+                labels.set_text(&pos, None);
+            }
+        }
+        (labels, headings, wires, callouts)
+    }
+
+    pub fn invoke(&self) -> (TextBuffer, Vec<Drawable<f32>>) {
+        let (labels, drawables) = self.draw_grouped(|_| ());
+        let drawables = drawables.into_iter().map(|(_, ops)| ops).collect();
         (labels, drawables)
     }
+
+    /// Like [`RenderJob::invoke`], but with a drawable for each
+    /// wire, callout and heading (and the frame) rather than one
+    /// for everything, in the order `invoke` draws them.  The
+    /// corners and arrow heads come out as they do in `invoke`,
+    /// each drawn with the first wire that ends there.
+    pub fn invoke_elements(&self) -> (TextBuffer, Vec<(Element, Drawable<f32>)>) {
+        self.draw_grouped(|element| *element)
+    }
+
+    /// Like [`RenderJob::invoke`], but splits the drawables into
+    /// those for wires (and callouts) that lie entirely within
+    /// `focus`, and those for the remaining wires.
+    pub fn invoke_focused(
+        &self,
+        focus: &Rectangle,
+    ) -> (TextBuffer, Vec<Drawable<f32>>, Vec<Drawable<f32>>) {
+        let (labels, drawables) = self.draw_grouped(|element| element.within(focus));
+        let (in_focus, out_of_focus): (Vec<_>, Vec<_>) =
+            drawables.into_iter().partition(|(within, _)| *within);
+        let ops = |drawables: Vec<(bool, Drawable<f32>)>| {
+            drawables.into_iter().map(|(_, ops)| ops).collect()
+        };
+        (labels, ops(in_focus), ops(out_of_focus))
+    }
+
+    // The one pass behind the `invoke` variants.  The parts of the
+    // diagram are found, taken out of the labels and drawn, with the
+    // parts that `group` gives the same key drawn together, in the
+    // order the keys first turn up.  Each group gets a drawable for
+    // its strokes and another for its dashed wires, if it has any.
+    fn draw_grouped<K: Copy + PartialEq>(
+        &self,
+        group: impl Fn(&Element) -> K,
+    ) -> (TextBuffer, Vec<(K, Drawable<f32>)>) {
+        let (labels, headings, wires, callouts) = self.find_parts();
+        let (dashed, wires): (Vec<_>, Vec<_>) = wires
+            .into_iter()
+            .partition(|wire| wire.is_dashed(&self.text));
+        let solid_parts = wires
+            .iter()
+            .map(|wire| Element::Wire(*wire))
+            .chain(callouts.iter().map(|callout| Element::Callout(*callout)))
+            .chain(headings.iter().map(|heading| Element::Heading(*heading)))
+            .chain(self.frame.then_some(Element::Frame))
+            .collect::<Vec<_>>();
+        let dashed_parts = dashed
+            .iter()
+            .map(|wire| Element::Wire(*wire))
+            .collect::<Vec<_>>();
+        let mut keys = vec![];
+        for key in solid_parts.iter().chain(&dashed_parts).map(&group) {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        let generator = roughr::generator::Generator::default();
+        let options = Some(self.options.clone());
+        let dashed_options = Some(self.dashed_options());
+        let mut drawn = vec![];
+        let mut drawn_dashed = vec![];
+        let mut drawables = vec![];
+        for key in keys {
+            let parts = solid_parts
+                .iter()
+                .filter(|part| group(part) == key)
+                .collect::<Vec<_>>();
+            if !parts.is_empty() {
+                let group_wires = parts
+                    .iter()
+                    .filter_map(|part| match part {
+                        Element::Wire(wire) => Some(*wire),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                let group_callouts = parts
+                    .iter()
+                    .filter_map(|part| match part {
+                        Element::Callout(callout) => Some(*callout),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                let group_headings = parts
+                    .iter()
+                    .filter_map(|part| match part {
+                        Element::Heading(heading) => Some(*heading),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                let before = drawn.len();
+                let mut path_segments =
+                    self.wire_path_segments_among(&group_wires, &wires, &mut drawn);
+                path_segments.extend(self.callout_path_segments(&group_callouts));
+                path_segments.extend(self.heading_path_segments(&group_headings));
+                if parts.contains(&&Element::Frame) {
+                    path_segments.extend(self.frame_path_segments());
+                }
+                let mut ops = generator.path_from_segments(path_segments, &options);
+                ops.sets
+                    .extend(self.wire_end_fills(drawn[before..].iter().copied()));
+                drawables.push((key, ops));
+            }
+            let group_dashed = dashed
+                .iter()
+                .filter(|wire| group(&Element::Wire(**wire)) == key)
+                .copied()
+                .collect::<Vec<_>>();
+            if !group_dashed.is_empty() {
+                let before = drawn_dashed.len();
+                let path_segments =
+                    self.wire_path_segments_among(&group_dashed, &dashed, &mut drawn_dashed);
+                let mut ops = generator.path_from_segments(path_segments, &dashed_options);
+                ops.sets
+                    .extend(self.wire_end_fills(drawn_dashed[before..].iter().copied()));
+                drawables.push((key, ops));
+            }
+        }
+        (labels, drawables)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze::{Endpoint, get_classified_wires, get_wire_groups};
+
+    fn arrow_head(arrow_style: ArrowStyle) -> Vec<PathSegment> {
+        let mut job = RenderJob::formal(TextBuffer::with_text("+-->"));
+        job.arrow_style = arrow_style;
+        job.render_wire_end('>', TextCoordinate { x: 3, y: 0 })
+    }
+
+    #[test]
+    fn test_labels_match_invoke() {
+        let texts = [
+            "+--> Label <--+\n|             |\n+-----------(1)",
+            "+--><--+\n  v\n  ^\n  +\n+--><",
+            "+\n \\\n  v  (12)",
+            " Title\n -----\n+--+\n|░░|\n+--+",
+        ];
+        for text in texts {
+            let mut job = RenderJob::formal(TextBuffer::with_text(text));
+            job.fill_color = Some("#ccc".to_string());
+            assert_eq!(job.labels().render(), job.invoke().0.render(), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_scale_to_width_keeps_cell_shape() {
+        let mut job = RenderJob::formal(TextBuffer::with_text("+-->\n|\n+"));
+        assert_eq!((job.width, job.height), (40.0, 45.0));
+        job.scale_to_width(20.0);
+        assert_eq!((job.width, job.height), (20.0, 22.5));
+    }
+
+    #[test]
+    fn test_bowing_changes_path() {
+        let text = TextBuffer::with_text("+------+\n|      |\n+------+");
+        let sets = |bowing: f32| {
+            let mut job = RenderJob::rough(text.clone());
+            job.options.bowing = Some(bowing);
+            let (_, drawables) = job.invoke();
+            drawables[0].sets.clone()
+        };
+        assert_eq!(sets(2.0), sets(2.0));
+        assert_ne!(sets(2.0), sets(8.0));
+    }
+
+    #[test]
+    fn test_wire_run_is_one_stroke() {
+        for text in ["+---+---+", "+---+---+---+---+", "+-+-+-+-+-+-+"] {
+            let job = RenderJob::rough(TextBuffer::with_text(text));
+            let wires = get_wires(&job.text);
+            assert_eq!(wires.len(), 1);
+            let segments = job.wire_path_segments(&wires);
+            let strokes = segments
+                .iter()
+                .filter(|seg| matches!(seg, PathSegment::MoveTo { .. }))
+                .count();
+            assert_eq!(strokes, 1);
+        }
+    }
+
+    #[test]
+    fn test_connected_wires_are_one_stroke() {
+        // A diagonal leaving a horizontal wire at a `+`, and the
+        // bottom wire drawn back toward it, trace a single stroke.
+        for text in ["+---+\n     \\\n      +---+", "+----+\n    /\n+--+"] {
+            let job = RenderJob::formal(TextBuffer::with_text(text));
+            let wires = get_wires(&job.text);
+            assert_eq!(wires.len(), 3, "{text}");
+            let segments = job.wire_path_segments(&wires);
+            let strokes = segments
+                .iter()
+                .filter(|seg| matches!(seg, PathSegment::MoveTo { .. }))
+                .count();
+            assert_eq!(strokes, 1, "{text}");
+        }
+    }
+
+    #[test]
+    fn test_diagonal_arrow_points_along_wire() {
+        let job = RenderJob::formal(TextBuffer::with_text("+\n \\\n  v"));
+        let wires = get_wires(&job.text);
+        assert_eq!(wires.len(), 1);
+        let segments = job.wire_path_segments(&wires);
+        assert!(job.labels().iter().next().is_none());
+        // The end cell is centered on (25, 37.5), and the tip is
+        // one cell further down and to the right.
+        let tip = |seg: &PathSegment| matches!(seg, PathSegment::LineTo { x, y, .. } if *x == 35.0 && *y == 52.5);
+        assert_eq!(segments.iter().filter(|seg| tip(seg)).count(), 1);
+        // An up-right wire ending in a `<` still points up and right.
+        let job = RenderJob::formal(TextBuffer::with_text("  <\n /\n+"));
+        let wires = get_wires(&job.text);
+        let segments = job.wire_path_segments(&wires);
+        let tip = |seg: &PathSegment| matches!(seg, PathSegment::LineTo { x, y, .. } if *x == 35.0 && *y == -7.5);
+        assert_eq!(segments.iter().filter(|seg| tip(seg)).count(), 1);
+    }
+
+    #[test]
+    fn test_rounded_corner() {
+        let text = TextBuffer::with_text("+---+\n    |\n    +");
+        let mut job = RenderJob::formal(text);
+        let wires = get_wires(&job.text);
+        let curves = |segments: &[PathSegment]| {
+            segments
+                .iter()
+                .filter(|seg| matches!(seg, PathSegment::CurveTo { .. }))
+                .count()
+        };
+        let straight = job.wire_path_segments(&wires);
+        assert_eq!(curves(&straight), 0);
+        job.wire_style = WireStyle::Rounded;
+        let rounded = job.wire_path_segments(&wires);
+        assert_eq!(curves(&rounded), 1);
+        // The corner is centered on (45, 7.5), and the wires stop
+        // one radius short of it, where the fillet picks up.
+        let point = |seg: &PathSegment| match *seg {
+            PathSegment::MoveTo { x, y, .. }
+            | PathSegment::LineTo { x, y, .. }
+            | PathSegment::CurveTo { x, y, .. } => Some((x, y)),
+            _ => None,
+        };
+        let points = rounded.iter().filter_map(point).collect::<Vec<_>>();
+        assert!(points.contains(&(40.0, 7.5)));
+        assert!(points.contains(&(45.0, 12.5)));
+        assert!(!points.contains(&(45.0, 7.5)));
+        // The free ends of the wires are not rounded.
+        assert!(points.contains(&(5.0, 7.5)));
+        assert!(points.contains(&(45.0, 37.5)));
+    }
+
+    #[test]
+    fn test_leftward_arrow_draws_in_typed_cell() {
+        // Wires are normalized to run left to right, but the ends
+        // are drawn from the glyph in each cell, so the heads stay
+        // where they were typed.
+        for (text, tip_x) in [("+----<", 45.0), ("<----+", -5.0)] {
+            let job = RenderJob::formal(TextBuffer::with_text(text));
+            let wires = get_wires(&job.text);
+            assert_eq!(wires.len(), 1);
+            let segments = job.wire_path_segments(&wires);
+            let tips = segments
+                .iter()
+                .filter(|seg| matches!(seg, PathSegment::LineTo { x, y, .. } if *x == tip_x && *y == 7.5))
+                .count();
+            assert_eq!(tips, 1, "{text}");
+        }
+    }
+
+    #[test]
+    fn test_closed_arrow_style() {
+        let head = arrow_head(ArrowStyle::Closed);
+        assert_eq!(head.len(), 4);
+        assert!(matches!(head.last(), Some(PathSegment::ClosePath { .. })));
+    }
+
+    #[test]
+    fn test_open_arrow_style() {
+        let head = arrow_head(ArrowStyle::Open);
+        assert_eq!(head.len(), 3);
+        assert!(
+            !head
+                .iter()
+                .any(|seg| matches!(seg, PathSegment::ClosePath { .. }))
+        );
+    }
+
+    #[test]
+    fn test_no_arrow_style() {
+        assert!(arrow_head(ArrowStyle::None).is_empty());
+        let mut job = RenderJob::formal(TextBuffer::with_text("+-->"));
+        job.arrow_style = ArrowStyle::None;
+        let (labels, _) = job.invoke();
+        assert_eq!(labels.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_cross_wire_end() {
+        let job = RenderJob::formal(TextBuffer::with_text("+--x"));
+        let end = job.render_wire_end('x', TextCoordinate { x: 3, y: 0 });
+        assert_eq!(
+            end.iter()
+                .filter(|seg| matches!(seg, PathSegment::MoveTo { .. }))
+                .count(),
+            2
+        );
+        assert_eq!(
+            end.iter()
+                .filter(|seg| matches!(seg, PathSegment::LineTo { .. }))
+                .count(),
+            2
+        );
+        let (labels, _) = job.invoke();
+        assert_eq!(labels.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_dot_wire_end() {
+        let job = RenderJob::formal(TextBuffer::with_text("*\n|\n+"));
+        let end = job.render_wire_end('*', TextCoordinate { x: 0, y: 0 });
+        assert_eq!(
+            end.iter()
+                .filter(|seg| matches!(seg, PathSegment::CurveTo { .. }))
+                .count(),
+            4
+        );
+        let (labels, _) = job.invoke();
+        assert_eq!(labels.iter().count(), 0);
+        assert_eq!(
+            fill_ops(&job),
+            vec![
+                OpType::Move,
+                OpType::BCurveTo,
+                OpType::BCurveTo,
+                OpType::BCurveTo,
+                OpType::BCurveTo
+            ]
+        );
+    }
+
+    // The ops of the filled paths that `invoke` draws.
+    fn fill_ops(job: &RenderJob) -> Vec<OpType> {
+        job.invoke()
+            .1
+            .into_iter()
+            .flat_map(|drawable| drawable.sets)
+            .filter(|set| set.op_set_type == OpSetType::FillPath)
+            .flat_map(|set| set.ops)
+            .map(|op| op.op)
+            .collect()
+    }
+
+    #[test]
+    fn test_square_wire_end() {
+        let text = "+---#\n|\n+--->";
+        let job = RenderJob::formal(TextBuffer::with_text(text));
+        let end = job.render_wire_end('#', TextCoordinate { x: 4, y: 0 });
+        assert_eq!(
+            end.iter()
+                .filter(|seg| matches!(seg, PathSegment::ClosePath { .. }))
+                .count(),
+            1
+        );
+        let (labels, _) = job.invoke();
+        assert_eq!(labels.iter().count(), 0);
+        assert_eq!(
+            fill_ops(&job),
+            vec![OpType::Move, OpType::LineTo, OpType::LineTo, OpType::LineTo]
+        );
+        let groups = get_wire_groups(&job.text);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].len(), 3);
+        let stub = get_classified_wires(&job.text)
+            .into_iter()
+            .find(|w| w.wire.end == TextCoordinate { x: 4, y: 0 })
+            .unwrap();
+        assert_eq!(stub.end, Endpoint::Terminated);
+    }
+
+    // The arrow head drawn at `pos` when rendering the wires of `text`.
+    fn contains_head(text: &str, pos: TextCoordinate, ch: char) -> bool {
+        let job = RenderJob::formal(TextBuffer::with_text(text));
+        let segments = job.wire_path_segments(&get_wires(&job.text));
+        let head = job.render_wire_end(ch, pos);
+        segments.windows(head.len()).any(|w| w == head)
+    }
+
+    #[test]
+    fn test_label_ending_in_arrow_letter() {
+        // The `v` is the end of a word, not a corner
+        let job = RenderJob::formal(TextBuffer::with_text("rcv---->\n\nrcv+--->"));
+        assert_eq!(
+            get_wires(&job.text),
+            vec![LineSegment {
+                start: TextCoordinate { x: 3, y: 2 },
+                end: TextCoordinate { x: 7, y: 2 },
+            }]
+        );
+        let labels = job.labels();
+        assert_eq!(labels.get(TextCoordinate { x: 2, y: 0 }), Some('v'));
+        assert_eq!(labels.get(TextCoordinate { x: 2, y: 2 }), Some('v'));
+    }
+
+    #[test]
+    fn test_arrow_at_corner_points_along_outgoing_wire() {
+        // Flow comes in from the left, and turns down at the arrow
+        let text = "+--->\n    |\n    +";
+        let pos = TextCoordinate { x: 4, y: 0 };
+        assert!(contains_head(text, pos, 'v'));
+        assert!(!contains_head(text, pos, '>'));
+        // Flow comes down and leaves to the right, as drawn
+        let text = "+\n|\n>---+";
+        let pos = TextCoordinate { x: 0, y: 2 };
+        assert!(contains_head(text, pos, '>'));
+        assert!(!contains_head(text, pos, 'v'));
+        let (labels, _) = RenderJob::formal(TextBuffer::with_text(text)).invoke();
+        assert_eq!(labels.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_facing_arrows_share_one_marker() {
+        for text in ["><", "v\n^"] {
+            let job = RenderJob::formal(TextBuffer::with_text(text));
+            let wires = get_wires(&job.text);
+            assert_eq!(wires.len(), 1);
+            let segments = job.wire_path_segments(&wires);
+            assert!(job.labels().iter().next().is_none());
+            // The wire itself, and then a single closed marker
+            let count = |f: fn(&PathSegment) -> bool| segments.iter().filter(|seg| f(seg)).count();
+            assert_eq!(
+                count(|seg| matches!(seg, PathSegment::MoveTo { .. })),
+                2,
+                "{text}"
+            );
+            assert_eq!(
+                count(|seg| matches!(seg, PathSegment::ClosePath { .. })),
+                1,
+                "{text}"
+            );
+            let marker = job.meeting_heads(wires[0].start, wires[0].end);
+            assert!(segments.ends_with(&marker), "{text}");
+        }
+        // Arrows pointing away from each other are left alone
+        let job = RenderJob::formal(TextBuffer::with_text("<-->"));
+        let segments = job.wire_path_segments(&get_wires(&job.text));
+        assert_eq!(
+            segments
+                .iter()
+                .filter(|seg| matches!(seg, PathSegment::ClosePath { .. }))
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_heading_rule_is_underline_not_wire() {
+        // The rule under `Title` is an underline, but the run under
+        // `Wire` ends in `+`, so it is still a wire.
+        let job = RenderJob::formal(TextBuffer::with_text("Title\n-----\nWire\n+--+"));
+        let headings = get_headings(&job.text);
+        assert_eq!(headings.len(), 1);
+        assert_eq!(get_wires(&job.text).len(), 1);
+        let underline = job.heading_path_segments(&headings);
+        // The underline runs along the bottom of the `Title` row,
+        // from the left edge of the `T` to the right edge of the `e`.
+        assert_eq!(
+            underline,
+            vec![move_to(vec2(0.0, 15.0)), line_to(vec2(50.0, 15.0))]
+        );
+        let (labels, _) = job.invoke();
+        assert_eq!(
+            labels.iter().map(|(_, ch)| ch).collect::<String>(),
+            "TitleWire"
+        );
+        assert_eq!(job.labels(), labels);
+    }
+
+    #[test]
+    fn test_label_padding_insets_labels() {
+        let mut job = RenderJob::formal(TextBuffer::with_text("+------+\n|ab  cd|\n+------+"));
+        let centers = |job: &RenderJob| {
+            job.label_runs(&TextBuffer::with_text("\n ab  cd"))
+                .into_iter()
+                .map(|(_, _, baseline)| baseline.x)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(centers(&job), vec![15.0, 25.0, 55.0, 65.0]);
+        // The interior runs from 10 to 70, and is squeezed into 20
+        // to 60, so the labels move in, and a single row stays on
+        // its baseline.
+        job.label_padding = 1.0;
+        let padded = centers(&job);
+        assert!(padded[0] > 20.0 && padded[3] < 60.0);
+        assert!((padded[0] + padded[3] - 80.0).abs() < 1e-4);
+        let baseline = job.label_baseline(TextCoordinate { x: 1, y: 1 });
+        let runs = job.label_runs(&TextBuffer::with_text("\n a"));
+        assert!((runs[0].2.y - baseline.y).abs() < 1e-4);
+        // The padding never takes more than half the interior
+        job.label_padding = 100.0;
+        let padded = centers(&job);
+        assert!(padded[0] > 25.0 && padded[3] < 55.0);
+    }
+
+    #[test]
+    fn test_canvas_fits_sparse_buffer() {
+        let mut tb = TextBuffer::new(100, 200);
+        tb.paste("+--+\n|  |\n+--+", TextCoordinate { x: 150, y: 80 });
+        for job in [RenderJob::rough(tb.clone()), RenderJob::formal(tb)] {
+            assert_eq!((job.width, job.height), (40.0, 45.0));
+            assert_eq!((job.x0, job.y0), (0.0, 0.0));
+            assert_eq!(
+                job.text.size(),
+                crate::Size {
+                    num_rows: 3,
+                    num_cols: 4
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_hop_crossings_in_both_directions() {
+        // The crossing is centered on (35, 22.5), and the hop
+        // spans its cell.
+        let hop = |text: &str| {
+            let mut job = RenderJob::formal(TextBuffer::with_text(text));
+            job.hop_crossings = true;
+            let segments = job.wire_path_segments(&get_wires(&job.text));
+            let at = segments
+                .iter()
+                .position(|seg| matches!(seg, PathSegment::CurveTo { .. }))
+                .unwrap();
+            segments[at - 1..=at + 1].to_vec()
+        };
+        assert_eq!(
+            hop("   |\n+--+--+\n   |"),
+            vec![
+                line_to(vec2(30.0, 22.5)),
+                curve_to(vec2(30.0, 13.5), vec2(40.0, 13.5), vec2(40.0, 22.5)),
+                line_to(vec2(65.0, 22.5)),
+            ]
+        );
+        // Here the wire is drawn right to left, carrying on from the
+        // `+` on the right, so the hop is too.
+        assert_eq!(
+            hop("   |  +\n+--+--+\n   |"),
+            vec![
+                line_to(vec2(40.0, 22.5)),
+                curve_to(vec2(40.0, 13.5), vec2(30.0, 13.5), vec2(30.0, 22.5)),
+                line_to(vec2(5.0, 22.5)),
+            ]
+        );
+        // Without the option, the wire runs straight through.
+        let job = RenderJob::formal(TextBuffer::with_text("   |\n+--+--+\n   |"));
+        let segments = job.wire_path_segments(&get_wires(&job.text));
+        assert!(
+            !segments
+                .iter()
+                .any(|seg| matches!(seg, PathSegment::CurveTo { .. }))
+        );
+    }
 }
@@ -1,13 +1,50 @@
-use roughr::core::{Drawable, OpSetType, OpType};
+use roughr::core::{Drawable, LineCap, LineJoin, OpSet, OpSetType, OpType};
+use svg::{Node, node::element::Group};
 
 use crate::{
-    render::{RenderJob, vec2},
-    tc::TextCoordinate,
+    analyze::{get_rectangles, is_side_of},
+    rect::Rectangle,
+    render::{Element, RenderJob, SizeSpec},
 };
 
-pub fn stroke_opset(ops: Drawable<f32>, mut painter: svg::Document, color: &str) -> svg::Document {
+// Opacity used for the parts of the diagram outside of the focus.
+const DIMMED_OPACITY: f32 = 0.3;
+
+// The advance of a glyph in a typical monospace font, as a
+// fraction of the font size.
+const MONOSPACE_ADVANCE: f32 = 0.6;
+
+// The time (in seconds) each shape takes to fade in when animated.
+const ANIMATION_STEP: f32 = 0.25;
+
+/// The `class` of the stroked paths (wires, boxes and arrows).
+pub const STROKE_CLASS: &str = "badascii-stroke";
+/// The `class` of the filled marks at the ends of wires (junction
+/// dots and square terminals).
+pub const MARK_CLASS: &str = "badascii-mark";
+/// The `class` of the text labels.
+pub const LABEL_CLASS: &str = "badascii-label";
+/// The start of the `id` of the `<title>` element, which ends with
+/// a hash of the diagram (so that several diagrams on one page get
+/// different ids).
+pub const TITLE_ID: &str = "badascii-title";
+/// The start of the `id` of the `<desc>` element, which ends like
+/// the title's.
+pub const DESCRIPTION_ID: &str = "badascii-desc";
+/// The `class` of the filled boxes.
+pub const FILL_CLASS: &str = "badascii-fill";
+/// The `class` of the background rectangle.
+pub const BACKGROUND_CLASS: &str = "badascii-bg";
+
+// A suffix for the ids in the document, from the checksum of the
+// source text, so a diagram always gets the same ids.
+fn id_suffix(job: &RenderJob) -> String {
+    format!("{:016x}", job.text.checksum())
+}
+
+pub fn stroke_opset<T: Node>(ops: Drawable<f32>, mut painter: T, color: &str) -> T {
     for op_set in ops.sets {
-        if op_set.op_set_type != OpSetType::Path {
+        if !matches!(op_set.op_set_type, OpSetType::Path | OpSetType::FillPath) {
             continue;
         }
         let mut data = svg::node::element::path::Data::new();
@@ -24,61 +61,486 @@ pub fn stroke_opset(ops: Drawable<f32>, mut painter: svg::Document, color: &str)
                 }
             }
         }
-        let path = svg::node::element::Path::new()
+        if op_set.op_set_type == OpSetType::FillPath {
+            painter.append(
+                svg::node::element::Path::new()
+                    .set("class", MARK_CLASS)
+                    .set("fill", color)
+                    .set("stroke", "none")
+                    .set("d", data),
+            );
+            continue;
+        }
+        let mut path = svg::node::element::Path::new()
+            .set("class", STROKE_CLASS)
             .set("fill", "none")
             .set("stroke", color)
             .set("stroke-width", 1)
             .set("d", data);
-        painter = painter.add(path);
+        if let Some(dash) = &ops.options.stroke_line_dash {
+            let dash = dash.iter().map(f64::to_string).collect::<Vec<_>>();
+            path = path.set("stroke-dasharray", dash.join(" "));
+        }
+        if let Some(cap) = ops.options.line_cap {
+            let cap = match cap {
+                LineCap::Butt => "butt",
+                LineCap::Round => "round",
+                LineCap::Square => "square",
+            };
+            path = path.set("stroke-linecap", cap);
+        }
+        match ops.options.line_join {
+            Some(LineJoin::Miter { limit }) => {
+                path = path
+                    .set("stroke-linejoin", "miter")
+                    .set("stroke-miterlimit", limit);
+            }
+            Some(LineJoin::Round) => path = path.set("stroke-linejoin", "round"),
+            Some(LineJoin::Bevel) => path = path.set("stroke-linejoin", "bevel"),
+            None => {}
+        }
+        painter.append(path);
+    }
+    painter
+}
+
+// Split the drawables into one per stroke (each starting with a
+// move), so that the strokes can be animated one at a time.  The
+// filled marks fade in on their own.
+fn split_strokes(drawables: Vec<Drawable<f32>>) -> Vec<Drawable<f32>> {
+    let mut strokes = vec![];
+    for drawable in drawables {
+        for op_set in drawable.sets {
+            if op_set.op_set_type == OpSetType::FillPath {
+                strokes.push(Drawable {
+                    shape: drawable.shape.clone(),
+                    options: drawable.options.clone(),
+                    sets: vec![op_set],
+                });
+                continue;
+            }
+            if op_set.op_set_type != OpSetType::Path {
+                continue;
+            }
+            for op in op_set.ops {
+                let in_stroke = strokes
+                    .last()
+                    .is_some_and(|last| last.sets[0].op_set_type == OpSetType::Path);
+                if op.op == OpType::Move || !in_stroke {
+                    strokes.push(Drawable {
+                        shape: drawable.shape.clone(),
+                        options: drawable.options.clone(),
+                        sets: vec![OpSet {
+                            op_set_type: OpSetType::Path,
+                            ops: vec![],
+                            size: None,
+                            path: None,
+                        }],
+                    });
+                }
+                if let Some(stroke) = strokes.last_mut() {
+                    stroke.sets[0].ops.push(op);
+                }
+            }
+        }
+    }
+    strokes
+}
+
+// The point a stroke starts from, which orders the animation.
+fn stroke_start(stroke: &Drawable<f32>) -> (f32, f32) {
+    let data = &stroke.sets[0].ops[0].data;
+    (data[0], data[1])
+}
+
+// The `id` of the group holding the sides of a box, from the
+// position and size (in cells) of the box.
+fn rect_id(rect: &Rectangle) -> String {
+    format!(
+        "rect-{}-{}-{}-{}",
+        rect.left(),
+        rect.top(),
+        rect.width(),
+        rect.height()
+    )
+}
+
+// Draw each element into a group with its `id`.  The wires along
+// the sides of a box go into a group for the box, which comes
+// first.  A wire that is the side of two boxes goes with the first.
+fn add_elements(
+    mut painter: Group,
+    elements: Vec<(Element, Drawable<f32>)>,
+    boxes: &[Rectangle],
+    color: &str,
+) -> Group {
+    let mut box_groups = boxes
+        .iter()
+        .map(|rect| Group::new().set("id", rect_id(rect)))
+        .collect::<Vec<_>>();
+    let mut loose = vec![];
+    for (element, ops) in elements {
+        let group = stroke_opset(ops, Group::new().set("id", element.id()), color);
+        let side_of = match element {
+            Element::Wire(wire) => boxes.iter().position(|rect| is_side_of(rect, &wire)),
+            _ => None,
+        };
+        match side_of {
+            Some(index) => box_groups[index].append(group),
+            None => loose.push(group),
+        }
+    }
+    for group in box_groups {
+        if group
+            .get_children()
+            .is_some_and(|children| !children.is_empty())
+        {
+            painter.append(group);
+        }
+    }
+    for group in loose {
+        painter.append(group);
     }
     painter
 }
 
+// Hide the group, and fade it in at `begin` seconds.
+fn fade_in(group: Group, begin: f32) -> Group {
+    group.set("opacity", 0).add(
+        svg::node::element::Animate::new()
+            .set("attributeName", "opacity")
+            .set("from", 0)
+            .set("to", 1)
+            .set("begin", format!("{begin}s"))
+            .set("dur", format!("{ANIMATION_STEP}s"))
+            .set("fill", "freeze"),
+    )
+}
+
 pub fn render(job: &RenderJob, color: &str, background: &str) -> String {
+    // The padding extends the view box on all sides, and leaves
+    // the diagram itself where it is.
+    let origin = 0.0 - job.padding;
+    let width = job.width + 2.0 * job.padding;
+    let height = job.height + 2.0 * job.padding;
+    // The height follows the width, so that the aspect ratio is
+    // kept.  A percentage width leaves the height to the viewBox.
+    let (display_width, display_height) = match job.display_width {
+        SizeSpec::Auto => (format!("{width}px"), Some(format!("{height}px"))),
+        SizeSpec::Fixed(fixed) => (
+            format!("{fixed}px"),
+            Some(format!("{}px", fixed * height / width)),
+        ),
+        SizeSpec::Percent(percent) => (format!("{percent}%"), None),
+    };
     let mut context = svg::Document::new()
-        .set("width", format!("{}px", job.width))
-        .set("viewBox", (0.0, 0.0, job.width, job.height));
+        .set("width", display_width)
+        .set("viewBox", (origin, origin, width, height));
+    if let Some(display_height) = display_height {
+        context = context.set("height", display_height);
+    }
+    // The title and description come first, so that assistive
+    // technology finds them.
+    let mut labelled_by = vec![];
+    let suffix = id_suffix(job);
+    if let Some(title) = &job.title {
+        let id = format!("{TITLE_ID}-{suffix}");
+        context =
+            context.add(svg::node::element::Title::new(title.as_str()).set("id", id.as_str()));
+        labelled_by.push(id);
+    }
+    if let Some(description) = &job.description {
+        let id = format!("{DESCRIPTION_ID}-{suffix}");
+        let mut desc = svg::node::element::Description::new().set("id", id.as_str());
+        desc.append(svg::node::Text::new(description.as_str()));
+        context = context.add(desc);
+        labelled_by.push(id);
+    }
+    if !labelled_by.is_empty() {
+        context = context
+            .set("role", "img")
+            .set("aria-labelledby", labelled_by.join(" "));
+    }
+    if job.embed_source {
+        let mut metadata = svg::node::element::Element::new("metadata");
+        metadata.assign("class", crate::import::SOURCE_CLASS);
+        metadata.append(svg::node::Text::new(job.text.render()));
+        context = context.add(metadata);
+    }
     if background != "none" {
         context = context.add(
             svg::node::element::Rectangle::new()
+                .set("class", BACKGROUND_CLASS)
                 .set("fill", background)
                 .set("stroke", "none")
-                .set("width", format!("{}px", job.width))
-                .set("height", format!("{}px", job.height))
-                .set("x", "0.0")
-                .set("y", "0.0"),
+                .set("width", format!("{width}px"))
+                .set("height", format!("{height}px"))
+                .set("x", format!("{origin:?}"))
+                .set("y", format!("{origin:?}")),
         )
     }
-    let delta_x = job.width / job.text.size().num_cols as f32;
-    let delta_y = job.height / job.text.size().num_rows as f32;
-    let (labels, drawables) = job.invoke();
-    let pos_map = |pos: TextCoordinate| {
-        vec2(pos.x as f32 * delta_x, pos.y as f32 * delta_y) + vec2(0.5 * delta_x, 0.5 * delta_y)
-    };
-    for op in drawables {
-        context = stroke_opset(op, context, color);
+    if let Some(fill_color) = &job.fill_color {
+        for (left_top, right_bottom) in job.fills() {
+            context = context.add(
+                svg::node::element::Rectangle::new()
+                    .set("class", FILL_CLASS)
+                    .set("fill", fill_color.as_str())
+                    .set("stroke", "none")
+                    .set("x", left_top.x - job.x0)
+                    .set("y", left_top.y - job.y0)
+                    .set("width", right_bottom.x - left_top.x)
+                    .set("height", right_bottom.y - left_top.y),
+            );
+        }
     }
-    let text_size = delta_x.min(delta_y) * 1.6;
-    for (coord, word) in labels.iter() {
-        let center = pos_map(coord);
+    // The strokes and labels are placed relative to `(x0, y0)`, so
+    // they are drawn into a group that is moved back to the origin.
+    let mut diagram = Group::new();
+    let mut dimmed_group = Group::new().set("opacity", DIMMED_OPACITY);
+    let mut begin = 0.0;
+    let labels = if job.element_ids && !job.animate {
+        let (labels, elements) = job.invoke_elements();
+        let (focused, dimmed): (Vec<_>, Vec<_>) = elements
+            .into_iter()
+            .partition(|(element, _)| job.focus.is_none_or(|focus| element.within(&focus)));
+        let boxes = get_rectangles(&job.text);
+        diagram = add_elements(diagram, focused, &boxes, color);
+        dimmed_group = add_elements(dimmed_group, dimmed, &boxes, color);
+        labels
+    } else {
+        let (labels, drawables, dimmed) = match &job.focus {
+            Some(focus) => job.invoke_focused(focus),
+            None => {
+                let (labels, drawables) = job.invoke();
+                (labels, drawables, vec![])
+            }
+        };
+        if job.animate {
+            let mut strokes = split_strokes(drawables);
+            strokes.sort_by(|a, b| {
+                let (ax, ay) = stroke_start(a);
+                let (bx, by) = stroke_start(b);
+                ay.total_cmp(&by).then(ax.total_cmp(&bx))
+            });
+            for op in strokes {
+                diagram = diagram.add(fade_in(stroke_opset(op, Group::new(), color), begin));
+                begin += ANIMATION_STEP;
+            }
+        } else {
+            for op in drawables {
+                diagram = stroke_opset(op, diagram, color);
+            }
+        }
+        for op in dimmed {
+            dimmed_group = stroke_opset(op, dimmed_group, color);
+        }
+        labels
+    };
+    let text_color = job.text_color.as_deref().unwrap_or(color);
+    let text_size = job.label_size();
+    let merged_labels = job.merged_labels && !job.proportional_labels;
+    // The labels come in row major order, so the output is
+    // byte-for-byte stable no matter how the labels were found.
+    let runs = if merged_labels {
+        let boxes = job.padded_boxes();
+        labels
+            .words()
+            .map(|(pos, word)| {
+                let baseline = job.inset_label(pos, job.label_baseline(pos), &boxes);
+                (pos, word, baseline)
+            })
+            .collect()
+    } else {
+        job.label_runs(&labels)
+    };
+    let delta_x = job.width / job.text.size().num_cols as f32;
+    // Rounded, so float noise (like 0.39999962) stays out of the SVG.
+    let spacing = ((delta_x - MONOSPACE_ADVANCE * text_size) * 1000.0).round() / 1000.0;
+    let mut label_group = Group::new();
+    for (coord, word, baseline) in runs {
         let text = svg::node::element::Text::new(word)
-            .set("x", center.x)
-            .set("y", center.y)
-            .set("font-family", "monospace")
+            .set("class", LABEL_CLASS)
+            .set("y", baseline.y)
+            .set("font-family", job.font_family.as_str())
             .set("font-size", text_size)
-            .set("text-anchor", "middle")
-            .set("dominant-baseline", "middle")
-            .set("fill", color);
-        context = context.add(text);
+            .set("fill", text_color);
+        // A merged word starts at the left of its first glyph, as
+        // the letter spacing also follows the last glyph and would
+        // throw off the centering.
+        let text = if merged_labels {
+            text.set("x", baseline.x - (MONOSPACE_ADVANCE * text_size) / 2.0)
+                .set("text-anchor", "start")
+                .set("letter-spacing", spacing)
+        } else {
+            text.set("x", baseline.x).set("text-anchor", "middle")
+        };
+        if job.focus.is_some_and(|focus| !focus.contains(&coord)) {
+            dimmed_group = dimmed_group.add(text);
+        } else {
+            label_group = label_group.add(text);
+        }
+    }
+    if job.animate {
+        diagram = diagram.add(fade_in(label_group, begin));
+    } else {
+        for node in label_group
+            .get_children_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+        {
+            diagram.append(node);
+        }
+    }
+    if job.focus.is_some() {
+        diagram = diagram.add(dimmed_group);
+    }
+    if job.x0 == 0.0 && job.y0 == 0.0 {
+        for node in diagram
+            .get_children_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+        {
+            context.append(node);
+        }
+    } else {
+        let offset = format!("translate({} {})", -job.x0, -job.y0);
+        context = context.add(diagram.set("transform", offset));
     }
     context.to_string()
 }
 
+/// Strip the insignificant whitespace out of a rendered SVG.  The
+/// line breaks between elements and around the label text go, as do
+/// trailing zeros on the numbers in attribute values.  The geometry
+/// is unchanged, and an embedded source is kept verbatim, so that
+/// the result still imports.
+pub fn minify(svg: &str) -> String {
+    let mut out = String::with_capacity(svg.len());
+    let mut rest = svg;
+    let mut in_metadata = false;
+    while !rest.is_empty() {
+        if let Some(tag) = rest.strip_prefix('<') {
+            let end = tag_end(tag).map_or(rest.len(), |ndx| ndx + 2);
+            let tag = &rest[..end];
+            in_metadata = if in_metadata {
+                !tag.starts_with("</metadata")
+            } else {
+                tag.starts_with("<metadata") && !tag.ends_with("/>")
+            };
+            minify_tag(tag, &mut out);
+            rest = &rest[end..];
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = &rest[..end];
+            if in_metadata {
+                out.push_str(text);
+            } else {
+                out.extend(text.chars().filter(|&ch| ch != '\n' && ch != '\r'));
+            }
+            rest = &rest[end..];
+        }
+    }
+    out
+}
+
+// The offset of the closing `>` of a tag, skipping over any quoted
+// attribute values.
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    for (ndx, ch) in tag.char_indices() {
+        match (quote, ch) {
+            (None, '"' | '\'') => quote = Some(ch),
+            (Some(q), ch) if q == ch => quote = None,
+            (None, '>') => return Some(ndx),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn minify_tag(tag: &str, out: &mut String) {
+    let mut quote = None;
+    let mut numeric = false;
+    let mut value = String::new();
+    for ch in tag.chars() {
+        match quote {
+            Some(q) if q == ch => {
+                if numeric {
+                    out.push_str(&trim_zeros(&value));
+                } else {
+                    out.push_str(&value);
+                }
+                value.clear();
+                out.push(ch);
+                quote = None;
+            }
+            Some(_) => value.push(ch),
+            None => {
+                if ch == '"' || ch == '\'' {
+                    quote = Some(ch);
+                    numeric = attribute_name(out).is_some_and(|name| NUMERIC_ATTRS.contains(&name));
+                }
+                out.push(ch);
+            }
+        }
+    }
+}
+
+// The attributes whose values are numbers (or lists of them), and so
+// can have their trailing zeros trimmed.  Anything else, like a font
+// name or a title, is copied as is.
+const NUMERIC_ATTRS: &[&str] = &[
+    "d",
+    "x",
+    "y",
+    "width",
+    "height",
+    "viewBox",
+    "transform",
+    "font-size",
+    "stroke-width",
+    "letter-spacing",
+];
+
+// The name of the attribute whose `=` ends `tag`, if any.
+fn attribute_name(tag: &str) -> Option<&str> {
+    let name = tag.trim_end().strip_suffix('=')?.trim_end();
+    name.rsplit(|ch: char| ch.is_whitespace() || ch == '<')
+        .next()
+}
+
+// Trim the trailing zeros (and a bare trailing `.`) from each
+// decimal number in an attribute value, so `1.50` becomes `1.5`
+// and `8.0` becomes `8`.
+fn trim_zeros(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut number = String::new();
+    let flush = |number: &mut String, out: &mut String| {
+        if number.contains('.') && number.chars().filter(|&ch| ch == '.').count() == 1 {
+            let trimmed = number.trim_end_matches('0').trim_end_matches('.');
+            out.push_str(if trimmed.is_empty() { "0" } else { trimmed });
+        } else {
+            out.push_str(number);
+        }
+        number.clear();
+    };
+    for ch in value.chars() {
+        if ch.is_ascii_digit() || ch == '.' {
+            number.push(ch);
+        } else {
+            flush(&mut number, &mut out);
+            out.push(ch);
+        }
+    }
+    flush(&mut number, &mut out);
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use expect_test::expect_file;
 
-    use crate::text_buffer::TextBuffer;
+    use crate::{render::SizeSpec, tc::TextCoordinate, text_buffer::TextBuffer};
 
     use super::*;
 
@@ -100,12 +562,8 @@ v    |                     |   |
         tb.paste(INITIAL_TEXT, TextCoordinate { x: 5, y: 5 });
         let svg = crate::svg::render(
             &RenderJob {
-                width: 600.0,
-                height: 450.0,
-                text: tb,
                 options: roughr::core::Options::default(),
-                x0: 0.0,
-                y0: 0.0,
+                ..RenderJob::new(tb, 600.0, 450.0)
             },
             "white",
             "none",
@@ -153,16 +611,450 @@ v    |                     |   |
         tb.paste(TEST_TEXT, TextCoordinate { x: 5, y: 5 });
         let svg = crate::svg::render(
             &RenderJob {
-                width: 1000.0,
-                height: 40.0 * 15.0,
-                text: tb,
                 options: roughr::core::Options::default(),
-                x0: 0.0,
-                y0: 0.0,
+                ..RenderJob::new(tb, 1000.0, 40.0 * 15.0)
             },
             "white",
             "black",
         );
         expect_file!["rough.svg"].assert_eq(&svg);
     }
+
+    #[test]
+    fn test_text_color_defaults_to_stroke_color() {
+        let tb = TextBuffer::with_text("+--> ok");
+        let mut job = RenderJob::formal(tb);
+        let svg = crate::svg::render(&job, "gray", "none");
+        assert!(svg.contains(r#"fill="gray""#));
+        job.text_color = Some("black".into());
+        let svg = crate::svg::render(&job, "gray", "none");
+        assert!(svg.contains(r#"stroke="gray""#));
+        assert!(svg.contains(r#"fill="black""#));
+        assert!(!svg.contains(r#"fill="gray""#));
+    }
+
+    #[test]
+    fn test_font_family() {
+        let tb = TextBuffer::with_text("+--> ok");
+        let mut job = RenderJob::formal(tb);
+        assert!(crate::svg::render(&job, "gray", "none").contains(r#"font-family="monospace""#));
+        job.font_family = "Fira Code".into();
+        let svg = crate::svg::render(&job, "gray", "none");
+        assert!(svg.contains(r#"font-family="Fira Code""#));
+    }
+
+    #[test]
+    fn test_focus_dims_outside_geometry() {
+        const TEXT: &str = "
++---+     +---+
+| a +---->+ b |
++---+     +---+
+";
+        let tb = TextBuffer::with_text(TEXT);
+        let mut job = RenderJob::formal(tb);
+        job.focus = Some(crate::rect::Rectangle::new(
+            TextCoordinate { x: 0, y: 0 },
+            TextCoordinate { x: 4, y: 3 },
+        ));
+        let svg = crate::svg::render(&job, "black", "none");
+        assert_eq!(svg.matches(r#"<g opacity="0.3">"#).count(), 1);
+        expect_file!["focus.svg"].assert_eq(&svg);
+    }
+
+    #[test]
+    fn test_callout_renders_circled_digit() {
+        let tb = TextBuffer::with_text("(1)");
+        let svg = render(&RenderJob::formal(tb), "#808080", "none");
+        assert!(svg.contains(">\n1\n</text>"));
+        assert!(!svg.contains(">\n(\n</text>"));
+        assert!(!svg.contains(">\n)\n</text>"));
+        assert!(svg.contains("<path"));
+    }
+
+    #[test]
+    fn test_labels_in_row_major_order() {
+        let tb = TextBuffer::with_text("b a\n  c\nd");
+        let svg = render(&RenderJob::formal(tb), "#808080", "none");
+        let labels = svg
+            .lines()
+            .filter(|line| line.len() == 1)
+            .collect::<String>();
+        assert_eq!(labels, "bacd");
+    }
+
+    #[test]
+    fn test_dashed_wire_into_solid_box() {
+        const TEXT: &str = "
+         +-----+
++=======>|     |
+         +-----+
+";
+        let tb = TextBuffer::with_text(TEXT);
+        let svg = render(&RenderJob::formal(tb), "#808080", "none");
+        let paths = svg
+            .lines()
+            .filter(|line| line.starts_with("<path"))
+            .collect::<Vec<_>>();
+        assert_eq!(paths.len(), 2);
+        assert!(!paths[0].contains("stroke-dasharray"));
+        assert!(paths[1].contains("stroke-dasharray"));
+    }
+
+    #[test]
+    fn test_dashed_box_border() {
+        let paths = |text: &str| {
+            let svg = render(
+                &RenderJob::formal(TextBuffer::with_text(text)),
+                "#808080",
+                "none",
+            );
+            svg.lines()
+                .filter(|line| line.starts_with("<path"))
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        let solid = paths("+--+\n|ok|\n+--+");
+        assert_eq!(solid.len(), 1);
+        assert!(!solid[0].contains("stroke-dasharray"));
+        let dashed = paths("+==+\n:ok:\n+==+");
+        assert_eq!(dashed.len(), 1);
+        assert!(dashed[0].contains("stroke-dasharray"));
+        // Each side keeps its own style.
+        let mixed = paths("+==+\n|ok|\n+--+");
+        assert_eq!(mixed.len(), 2);
+        assert_eq!(
+            mixed
+                .iter()
+                .filter(|path| path.contains("stroke-dasharray"))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_filled_box() {
+        let text = "+----+  +--+\n|░░░░|  |░ |\n|░░░░|  +--+\n+----+";
+        let mut job = RenderJob::formal(TextBuffer::with_text(text));
+        let svg = render(&job, "#808080", "none");
+        assert!(!svg.contains(FILL_CLASS));
+        assert!(svg.contains("░"));
+        job.fill_color = Some("#336699".into());
+        let svg = render(&job, "#808080", "none");
+        let fills = svg
+            .lines()
+            .filter(|line| line.contains(FILL_CLASS))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fills,
+            vec![
+                r##"<rect class="badascii-fill" fill="#336699" height="45" stroke="none" width="50" x="5" y="7.5"/>"##
+            ]
+        );
+        // The partly filled box keeps its fill character as a label.
+        assert_eq!(svg.matches("░").count(), 1);
+    }
+
+    #[test]
+    fn test_title_and_description() {
+        let mut job = RenderJob::formal(TextBuffer::with_text("+--> ok"));
+        let svg = render(&job, "#808080", "none");
+        assert!(!svg.contains("<title"));
+        assert!(!svg.contains("role="));
+        job.title = Some("A wire".into());
+        job.description = Some("A wire that says <ok>".into());
+        let svg = render(&job, "#808080", "none");
+        let mut lines = svg.lines();
+        assert!(lines.next().unwrap().starts_with(
+            r#"<svg aria-labelledby="badascii-title-ede4e1ac2aa943ae badascii-desc-ede4e1ac2aa943ae" height="15px" role="img""#
+        ));
+        assert_eq!(
+            lines.next().unwrap(),
+            r#"<title id="badascii-title-ede4e1ac2aa943ae">A wire</title>"#
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            r#"<desc id="badascii-desc-ede4e1ac2aa943ae">A wire that says &lt;ok&gt;</desc>"#
+        );
+        // Another diagram gets other ids, so both can be on one page
+        let mut other = RenderJob::formal(TextBuffer::with_text("+--> no"));
+        other.title = job.title.clone();
+        let other = render(&other, "#808080", "none");
+        assert!(!other.contains("badascii-title-ede4e1ac2aa943ae"));
+        assert!(other.contains(r#"<title id="badascii-title-"#));
+    }
+
+    #[test]
+    fn test_element_ids() {
+        let text = TextBuffer::with_text("+--+\n|  +--->\n+--+  (1)");
+        let mut job = RenderJob::formal(text.clone());
+        job.element_ids = true;
+        let svg = render(&job, "#808080", "none");
+        let box_group = r#"<g id="rect-0-0-4-3">"#;
+        let wire_group = r#"<g id="wire-3-1-7-1">"#;
+        assert_eq!(svg.matches(box_group).count(), 1);
+        assert_eq!(svg.matches(wire_group).count(), 1);
+        assert_eq!(svg.matches(r#"<g id="callout-6-2">"#).count(), 1);
+        // The wire leaving the box is not one of its sides
+        assert!(svg.find(box_group) < svg.find(wire_group));
+        assert_eq!(svg.matches("<g id=\"wire-").count(), 5);
+        assert_eq!(svg, render(&job, "#808080", "none"));
+        // The ids are left out unless asked for
+        let plain = render(&RenderJob::formal(text), "#808080", "none");
+        assert!(!plain.contains("<g id="));
+        // Ids keep the full coordinates, even far across the diagram
+        let mut job = RenderJob::formal(TextBuffer::with_text(&format!(
+            "+--->{}+--->",
+            " ".repeat(251)
+        )));
+        job.element_ids = true;
+        let svg = render(&job, "#808080", "none");
+        assert_eq!(svg.matches(r#"<g id="wire-0-0-4-0">"#).count(), 1);
+        assert_eq!(svg.matches(r#"<g id="wire-256-0-260-0">"#).count(), 1);
+    }
+
+    #[test]
+    fn test_solid_marks_are_filled() {
+        let job = RenderJob::formal(TextBuffer::with_text("*--#"));
+        let svg = render(&job, "#808080", "none");
+        let marks = svg
+            .lines()
+            .filter(|line| line.contains(&format!(r#"class="{MARK_CLASS}""#)))
+            .collect::<Vec<_>>();
+        assert_eq!(marks.len(), 1);
+        assert!(marks[0].contains(r##"fill="#808080""##));
+        assert!(marks[0].contains(r#"stroke="none""#));
+    }
+
+    #[test]
+    fn test_merged_labels() {
+        let mut job = RenderJob::formal(TextBuffer::with_text("+--> hello"));
+        let svg = render(&job, "#808080", "none");
+        assert_eq!(svg.matches("<text").count(), 5);
+        job.merged_labels = true;
+        let svg = render(&job, "#808080", "none");
+        let texts = svg
+            .lines()
+            .filter(|line| line.starts_with("<text"))
+            .collect::<Vec<_>>();
+        assert_eq!(texts.len(), 1);
+        assert!(texts[0].contains(r#"letter-spacing="0.4""#));
+        assert!(texts[0].contains(r#"text-anchor="start""#));
+        assert!(texts[0].contains(r#"x="50.2""#));
+        assert!(svg.contains(">\nhello\n</text>"));
+    }
+
+    #[test]
+    fn test_class_attributes() {
+        let tb = TextBuffer::with_text("+--> ok");
+        let svg = render(&RenderJob::formal(tb), "#808080", "#000000");
+        assert!(svg.contains(&format!("<rect class=\"{BACKGROUND_CLASS}\"")));
+        assert!(svg.contains(&format!("<path class=\"{STROKE_CLASS}\"")));
+        assert!(svg.contains(&format!("<text class=\"{LABEL_CLASS}\"")));
+        assert_eq!(svg.matches(LABEL_CLASS).count(), 2);
+    }
+
+    #[test]
+    fn test_padding_expands_view_box() {
+        let tb = TextBuffer::with_text("+--+\n|ok|\n+--+");
+        let mut job = RenderJob::formal(tb);
+        job.padding = 8.0;
+        let svg = render(&job, "#808080", "#000000");
+        assert!(svg.contains(r#"viewBox="-8 -8 56 61""#));
+        expect_file!["padded.svg"].assert_eq(&svg);
+    }
+
+    #[test]
+    fn test_label_padding() {
+        let tb = TextBuffer::with_text("+------+\n|ab    |\n|    cd|\n+------+\nef");
+        let mut job = RenderJob::formal(tb);
+        let plain = render(&job, "#808080", "none");
+        job.label_padding = 1.0;
+        let svg = render(&job, "#808080", "none");
+        // The `a` moves in from the left border, the `d` in from the
+        // right border, and the `e` outside the box stays put.
+        for cell in [r#"x="15" y="28.1""#, r#"x="65" y="43.1""#] {
+            assert!(plain.contains(cell) && !svg.contains(cell));
+        }
+        assert!(plain.contains(r#"x="5" y="73.1""#) && svg.contains(r#"x="5" y="73.1""#));
+        expect_file!["label_padding.svg"].assert_eq(&svg);
+    }
+
+    #[test]
+    fn test_line_cap_and_join() {
+        let tb = TextBuffer::with_text("+--+\n|  |-->\n+--+");
+        let mut job = RenderJob::formal(tb);
+        let svg = render(&job, "#808080", "none");
+        assert!(svg.contains(r#"stroke-linecap="round""#));
+        assert!(svg.contains(r#"stroke-linejoin="round""#));
+        job.options.line_cap = Some(LineCap::Square);
+        job.options.line_join = Some(LineJoin::Miter { limit: 4.0 });
+        let svg = render(&job, "#808080", "none");
+        assert!(!svg.contains("round"));
+        expect_file!["line_cap.svg"].assert_eq(&svg);
+    }
+
+    #[test]
+    fn test_offset_canvas_starts_at_origin() {
+        let tb = TextBuffer::with_text("+--+\n|ok|\n+--+");
+        let aligned = render(&RenderJob::formal(tb.clone()), "#808080", "none");
+        let mut job = RenderJob::formal(tb);
+        job.x0 = 250.0;
+        job.y0 = -75.0;
+        let svg = render(&job, "#808080", "none");
+        assert!(svg.contains(r#"viewBox="0 0 40 45""#));
+        assert!(svg.contains(r#"<g transform="translate(-250 75)">"#));
+        // Undoing the translation gives back the aligned drawing.
+        let first_path = |svg: &str| {
+            let start = svg.find(" d=\"").unwrap() + 4;
+            svg[start..].split('"').next().unwrap().to_string()
+        };
+        let shifted = first_path(&svg);
+        let first = shifted
+            .split(|c: char| c.is_ascii_alphabetic())
+            .find(|part| !part.is_empty())
+            .unwrap();
+        let (x, y) = first.split_once(',').unwrap();
+        let x = x.trim().parse::<f32>().unwrap() - 250.0;
+        let y = y.trim().parse::<f32>().unwrap() + 75.0;
+        assert!((0.0..=40.0).contains(&x) && (0.0..=45.0).contains(&y));
+        assert!(first_path(&aligned).starts_with(&format!("M{x},{y}")));
+        // The labels move with the strokes, and land in the same
+        // places once the translation is undone.
+        let labels = |svg: &str, dx: f32, dy: f32| {
+            svg.lines()
+                .filter(|line| line.contains(LABEL_CLASS))
+                .map(|line| {
+                    let attr = |name: &str| {
+                        let start = line.find(&format!(" {name}=\"")).unwrap() + name.len() + 3;
+                        line[start..]
+                            .split('"')
+                            .next()
+                            .unwrap()
+                            .parse::<f32>()
+                            .unwrap()
+                    };
+                    (attr("x") + dx, attr("y") + dy)
+                })
+                .collect::<Vec<_>>()
+        };
+        let shifted = labels(&svg, -250.0, 75.0);
+        assert_eq!(shifted.len(), 2);
+        assert!(
+            shifted
+                .iter()
+                .all(|(x, y)| (0.0..=40.0).contains(x) && (0.0..=45.0).contains(y)),
+            "{shifted:?}"
+        );
+        let aligned = labels(&aligned, 0.0, 0.0);
+        assert!(
+            shifted
+                .iter()
+                .zip(&aligned)
+                .all(|(a, b)| (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3),
+            "{shifted:?} vs {aligned:?}"
+        );
+    }
+
+    #[test]
+    fn test_display_width() {
+        let tb = TextBuffer::with_text("+--+\n|ok|\n+--+");
+        let mut job = RenderJob::formal(tb);
+        job.padding = 5.0;
+        let svg = render(&job, "#808080", "none");
+        assert!(svg.starts_with(r#"<svg height="55px" viewBox="-5 -5 50 55" width="50px""#));
+        job.display_width = SizeSpec::Fixed(320.0);
+        let svg = render(&job, "#808080", "none");
+        assert!(svg.starts_with(r#"<svg height="352px" viewBox="-5 -5 50 55" width="320px""#));
+        job.display_width = SizeSpec::Percent(100.0);
+        let svg = render(&job, "#808080", "none");
+        assert!(svg.starts_with(r#"<svg viewBox="-5 -5 50 55" width="100%""#));
+    }
+
+    #[test]
+    fn test_frame_around_padded_canvas() {
+        let tb = TextBuffer::with_text("ok");
+        let mut job = RenderJob::formal(tb);
+        job.padding = 4.0;
+        assert!(!render(&job, "#808080", "none").contains("<path"));
+        job.frame = true;
+        let svg = render(&job, "#808080", "none");
+        assert_eq!(svg.matches("<path").count(), 1);
+        assert!(svg.contains("M-3.5,-3.5"));
+    }
+
+    #[test]
+    fn test_proportional_labels_are_one_run() {
+        let tb = TextBuffer::with_text("+--+  the quick fox\n|  |\n+--+  a  b");
+        let mut job = RenderJob::formal(tb);
+        job.proportional_labels = true;
+        job.font_family = "sans-serif".into();
+        let svg = render(&job, "#808080", "none");
+        assert_eq!(svg.matches("<text").count(), 3);
+        assert!(svg.contains(">\nthe quick fox\n</text>"));
+        assert!(!svg.contains(">\nq\n</text>"));
+        // Centered on the cells of the phrase
+        assert!(svg.contains(r#"text-anchor="middle" x="125""#));
+    }
+
+    #[test]
+    fn test_minify() {
+        let tb = TextBuffer::with_text("+----+\n| hi |--->\n+----+\n  ok");
+        let mut job = RenderJob::formal(tb.clone());
+        job.embed_source = true;
+        let svg = render(&job, "#808080", "#0A0A0A");
+        let minified = minify(&svg);
+        assert!(minified.len() < svg.len());
+        assert!(minified.contains(">h</text>"));
+        assert_eq!(
+            minified.matches("<path").count(),
+            svg.matches("<path").count()
+        );
+        let source = crate::import::from_svg(&minified).unwrap();
+        assert_eq!(source.render(), tb.render());
+        // Only numeric attributes lose their trailing zeros
+        job.font_family = "Font 1.50".into();
+        let minified = minify(&render(&job, "#808080", "#0A0A0A"));
+        assert!(minified.contains(r#"font-family="Font 1.50""#));
+        assert_eq!(
+            minify(r#"<rect x="1.50" y='2.0' data-v="3.0"/>"#),
+            r#"<rect x="1.5" y='2' data-v="3.0"/>"#
+        );
+    }
+
+    #[test]
+    fn test_trim_zeros() {
+        assert_eq!(trim_zeros("M1.50,8.0 L10,0.0"), "M1.5,8 L10,0");
+        assert_eq!(trim_zeros("#0A0A0A"), "#0A0A0A");
+        assert_eq!(
+            trim_zeros("translate(-100 -2.250)"),
+            "translate(-100 -2.25)"
+        );
+    }
+
+    #[test]
+    fn test_animate() {
+        let tb = TextBuffer::with_text("+--+  +--+\n|ok|->|  |\n+--+  +--+");
+        let mut job = RenderJob::formal(tb);
+        assert!(!render(&job, "#808080", "none").contains("<animate"));
+        job.animate = true;
+        let svg = render(&job, "#808080", "none");
+        let begins = svg
+            .split(r#"begin=""#)
+            .skip(1)
+            .map(|rest| rest.split('s').next().unwrap().parse::<f32>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(svg.matches("<animate ").count(), begins.len());
+        // At least the two boxes, the wire and the labels
+        assert!(begins.len() >= 4);
+        assert!(begins.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_width_and_height() {
+        let tb = TextBuffer::with_text("+--> ok");
+        let job = RenderJob::formal(tb);
+        let svg = render(&job, "#808080", "none");
+        assert!(svg.contains(r#" width="70px""#));
+        assert!(svg.contains(r#" height="15px""#));
+    }
 }
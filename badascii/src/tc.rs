@@ -1,4 +1,7 @@
+use crate::text_buffer::Size;
+
 #[derive(Copy, Clone, Debug, PartialEq, Default, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TextCoordinate {
     pub x: u32,
     pub y: u32,
@@ -30,6 +33,16 @@ impl TextCoordinate {
         }
     }
 
+    /// The nearest coordinate that lies inside a buffer of the
+    /// given `size`.  An empty buffer has no cells, so everything
+    /// clamps to the origin.
+    pub fn clamp_to(&self, size: &Size) -> TextCoordinate {
+        TextCoordinate {
+            x: self.x.min(size.num_cols.saturating_sub(1)),
+            y: self.y.min(size.num_rows.saturating_sub(1)),
+        }
+    }
+
     pub fn shifted(self, origin: TextCoordinate, move_pos: TextCoordinate) -> TextCoordinate {
         let delta_x = move_pos.x as i32 - origin.x as i32;
         let delta_y = move_pos.y as i32 - origin.y as i32;
@@ -39,3 +52,26 @@ impl TextCoordinate {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp_to() {
+        let size = Size {
+            num_cols: 10,
+            num_rows: 4,
+        };
+        let tc = |x, y| TextCoordinate { x, y };
+        assert_eq!(tc(3, 2).clamp_to(&size), tc(3, 2));
+        assert_eq!(tc(10, 2).clamp_to(&size), tc(9, 2));
+        assert_eq!(tc(3, 40).clamp_to(&size), tc(3, 3));
+        assert_eq!(tc(u32::MAX, u32::MAX).clamp_to(&size), tc(9, 3));
+        let empty = Size {
+            num_cols: 0,
+            num_rows: 0,
+        };
+        assert_eq!(tc(5, 5).clamp_to(&empty), tc(0, 0));
+    }
+}
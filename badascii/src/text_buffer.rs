@@ -1,17 +1,118 @@
+use std::cmp::Ordering;
+
 use crate::{rect::Rectangle, tc::TextCoordinate};
 
+/// The dimensions of a [`TextBuffer`], used both to report its
+/// size and to resize it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Size {
     pub num_rows: u32,
     pub num_cols: u32,
 }
 
-#[derive(Clone, Debug, Hash)]
+impl Size {
+    pub fn new(num_rows: u32, num_cols: u32) -> Self {
+        Self { num_rows, num_cols }
+    }
+    /// The number of cells in a buffer of this size.
+    pub fn area(&self) -> usize {
+        self.num_rows as usize * self.num_cols as usize
+    }
+    /// True if `coord` lies inside a buffer of this size.
+    pub fn fits(&self, coord: TextCoordinate) -> bool {
+        coord.x < self.num_cols && coord.y < self.num_rows
+    }
+}
+
+/// Converts a `(num_rows, num_cols)` pair, in the same order as
+/// [`TextBuffer::new`] takes them.
+impl From<(u32, u32)> for Size {
+    fn from((num_rows, num_cols): (u32, u32)) -> Self {
+        Self::new(num_rows, num_cols)
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "TextBufferRows", try_from = "TextBufferRows")
+)]
 pub struct TextBuffer {
     buffer: Box<[Option<char>]>,
     num_rows: u32,
     num_cols: u32,
 }
 
+/// The serialized form of a `TextBuffer`.  Each row is stored
+/// as a string (with empty cells as spaces), so that saved
+/// diagrams are human readable and diff nicely.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TextBufferRows {
+    num_rows: u32,
+    num_cols: u32,
+    rows: Vec<String>,
+}
+
+#[cfg(feature = "serde")]
+impl From<TextBuffer> for TextBufferRows {
+    fn from(tb: TextBuffer) -> Self {
+        // One string per row, even when the rows are empty, so a
+        // buffer with no columns keeps its height.
+        let num_cols = tb.num_cols as usize;
+        let rows = (0..tb.num_rows as usize)
+            .map(|y| {
+                tb.buffer[y * num_cols..(y + 1) * num_cols]
+                    .iter()
+                    .map(|c| c.unwrap_or(' '))
+                    .collect()
+            })
+            .collect();
+        Self {
+            num_rows: tb.num_rows,
+            num_cols: tb.num_cols,
+            rows,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<TextBufferRows> for TextBuffer {
+    type Error = String;
+
+    fn try_from(value: TextBufferRows) -> Result<Self, Self::Error> {
+        if value.rows.len() != value.num_rows as usize {
+            return Err(format!(
+                "Expected {} rows, found {}",
+                value.num_rows,
+                value.rows.len()
+            ));
+        }
+        let mut tb = TextBuffer::new(value.num_rows, value.num_cols);
+        for (y, row) in value.rows.iter().enumerate() {
+            if row.chars().count() != value.num_cols as usize {
+                return Err(format!(
+                    "Expected {} columns in row {y}, found {}",
+                    value.num_cols,
+                    row.chars().count()
+                ));
+            }
+            for (x, ch) in row.chars().enumerate() {
+                tb.set_text(
+                    &TextCoordinate {
+                        x: x as u32,
+                        y: y as u32,
+                    },
+                    Some(ch),
+                );
+            }
+        }
+        Ok(tb)
+    }
+}
+
 impl std::fmt::Display for TextBuffer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use std::fmt::Write;
@@ -28,7 +129,7 @@ impl std::fmt::Display for TextBuffer {
 impl TextBuffer {
     pub fn new(rows: u32, cols: u32) -> Self {
         Self {
-            buffer: vec![None; (cols * rows) as usize].into_boxed_slice(),
+            buffer: vec![None; Size::new(rows, cols).area()].into_boxed_slice(),
             num_rows: rows,
             num_cols: cols,
         }
@@ -40,6 +141,33 @@ impl TextBuffer {
         me.paste(text, TextCoordinate { x: 0, y: 0 });
         me
     }
+    /// Create a buffer just large enough to hold `lines`, one
+    /// line per row.
+    pub fn from_lines(lines: &[&str]) -> Self {
+        let num_cols = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0) as u32;
+        let mut me = Self::new(lines.len() as u32, num_cols);
+        for (row, line) in lines.iter().enumerate() {
+            me.paste(
+                line,
+                TextCoordinate {
+                    x: 0,
+                    y: row as u32,
+                },
+            );
+        }
+        me
+    }
+    /// Create a buffer of the given size, with `text` pasted
+    /// into it at `at`.  Any text that does not fit is dropped.
+    pub fn with_dimensions(text: &str, size: Size, at: TextCoordinate) -> Self {
+        let mut me = Self::new(size.num_rows, size.num_cols);
+        me.paste(text, at);
+        me
+    }
     pub fn size(&self) -> Size {
         Size {
             num_cols: self.num_cols,
@@ -48,7 +176,7 @@ impl TextBuffer {
     }
     pub fn set_text(&mut self, pos: &TextCoordinate, ch: Option<char>) {
         let ch = if ch == Some(' ') { None } else { ch };
-        if (0..self.num_cols).contains(&pos.x) && (0..self.num_rows).contains(&pos.y) {
+        if self.size().fits(*pos) {
             self.buffer[(pos.x + pos.y * self.num_cols) as usize] = ch;
         }
     }
@@ -153,6 +281,96 @@ impl TextBuffer {
             }
         })
     }
+    /// The words strictly inside `rect` (i.e., not on its border),
+    /// with words that cross the border clipped to the interior.
+    pub fn words_in(&self, rect: &Rectangle) -> Vec<(TextCoordinate, String)> {
+        if rect.width() <= 2 || rect.height() <= 2 {
+            return vec![];
+        }
+        let right_bottom = rect.right_bottom();
+        let interior = Rectangle::new(
+            TextCoordinate {
+                x: rect.left() + 1,
+                y: rect.top() + 1,
+            },
+            TextCoordinate {
+                x: right_bottom.x - 1,
+                y: right_bottom.y - 1,
+            },
+        );
+        let origin = interior.left_top();
+        self.window(&interior)
+            .words()
+            .map(|(pos, word)| {
+                (
+                    TextCoordinate {
+                        x: pos.x + origin.x,
+                        y: pos.y + origin.y,
+                    },
+                    word,
+                )
+            })
+            .collect()
+    }
+    /// The start of every (non-overlapping) occurrence of `needle`.
+    /// Empty cells match spaces, and matches do not wrap across rows.
+    pub fn find(&self, needle: &str) -> Vec<TextCoordinate> {
+        let needle = needle.chars().collect::<Vec<_>>();
+        if needle.is_empty() {
+            return vec![];
+        }
+        let mut matches = vec![];
+        for (y, row) in self
+            .buffer
+            .chunks(self.num_cols.max(1) as usize)
+            .enumerate()
+        {
+            let row = row.iter().map(|c| c.unwrap_or(' ')).collect::<Vec<_>>();
+            let mut x = 0;
+            while x + needle.len() <= row.len() {
+                if row[x..x + needle.len()] == needle[..] {
+                    matches.push(TextCoordinate {
+                        x: x as u32,
+                        y: y as u32,
+                    });
+                    x += needle.len();
+                } else {
+                    x += 1;
+                }
+            }
+        }
+        matches
+    }
+    /// Replace every `from` character with `to`, and return
+    /// the number of characters replaced.
+    pub fn replace(&mut self, from: char, to: char) -> usize {
+        let to = if to == ' ' { None } else { Some(to) };
+        let mut count = 0;
+        for cell in self.buffer.iter_mut().filter(|c| **c == Some(from)) {
+            *cell = to;
+            count += 1;
+        }
+        count
+    }
+    /// The smallest rectangle holding every character in the
+    /// buffer, or `None` if the buffer is empty.
+    pub fn bounding_box(&self) -> Option<Rectangle> {
+        self.iter()
+            .map(|(pos, _)| (pos, pos))
+            .reduce(|(min, max), (pos, _)| {
+                (
+                    TextCoordinate {
+                        x: min.x.min(pos.x),
+                        y: min.y.min(pos.y),
+                    },
+                    TextCoordinate {
+                        x: max.x.max(pos.x),
+                        y: max.y.max(pos.y),
+                    },
+                )
+            })
+            .map(|(min, max)| Rectangle::new(min, max))
+    }
     pub fn clear_rectangle(&mut self, selection: Rectangle) {
         for pos in selection.iter_interior() {
             self.set_text(&pos, None);
@@ -160,7 +378,7 @@ impl TextBuffer {
     }
 
     pub fn get(&self, pos: TextCoordinate) -> Option<char> {
-        if (0..self.num_cols).contains(&pos.x) && (0..self.num_rows).contains(&pos.y) {
+        if self.size().fits(pos) {
             self.buffer[(pos.x + pos.y * self.num_cols) as usize]
         } else {
             None
@@ -187,6 +405,55 @@ impl TextBuffer {
         }
         Rectangle { corner_1, corner_2 }
     }
+    /// Paste `text` at `pos`, first enlarging the buffer so that
+    /// all of it fits.  The buffer never grows past `max` in either
+    /// direction (and is never shrunk), so text beyond the cap is
+    /// clipped as in [`TextBuffer::paste`].  Returns the new size.
+    pub fn paste_growing(&mut self, text: &str, pos: TextCoordinate, max: Size) -> Size {
+        let rows = text.lines().count() as u32;
+        let cols = text
+            .lines()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0) as u32;
+        self.ensure_capacity(
+            (pos.y + rows).min(max.num_rows),
+            (pos.x + cols).min(max.num_cols),
+        );
+        self.paste(text, pos);
+        self.size()
+    }
+    /// Enlarge the buffer to at least `rows` by `cols`, keeping the
+    /// contents where they are.  The buffer is never shrunk.
+    pub fn ensure_capacity(&mut self, rows: u32, cols: u32) {
+        let needed = Size::new(rows.max(self.num_rows), cols.max(self.num_cols));
+        if needed != self.size() {
+            *self = self.resize(needed);
+        }
+    }
+    /// Stamp the contents of `other` onto this buffer, with the
+    /// top left corner of `other` placed at `at`.  Blank cells in
+    /// `other` leave the existing contents untouched.
+    pub fn overlay(&mut self, other: &TextBuffer, at: TextCoordinate) {
+        for (pos, ch) in other.iter() {
+            let pos = TextCoordinate {
+                x: pos.x + at.x,
+                y: pos.y + at.y,
+            };
+            self.merge_text(&pos, Some(ch));
+        }
+    }
+    /// Stack `other` under this buffer, `gap` blank rows below its
+    /// last row and lined up on the left edge.  The buffer grows to
+    /// fit both.
+    pub fn append_below(&mut self, other: &TextBuffer, gap: u32) {
+        let at = TextCoordinate {
+            x: 0,
+            y: self.num_rows + gap,
+        };
+        self.ensure_capacity(at.y + other.num_rows, other.num_cols);
+        self.overlay(other, at);
+    }
     pub fn window(&self, rect: &Rectangle) -> TextBuffer {
         let mut out_buffer = TextBuffer::new(rect.height(), rect.width());
         let min_x = rect.left();
@@ -215,6 +482,56 @@ impl TextBuffer {
         });
         t.collect()
     }
+    /// Every cell of the buffer, row by row, with the rows joined by
+    /// newlines.  Unlike [`TextBuffer::render`], the blank rows and
+    /// columns around the diagram are kept, so the result reads back
+    /// into the same buffer with [`TextBuffer::with_text`].
+    pub fn render_exact(&self) -> String {
+        let num_cols = self.num_cols as usize;
+        (0..self.num_rows as usize)
+            .map(|row| {
+                self.buffer[row * num_cols..(row + 1) * num_cols]
+                    .iter()
+                    .map(|c| c.unwrap_or(' '))
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+    /// Every row of the buffer, as in [`TextBuffer::render_exact`],
+    /// without its trailing spaces.  Each row is only put together
+    /// when the iterator reaches it, so a large buffer can be
+    /// written out a line at a time.
+    pub fn lines_iter(&self) -> impl Iterator<Item = String> + '_ {
+        let num_cols = self.num_cols as usize;
+        (0..self.num_rows as usize).map(move |row| {
+            let mut line = self.buffer[row * num_cols..(row + 1) * num_cols]
+                .iter()
+                .map(|c| c.unwrap_or(' '))
+                .collect::<String>();
+            line.truncate(line.trim_end_matches(' ').len());
+            line
+        })
+    }
+    /// The rows of the trimmed buffer, as in [`TextBuffer::render`],
+    /// without trailing spaces and not joined into one string.  An
+    /// empty buffer has no lines.
+    pub fn to_lines(&self) -> Vec<String> {
+        if self.iter().next().is_none() {
+            return vec![];
+        }
+        let me = self.shrink_to_fit();
+        me.buffer
+            .chunks(me.num_cols as usize)
+            .map(|row| {
+                row.iter()
+                    .map(|c| c.unwrap_or(' '))
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
     #[must_use]
     pub fn resize(&self, resize: Size) -> TextBuffer {
         let mut output = TextBuffer::new(resize.num_rows, resize.num_cols);
@@ -255,6 +572,214 @@ impl TextBuffer {
         );
         self.window(&cut_rectangle)
     }
+    fn transformed(
+        &self,
+        size: Size,
+        map_pos: impl Fn(TextCoordinate) -> TextCoordinate,
+        map_char: impl Fn(char) -> char,
+    ) -> TextBuffer {
+        let mut output = TextBuffer::new(size.num_rows, size.num_cols);
+        for (pos, ch) in self.iter() {
+            output.set_text(&map_pos(pos), Some(map_char(ch)));
+        }
+        output
+    }
+    /// Insert a blank row before row `at`, moving everything at or
+    /// below it down by one.  If `at` is past the last row, the new
+    /// row is added at the bottom.
+    pub fn insert_row(&mut self, at: u32) {
+        let at = at.min(self.num_rows);
+        *self = self.transformed(
+            Size {
+                num_rows: self.num_rows + 1,
+                num_cols: self.num_cols,
+            },
+            |pos| TextCoordinate {
+                x: pos.x,
+                y: if pos.y >= at { pos.y + 1 } else { pos.y },
+            },
+            |ch| ch,
+        );
+    }
+    /// Remove row `at`, moving everything below it up by one.
+    /// Does nothing if `at` is out of range.
+    pub fn delete_row(&mut self, at: u32) {
+        if at >= self.num_rows {
+            return;
+        }
+        *self = self.transformed(
+            Size {
+                num_rows: self.num_rows - 1,
+                num_cols: self.num_cols,
+            },
+            |pos| TextCoordinate {
+                x: pos.x,
+                // The deleted row maps off the end of the buffer
+                y: match pos.y.cmp(&at) {
+                    Ordering::Less => pos.y,
+                    Ordering::Equal => u32::MAX,
+                    Ordering::Greater => pos.y - 1,
+                },
+            },
+            |ch| ch,
+        );
+    }
+    /// Insert a blank column before column `at`, moving everything
+    /// at or to the right of it over by one.  If `at` is past the
+    /// last column, the new column is added at the right.
+    pub fn insert_col(&mut self, at: u32) {
+        let at = at.min(self.num_cols);
+        *self = self.transformed(
+            Size {
+                num_rows: self.num_rows,
+                num_cols: self.num_cols + 1,
+            },
+            |pos| TextCoordinate {
+                x: if pos.x >= at { pos.x + 1 } else { pos.x },
+                y: pos.y,
+            },
+            |ch| ch,
+        );
+    }
+    /// Remove column `at`, moving everything to the right of it
+    /// over by one.  Does nothing if `at` is out of range.
+    pub fn delete_col(&mut self, at: u32) {
+        if at >= self.num_cols {
+            return;
+        }
+        *self = self.transformed(
+            Size {
+                num_rows: self.num_rows,
+                num_cols: self.num_cols - 1,
+            },
+            |pos| TextCoordinate {
+                x: match pos.x.cmp(&at) {
+                    Ordering::Less => pos.x,
+                    Ordering::Equal => u32::MAX,
+                    Ordering::Greater => pos.x - 1,
+                },
+                y: pos.y,
+            },
+            |ch| ch,
+        );
+    }
+    /// Mirror the buffer left to right.  Direction sensitive glyphs
+    /// (`<` and `>`, `/` and `\`, parentheses and brackets) are swapped
+    /// so that arrows and diagonals still point the right way.  Note
+    /// that this applies to label text as well.
+    #[must_use]
+    pub fn flip_horizontal(&self) -> TextBuffer {
+        let last_col = self.num_cols.saturating_sub(1);
+        self.transformed(
+            self.size(),
+            |pos| TextCoordinate {
+                x: last_col - pos.x,
+                y: pos.y,
+            },
+            |ch| match ch {
+                '<' => '>',
+                '>' => '<',
+                '/' => '\\',
+                '\\' => '/',
+                '(' => ')',
+                ')' => '(',
+                '[' => ']',
+                ']' => '[',
+                _ => ch,
+            },
+        )
+    }
+    /// Mirror the buffer top to bottom, swapping `^` and `v`, and
+    /// `/` and `\`.
+    #[must_use]
+    pub fn flip_vertical(&self) -> TextBuffer {
+        let last_row = self.num_rows.saturating_sub(1);
+        self.transformed(
+            self.size(),
+            |pos| TextCoordinate {
+                x: pos.x,
+                y: last_row - pos.y,
+            },
+            |ch| match ch {
+                '^' => 'v',
+                'v' => '^',
+                '/' => '\\',
+                '\\' => '/',
+                _ => ch,
+            },
+        )
+    }
+    /// Rotate the buffer a quarter turn clockwise.  The number of
+    /// rows and columns are swapped, horizontal and vertical wire
+    /// glyphs are exchanged, and arrow heads are turned to match.
+    #[must_use]
+    pub fn rotate_cw(&self) -> TextBuffer {
+        let last_row = self.num_rows.saturating_sub(1);
+        self.transformed(
+            Size {
+                num_rows: self.num_cols,
+                num_cols: self.num_rows,
+            },
+            |pos| TextCoordinate {
+                x: last_row - pos.y,
+                y: pos.x,
+            },
+            |ch| match ch {
+                '>' => 'v',
+                'v' => '<',
+                '<' => '^',
+                '^' => '>',
+                ch => rotate_glyph(ch),
+            },
+        )
+    }
+    /// Rotate the buffer a quarter turn counter clockwise.  See
+    /// [`TextBuffer::rotate_cw`].
+    #[must_use]
+    pub fn rotate_ccw(&self) -> TextBuffer {
+        let last_col = self.num_cols.saturating_sub(1);
+        self.transformed(
+            Size {
+                num_rows: self.num_cols,
+                num_cols: self.num_rows,
+            },
+            |pos| TextCoordinate {
+                x: pos.y,
+                y: last_col - pos.x,
+            },
+            |ch| match ch {
+                '>' => '^',
+                '^' => '<',
+                '<' => 'v',
+                'v' => '>',
+                ch => rotate_glyph(ch),
+            },
+        )
+    }
+    /// A checksum of the trimmed contents of the buffer.  Unlike
+    /// the derived `Hash`, this uses a fixed FNV-1a hash, so the
+    /// value is stable across runs and can be used as a persistent
+    /// cache key.
+    pub fn checksum(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+        self.render().bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+        })
+    }
+}
+
+// The glyphs that change the same way under either quarter turn.
+fn rotate_glyph(ch: char) -> char {
+    match ch {
+        '-' => '|',
+        '|' => '-',
+        '=' => ':',
+        ':' => '=',
+        '/' => '\\',
+        '\\' => '/',
+        _ => ch,
+    }
 }
 
 #[cfg(test)]
@@ -263,6 +788,40 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_bounding_box() {
+        let mut buffer = TextBuffer::new(5, 10);
+        assert_eq!(buffer.bounding_box(), None);
+        buffer.paste("ab", TextCoordinate { x: 3, y: 1 });
+        buffer.paste("c", TextCoordinate { x: 1, y: 3 });
+        assert_eq!(
+            buffer.bounding_box(),
+            Some(Rectangle::new(
+                TextCoordinate { x: 1, y: 1 },
+                TextCoordinate { x: 4, y: 3 }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_size_helpers() {
+        let size = Size::new(3, 5);
+        assert_eq!(
+            size,
+            Size {
+                num_rows: 3,
+                num_cols: 5
+            }
+        );
+        assert_eq!(Size::from((3, 5)), size);
+        assert_eq!(size.area(), 15);
+        assert_eq!(TextBuffer::new(3, 5).size(), size);
+        assert!(size.fits(TextCoordinate { x: 4, y: 2 }));
+        assert!(!size.fits(TextCoordinate { x: 5, y: 2 }));
+        assert!(!size.fits(TextCoordinate { x: 4, y: 3 }));
+        assert!(!Size::new(0, 0).fits(TextCoordinate { x: 0, y: 0 }));
+    }
+
     #[test]
     fn test_trailing_whitespace_trimmed_on_render() {
         let test_text = "
@@ -384,4 +943,336 @@ a bad
         "#]];
         expect.assert_debug_eq(&words);
     }
+
+    #[test]
+    fn test_checksum_is_stable() {
+        let text = "+--+\n|  |\n+--+\n";
+        let first = TextBuffer::with_text(text);
+        let mut second = TextBuffer::new(10, 20);
+        second.paste(text, TextCoordinate { x: 3, y: 2 });
+        assert_eq!(first.checksum(), second.checksum());
+        assert_eq!(first.checksum(), TextBuffer::with_text(text).checksum());
+        assert_ne!(first.checksum(), TextBuffer::with_text("+--+").checksum());
+        let expect = expect!["5189747770060128637"];
+        expect.assert_eq(&first.checksum().to_string());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut tb = TextBuffer::new(4, 8);
+        tb.paste("+--+\n|  |--->\n+--+", TextCoordinate { x: 1, y: 1 });
+        let json = serde_json::to_string(&tb).unwrap();
+        let expect = expect![[
+            r#"{"num_rows":4,"num_cols":8,"rows":["        "," +--+   "," |  |---"," +--+   "]}"#
+        ]];
+        expect.assert_eq(&json);
+        let back: TextBuffer = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, tb);
+        // Empty buffers keep their shape
+        for tb in [TextBuffer::new(3, 0), TextBuffer::new(0, 3)] {
+            let json = serde_json::to_string(&tb).unwrap();
+            let back: TextBuffer = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, tb, "{json}");
+        }
+        assert!(
+            serde_json::from_str::<TextBuffer>(r#"{"num_rows":2,"num_cols":2,"rows":["ab"]}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_overlay() {
+        let small_box = TextBuffer::with_text("+-+\n| |\n+-+");
+        let mut tb = TextBuffer::new(4, 10);
+        tb.overlay(&small_box, TextCoordinate { x: 0, y: 0 });
+        tb.overlay(&small_box, TextCoordinate { x: 2, y: 1 });
+        let expect = expect![[r#"
+            +-+  
+            | +-+
+            +-| |
+              +-+
+        "#]];
+        expect.assert_eq(&tb.render());
+    }
+
+    #[test]
+    fn test_words_in() {
+        const FIFO: &str = "
+ +---------------------+
+ |                     |
+ | data           data |o--+
+ |                     |   |
+ | full           next |>  |
+ |                 overflow
+ +---------------------+
+";
+        let tb = TextBuffer::with_text(FIFO);
+        let rect = Rectangle::new(
+            TextCoordinate { x: 1, y: 1 },
+            TextCoordinate { x: 23, y: 7 },
+        );
+        let words = tb
+            .words_in(&rect)
+            .into_iter()
+            .map(|(pos, word)| format!("{},{} {word}", pos.x, pos.y))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            words,
+            vec![
+                "3,3 data",
+                "18,3 data",
+                "3,5 full",
+                "18,5 next",
+                "19,6 over"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_with_dimensions() {
+        let tb = TextBuffer::with_dimensions(
+            "+--+\n|  |\n+--+",
+            Size {
+                num_rows: 10,
+                num_cols: 20,
+            },
+            TextCoordinate { x: 17, y: 2 },
+        );
+        assert_eq!(tb.size().num_rows, 10);
+        assert_eq!(tb.size().num_cols, 20);
+        assert_eq!(tb.get(TextCoordinate { x: 17, y: 2 }), Some('+'));
+        assert_eq!(tb.get(TextCoordinate { x: 19, y: 3 }), None);
+        assert_eq!(tb.render(), "+--\n|  \n+--\n");
+    }
+
+    #[test]
+    fn test_find() {
+        let tb = TextBuffer::with_text("data  data\nnext da\nta data");
+        assert_eq!(
+            tb.find("data"),
+            vec![
+                TextCoordinate { x: 0, y: 0 },
+                TextCoordinate { x: 6, y: 0 },
+                TextCoordinate { x: 3, y: 2 },
+            ]
+        );
+        assert_eq!(tb.find("t d"), vec![TextCoordinate { x: 3, y: 1 }]);
+        assert!(tb.find("full").is_empty());
+        assert!(tb.find("").is_empty());
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut tb = TextBuffer::with_text("+--+\n|  |\n+--+");
+        assert_eq!(tb.replace('-', '='), 4);
+        assert_eq!(tb.replace('+', ' '), 4);
+        assert_eq!(tb.replace('#', '*'), 0);
+        assert_eq!(tb.render(), " == \n|  |\n == \n");
+    }
+
+    const ARROWS: &str = "+-->/\n|\nv";
+
+    #[test]
+    fn test_flip_horizontal() {
+        let tb = TextBuffer::with_text(ARROWS).flip_horizontal();
+        assert_eq!(tb.size().num_rows, 3);
+        assert_eq!(tb.size().num_cols, 5);
+        let expect = expect![[r#"
+            \<--+
+                |
+                v
+        "#]];
+        expect.assert_eq(&tb.render());
+        assert_eq!(tb.flip_horizontal(), TextBuffer::with_text(ARROWS));
+    }
+
+    #[test]
+    fn test_flip_vertical() {
+        let tb = TextBuffer::with_text(ARROWS).flip_vertical();
+        let expect = expect![[r#"
+            ^    
+            |    
+            +-->\
+        "#]];
+        expect.assert_eq(&tb.render());
+        assert_eq!(tb.flip_vertical(), TextBuffer::with_text(ARROWS));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let tb = TextBuffer::with_text(ARROWS);
+        let cw = tb.rotate_cw();
+        assert_eq!(cw.size().num_rows, 5);
+        assert_eq!(cw.size().num_cols, 3);
+        let expect = expect![[r#"
+            <-+
+              |
+              |
+              v
+              \
+        "#]];
+        expect.assert_eq(&cw.render());
+        let ccw = tb.rotate_ccw();
+        let expect = expect![[r#"
+            \  
+            ^  
+            |  
+            |  
+            +->
+        "#]];
+        expect.assert_eq(&ccw.render());
+        assert_eq!(cw.rotate_ccw(), tb);
+        assert_eq!(ccw.rotate_cw(), tb);
+        assert_eq!(cw.rotate_cw(), tb.flip_horizontal().flip_vertical());
+    }
+
+    #[test]
+    fn test_lines_round_trip() {
+        let lines = ["+--+", "|ok|   x", "+--+"];
+        let tb = TextBuffer::from_lines(&lines);
+        assert_eq!(
+            tb.size(),
+            Size {
+                num_cols: 8,
+                num_rows: 3
+            }
+        );
+        assert_eq!(tb.to_lines(), lines);
+        let lines = tb.to_lines();
+        let lines = lines.iter().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(TextBuffer::from_lines(&lines), tb);
+        assert!(TextBuffer::new(3, 3).to_lines().is_empty());
+    }
+
+    #[test]
+    fn test_ensure_capacity() {
+        let mut tb = TextBuffer::with_text("ab\ncd");
+        tb.ensure_capacity(4, 3);
+        assert_eq!(tb.size(), Size::new(4, 3));
+        assert_eq!(tb.render_exact(), "ab \ncd \n   \n   ");
+        tb.ensure_capacity(1, 5);
+        assert_eq!(tb.size(), Size::new(4, 5));
+        tb.paste("xyz", TextCoordinate { x: 2, y: 3 });
+        assert_eq!(tb.render_exact(), "ab   \ncd   \n     \n  xyz");
+    }
+
+    #[test]
+    fn test_lines_iter() {
+        let tb = TextBuffer::with_text("\n  +--+\n  |  |  x\n  +--+\n");
+        let lines = tb.lines_iter().collect::<Vec<_>>();
+        let exact = tb
+            .render_exact()
+            .lines()
+            .map(|line| line.trim_end().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, exact);
+        assert_eq!(lines[0], "");
+        assert_eq!(lines[2], "  |  |  x");
+        assert_eq!(tb.lines_iter().count(), tb.size().num_rows as usize);
+        assert_eq!(TextBuffer::new(0, 0).lines_iter().count(), 0);
+    }
+
+    #[test]
+    fn test_append_below() {
+        let mut tb = TextBuffer::with_text("ab\ncd");
+        tb.append_below(&TextBuffer::with_text("wxyz"), 1);
+        assert_eq!(tb.size(), Size::new(4, 4));
+        assert_eq!(tb.render_exact(), "ab  \ncd  \n    \nwxyz");
+        tb.append_below(&TextBuffer::with_text("q"), 0);
+        assert_eq!(tb.render_exact(), "ab  \ncd  \n    \nwxyz\nq   ");
+    }
+
+    #[test]
+    fn test_paste_growing() {
+        let max = Size {
+            num_cols: 100,
+            num_rows: 100,
+        };
+        let mut tb = TextBuffer::new(2, 3);
+        let size = tb.paste_growing("+--+\n|  |\n+--+", TextCoordinate { x: 1, y: 1 }, max);
+        assert_eq!(
+            size,
+            Size {
+                num_cols: 5,
+                num_rows: 4
+            }
+        );
+        assert_eq!(tb.size(), size);
+        assert_eq!(tb.render(), "+--+\n|  |\n+--+\n");
+        // Pasting something that already fits leaves the size alone.
+        assert_eq!(
+            tb.paste_growing("ok", TextCoordinate { x: 0, y: 0 }, max),
+            size
+        );
+    }
+
+    #[test]
+    fn test_paste_growing_is_capped() {
+        let max = Size {
+            num_cols: 4,
+            num_rows: 2,
+        };
+        let mut tb = TextBuffer::new(1, 1);
+        let size = tb.paste_growing("abcdef\nghijkl\nmnopqr", TextCoordinate { x: 1, y: 0 }, max);
+        assert_eq!(size, max);
+        assert_eq!(tb.render(), "abc\nghi\n");
+    }
+
+    #[test]
+    fn test_insert_row_and_col() {
+        let mut tb = TextBuffer::with_text("+--+\n|ab|\n+--+");
+        tb.insert_row(1);
+        tb.insert_col(2);
+        assert_eq!(tb.size().num_rows, 4);
+        assert_eq!(tb.size().num_cols, 5);
+        assert_eq!(tb.get(TextCoordinate { x: 0, y: 1 }), None);
+        assert_eq!(tb.get(TextCoordinate { x: 1, y: 2 }), Some('a'));
+        assert_eq!(tb.get(TextCoordinate { x: 2, y: 2 }), None);
+        assert_eq!(tb.get(TextCoordinate { x: 3, y: 2 }), Some('b'));
+        assert_eq!(tb.get(TextCoordinate { x: 4, y: 3 }), Some('+'));
+        assert_eq!(
+            tb.render(),
+            "+- -+
+     
+|a b|
++- -+
+"
+        );
+        tb.insert_row(10);
+        assert_eq!(tb.size().num_rows, 5);
+    }
+
+    #[test]
+    fn test_delete_row_and_col() {
+        let mut tb = TextBuffer::with_text("+--+\n|ab|\n|cd|\n+--+");
+        tb.delete_row(1);
+        tb.delete_col(2);
+        assert_eq!(tb.size().num_rows, 3);
+        assert_eq!(tb.size().num_cols, 3);
+        let expect = expect![[r#"
+            +-+
+            |c|
+            +-+
+        "#]];
+        expect.assert_eq(&tb.render());
+        tb.delete_row(3);
+        tb.delete_col(3);
+        assert_eq!(tb.size().num_rows, 3);
+        assert_eq!(tb.size().num_cols, 3);
+        tb.insert_row(1);
+        tb.delete_row(1);
+        assert_eq!(tb, TextBuffer::with_text("+-+\n|c|\n+-+"));
+    }
+
+    #[test]
+    fn test_render_exact() {
+        let tb = TextBuffer::with_text("\n+--+  \n|ok|\n+--+\n\n");
+        assert_eq!(
+            tb.render_exact(),
+            "      \n+--+  \n|ok|  \n+--+  \n      \n      "
+        );
+        assert_eq!(TextBuffer::with_text(&tb.render_exact()), tb);
+        assert_eq!(tb.render(), "+--+\n|ok|\n+--+\n");
+    }
 }